@@ -0,0 +1,134 @@
+//! Restrictions on which containers the crontab is allowed to start, so that
+//! a bad crontab edit can't launch arbitrary workloads on the host.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    job::Job,
+    policy::{RunPolicy, Verdict},
+};
+
+/// Shell-style pattern where `*` matches any run of characters and `?`
+/// matches any single character.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob(String);
+
+impl Glob {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Glob(pattern.into())
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let pattern: Vec<char> = self.0.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        let (mut p, mut n) = (0, 0);
+        // Where to resume if the most recent `*` has to swallow more input.
+        let mut backtrack = None;
+
+        while n < name.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p, n));
+                    p += 1;
+                }
+                Some('?') => {
+                    p += 1;
+                    n += 1;
+                }
+                Some(&c) if c == name[n] => {
+                    p += 1;
+                    n += 1;
+                }
+                _ => match backtrack {
+                    Some((star_p, star_n)) => {
+                        backtrack = Some((star_p, star_n + 1));
+                        p = star_p + 1;
+                        n = star_n + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        pattern[p..].iter().all(|&c| c == '*')
+    }
+}
+
+/// Container names that jobs may and may not start. A denied name is never
+/// started; if any names are allowed, only those are started.
+#[derive(Clone, Debug, Default)]
+pub struct ContainerFilter {
+    allow: Vec<Glob>,
+    deny: Vec<Glob>,
+}
+
+impl ContainerFilter {
+    pub fn new() -> Self {
+        ContainerFilter::default()
+    }
+
+    pub fn allow(&mut self, pattern: Glob) -> &mut Self {
+        self.allow.push(pattern);
+        self
+    }
+
+    pub fn deny(&mut self, pattern: Glob) -> &mut Self {
+        self.deny.push(pattern);
+        self
+    }
+
+    pub fn permits(&self, container: &str) -> bool {
+        if self.deny.iter().any(|glob| glob.matches(container)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|glob| glob.matches(container))
+    }
+}
+
+#[async_trait]
+impl RunPolicy for ContainerFilter {
+    async fn check(&self, job: &Job, _due: DateTime<Utc>) -> Verdict {
+        let container = job.action.container();
+
+        if self.permits(container) {
+            Verdict::allow()
+        } else {
+            Verdict::veto(format!("container {container:?} is not allowed"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(Glob::new("backup").matches("backup"));
+        assert!(!Glob::new("backup").matches("backups"));
+        assert!(Glob::new("backup-*").matches("backup-db"));
+        assert!(Glob::new("backup-*").matches("backup-"));
+        assert!(!Glob::new("backup-*").matches("restore-db"));
+        assert!(Glob::new("*-job-?").matches("nightly-job-1"));
+        assert!(!Glob::new("*-job-?").matches("nightly-job-10"));
+        assert!(Glob::new("a*b*c").matches("aXbYbZc"));
+        assert!(Glob::new("*").matches(""));
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = ContainerFilter::new();
+        assert!(filter.permits("anything"));
+
+        filter.deny(Glob::new("*-prod"));
+        assert!(filter.permits("backup"));
+        assert!(!filter.permits("backup-prod"));
+
+        filter.allow(Glob::new("backup*"));
+        assert!(filter.permits("backup"));
+        assert!(!filter.permits("backup-prod"));
+        assert!(!filter.permits("miner"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Maintenance windows during which no jobs run.
+//!
+//! A window is written either as a daily time range, such as `02:00-04:00`
+//! (which may wrap past midnight), or as a cron schedule for when the window
+//! opens and how long it stays open, such as `0 0 1 * * Sun for 3h`. Runs
+//! that fall due during a window are skipped, or deferred until it closes.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
+use thiserror::Error;
+
+use crate::{crontab::CronSchedule, timezone::Timezone};
+
+/// How many back-to-back windows to follow when working out when a blackout
+/// ends, in case windows are so long or frequent that it never does.
+const MAX_CHAINED_WINDOWS: usize = 1000;
+
+#[derive(Debug, Error)]
+#[error("Invalid blackout window {0:?}, expected e.g. 02:00-04:00 or \"0 0 1 * * Sun for 3h\"")]
+pub struct InvalidBlackoutError(String);
+
+/// A recurring window of time during which jobs don't run.
+#[derive(Clone, Debug)]
+pub struct Blackout {
+    /// When each window opens.
+    schedule: CronSchedule,
+    length: Duration,
+    spec: String,
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+impl FromStr for Blackout {
+    type Err = InvalidBlackoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidBlackoutError(s.to_string());
+        let spec = s.trim();
+
+        let (schedule, length) = if let Some((schedule, length)) = spec.rsplit_once(" for ") {
+            let schedule = CronSchedule::from_str(schedule.trim()).map_err(|_| invalid())?;
+            let length = humantime::parse_duration(length.trim()).map_err(|_| invalid())?;
+
+            (schedule, length)
+        } else {
+            let range = spec.strip_suffix("daily").unwrap_or(spec);
+            let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+            let start = parse_time(start).ok_or_else(invalid)?;
+            let end = parse_time(end).ok_or_else(invalid)?;
+            let schedule = format!("0 {} {} * * *", start.minute(), start.hour());
+            let length = (end - start).num_seconds().rem_euclid(86400) as u64;
+
+            (
+                CronSchedule::from_str(&schedule).map_err(|_| invalid())?,
+                Duration::from_secs(length),
+            )
+        };
+
+        if length.is_zero() {
+            return Err(invalid());
+        }
+
+        Ok(Blackout {
+            schedule,
+            length,
+            spec: spec.to_string(),
+        })
+    }
+}
+
+impl Display for Blackout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.spec)
+    }
+}
+
+impl Blackout {
+    /// If `at` is within one of the windows, when that window closes.
+    fn closes(&self, at: DateTime<Utc>, timezone: &Timezone) -> Option<DateTime<Utc>> {
+        let length = chrono::Duration::from_std(self.length).ok()?;
+        let opened = timezone.next_after(&self.schedule, at - length)?;
+
+        (opened <= at).then(|| opened + length)
+    }
+}
+
+/// What to do with a run that falls due during a blackout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlackoutAction {
+    #[default]
+    Skip,
+    /// Run once the blackout is over.
+    Defer,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown blackout action {0:?}, expected skip or defer")]
+pub struct UnknownBlackoutActionError(String);
+
+impl FromStr for BlackoutAction {
+    type Err = UnknownBlackoutActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(BlackoutAction::Skip),
+            "defer" => Ok(BlackoutAction::Defer),
+            _ => Err(UnknownBlackoutActionError(s.to_string())),
+        }
+    }
+}
+
+impl Display for BlackoutAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlackoutAction::Skip => write!(f, "skip"),
+            BlackoutAction::Defer => write!(f, "defer"),
+        }
+    }
+}
+
+/// Every blackout window that applies to a scheduler, with their times of
+/// day interpreted in one time zone.
+#[derive(Clone, Debug)]
+pub struct Blackouts {
+    pub windows: Vec<Blackout>,
+    pub timezone: Timezone,
+    pub action: BlackoutAction,
+}
+
+impl Blackouts {
+    pub fn new(windows: Vec<Blackout>, timezone: Timezone, action: BlackoutAction) -> Self {
+        Blackouts {
+            windows,
+            timezone,
+            action,
+        }
+    }
+
+    /// If `at` is blacked out, when the blackout ends. Windows that overlap
+    /// or follow on from each other count as one blackout.
+    pub fn until(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut end = None;
+        let mut t = at;
+
+        for _ in 0..MAX_CHAINED_WINDOWS {
+            let closes = self
+                .windows
+                .iter()
+                .filter_map(|window| window.closes(t, &self.timezone))
+                .max();
+
+            match closes {
+                Some(closes) if closes > t => {
+                    end = Some(closes);
+                    t = closes;
+                }
+                _ => break,
+            }
+        }
+
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), anyhow::Error> {
+        let window = Blackout::from_str("23:30-01:00")?;
+        assert_eq!(window.schedule.to_string(), "0 30 23 * * *");
+        assert_eq!(window.length, Duration::from_secs(90 * 60));
+
+        let window = Blackout::from_str("02:00-04:00 daily")?;
+        assert_eq!(window.length, Duration::from_secs(7200));
+
+        let window = Blackout::from_str("0 0 1 * * Sun for 3h")?;
+        assert_eq!(window.length, Duration::from_secs(3 * 3600));
+        assert_eq!(window.to_string(), "0 0 1 * * Sun for 3h");
+
+        assert!(Blackout::from_str("02:00").is_err());
+        assert!(Blackout::from_str("02:00-02:00").is_err());
+        assert!(Blackout::from_str("25:00-02:00").is_err());
+        assert!(Blackout::from_str("@daily for forever").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_until() -> Result<(), anyhow::Error> {
+        let blackouts = Blackouts::new(
+            vec![
+                Blackout::from_str("23:30-01:00")?,
+                Blackout::from_str("01:00-02:00")?,
+            ],
+            Timezone::from_str("+02:00")?,
+            BlackoutAction::Skip,
+        );
+
+        // 00:30 local time, so the blackout runs on until 02:00 local.
+        assert_eq!(
+            blackouts.until(at("2024-03-01T22:30:00Z")),
+            Some(at("2024-03-02T00:00:00Z"))
+        );
+        assert_eq!(blackouts.until(at("2024-03-01T21:29:59Z")), None);
+        assert_eq!(blackouts.until(at("2024-03-02T00:00:00Z")), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+//! Deliberate failures, for checking that retries, notifications and alerts
+//! work before a real failure happens.
+//!
+//! A [`ChaosExecutor`] wraps another executor and, for containers matching
+//! its rules, pretends that runs failed instead of running them. Rules are
+//! written as `<pattern>=<faults>`, where the pattern is a container name
+//! glob and the faults are either:
+//!
+//! - a single fault, optionally with a chance of happening on each run, such
+//!   as `backup-*=exit:3@25%`; or
+//! - a script of outcomes for successive runs, after which runs are left
+//!   alone, such as `report=ok,start,hang`.
+//!
+//! The faults are `start` (the container fails to start), `exit:<status>`
+//! (it exits with that status), `hang` (it never exits, so the job's timeout
+//! fires) and `ok` (the run goes ahead for real).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::{
+    allowlist::Glob,
+    executor::{Executor, OutputStream, ResourceUsage},
+    job::{ComposeService, ControlOp, Prune, PullPolicy, RunImage},
+    random::Rng,
+    scheduler::RunError,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Let the run go ahead.
+    None,
+    StartFailure,
+    Exit(i64),
+    /// Never exit.
+    Hang,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Faults {
+    /// A fault that happens on a percentage of runs.
+    Random { fault: Fault, percent: u64 },
+    /// Faults for each of the first few runs.
+    Script(Vec<Fault>),
+}
+
+/// Which containers to inject faults into, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaultRule {
+    pattern: Glob,
+    faults: Faults,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid fault rule {0:?}, expected e.g. backup-*=exit:1@50% or report=ok,start,hang")]
+pub struct InvalidFaultRuleError(String);
+
+fn parse_fault(s: &str) -> Option<Fault> {
+    match s {
+        "ok" => Some(Fault::None),
+        "start" => Some(Fault::StartFailure),
+        "hang" => Some(Fault::Hang),
+        _ => s.strip_prefix("exit:")?.parse().ok().map(Fault::Exit),
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::None => write!(f, "ok"),
+            Fault::StartFailure => write!(f, "start"),
+            Fault::Exit(status) => write!(f, "exit:{status}"),
+            Fault::Hang => write!(f, "hang"),
+        }
+    }
+}
+
+impl FromStr for FaultRule {
+    type Err = InvalidFaultRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidFaultRuleError(s.to_string());
+        let (pattern, faults) = s.split_once('=').ok_or_else(invalid)?;
+
+        let faults = if faults.contains(',') {
+            let script = faults
+                .split(',')
+                .map(|fault| parse_fault(fault.trim()))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?;
+
+            Faults::Script(script)
+        } else {
+            let (fault, percent) = match faults.split_once('@') {
+                Some((fault, percent)) => {
+                    let percent = percent
+                        .strip_suffix('%')
+                        .and_then(|percent| percent.parse().ok())
+                        .filter(|percent| *percent <= 100)
+                        .ok_or_else(invalid)?;
+
+                    (fault, percent)
+                }
+                None => (faults, 100),
+            };
+
+            Faults::Random {
+                fault: parse_fault(fault.trim()).ok_or_else(invalid)?,
+                percent,
+            }
+        };
+
+        Ok(FaultRule {
+            pattern: Glob::new(pattern.trim()),
+            faults,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ContainerChaos {
+    rng: Rng,
+    runs: usize,
+    /// The fault chosen for the run in progress.
+    current: Fault,
+    /// Wakes a hanging run when the container is stopped.
+    stopped: Arc<Notify>,
+}
+
+/// An [`Executor`] that injects faults into runs of some containers and
+/// passes everything else through to another executor.
+pub struct ChaosExecutor<E> {
+    inner: E,
+    rules: Vec<FaultRule>,
+    seed: u64,
+    containers: Mutex<HashMap<String, ContainerChaos>>,
+}
+
+impl<E: Executor> ChaosExecutor<E> {
+    /// Wrap `inner`, drawing random faults from generators derived from
+    /// `seed`, so that the same seed injects the same faults.
+    pub fn new(inner: E, rules: Vec<FaultRule>, seed: u64) -> Self {
+        ChaosExecutor {
+            inner,
+            rules,
+            seed,
+            containers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Decide what happens to a new run of `container`.
+    fn choose(&self, container: &str) -> Fault {
+        let Some(rule) = self
+            .rules
+            .iter()
+            .find(|rule| rule.pattern.matches(container))
+        else {
+            return Fault::None;
+        };
+
+        let mut containers = self.containers.lock().unwrap();
+        let chaos = containers
+            .entry(container.to_string())
+            .or_insert_with(|| ContainerChaos {
+                rng: Rng::for_job(self.seed, container),
+                runs: 0,
+                current: Fault::None,
+                stopped: Arc::new(Notify::new()),
+            });
+
+        let fault = match &rule.faults {
+            Faults::Random { fault, percent } if chaos.rng.below(100) < *percent => *fault,
+            Faults::Random { .. } => Fault::None,
+            Faults::Script(script) => script.get(chaos.runs).copied().unwrap_or(Fault::None),
+        };
+
+        chaos.runs += 1;
+        chaos.current = fault;
+
+        fault
+    }
+
+    fn current(&self, container: &str) -> Fault {
+        self.containers
+            .lock()
+            .unwrap()
+            .get(container)
+            .map_or(Fault::None, |chaos| chaos.current)
+    }
+
+    fn stopped(&self, container: &str) -> Option<Arc<Notify>> {
+        self.containers
+            .lock()
+            .unwrap()
+            .get(container)
+            .map(|chaos| chaos.stopped.clone())
+    }
+}
+
+#[async_trait]
+impl<E: Executor> Executor for ChaosExecutor<E> {
+    async fn start(&self, container: &str) -> Result<(), RunError> {
+        let fault = self.choose(container);
+
+        if fault != Fault::None {
+            warn!(container, %fault, "Injecting fault");
+        }
+
+        match fault {
+            Fault::None => self.inner.start(container).await,
+            Fault::StartFailure => Err(RunError::Start(
+                bollard::errors::Error::DockerResponseServerError {
+                    status_code: 500,
+                    message: String::from("Injected start failure"),
+                },
+            )),
+            Fault::Exit(_) | Fault::Hang => Ok(()),
+        }
+    }
+
+    async fn wait(&self, container: &str) -> Result<(), RunError> {
+        match self.current(container) {
+            Fault::None | Fault::StartFailure => self.inner.wait(container).await,
+            Fault::Exit(status_code) => Err(RunError::Exit { status_code }),
+            Fault::Hang => match self.stopped(container) {
+                Some(stopped) => {
+                    stopped.notified().await;
+                    Err(RunError::Exit { status_code: 137 })
+                }
+                None => std::future::pending().await,
+            },
+        }
+    }
+
+    async fn wait_healthy(&self, container: &str) -> Result<(), RunError> {
+        match self.current(container) {
+            Fault::None | Fault::StartFailure => self.inner.wait_healthy(container).await,
+            Fault::Exit(status_code) => Err(RunError::Unhealthy {
+                reason: format!("the container exited with status {status_code}"),
+            }),
+            Fault::Hang => std::future::pending().await,
+        }
+    }
+
+    async fn control(&self, container: &str, op: ControlOp) -> Result<(), RunError> {
+        self.inner.control(container, op).await
+    }
+
+    async fn prune(&self, prune: &Prune) -> Result<(), RunError> {
+        self.inner.prune(prune).await
+    }
+
+    async fn stop(&self, container: &str, grace: Duration) -> Result<(), RunError> {
+        match self.current(container) {
+            Fault::None | Fault::StartFailure => self.inner.stop(container, grace).await,
+            Fault::Exit(_) => Ok(()),
+            Fault::Hang => {
+                if let Some(stopped) = self.stopped(container) {
+                    stopped.notify_waiters();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn copy(
+        &self,
+        container: &str,
+        copy: &str,
+        env: &BTreeMap<String, String>,
+        command: &[String],
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        self.inner.copy(container, copy, env, command, labels).await
+    }
+
+    async fn find_service(&self, service: &ComposeService) -> Result<String, RunError> {
+        self.inner.find_service(service).await
+    }
+
+    async fn pull(&self, image: &str, policy: PullPolicy) -> Result<(), RunError> {
+        self.inner.pull(image, policy).await
+    }
+
+    async fn create(
+        &self,
+        container: &str,
+        run: &RunImage,
+        env: &BTreeMap<String, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        self.inner.create(container, run, env, labels).await
+    }
+
+    async fn remove(&self, container: &str) -> Result<(), RunError> {
+        self.inner.remove(container).await
+    }
+
+    async fn exec(
+        &self,
+        container: &str,
+        command: &[String],
+        env: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        self.inner.exec(container, command, env).await
+    }
+
+    async fn sample_usage(&self, container: &str, usage: &Mutex<ResourceUsage>) {
+        if self.current(container) == Fault::None {
+            self.inner.sample_usage(container, usage).await;
+        }
+    }
+
+    async fn follow_logs(
+        &self,
+        container: &str,
+        since: DateTime<Utc>,
+        line: &(dyn for<'a> Fn(OutputStream, &'a str) + Sync),
+    ) {
+        if self.current(container) == Fault::None {
+            self.inner.follow_logs(container, since, line).await;
+        }
+    }
+
+    async fn log_tail(&self, container: &str, lines: usize) -> Vec<String> {
+        self.inner.log_tail(container, lines).await
+    }
+
+    async fn is_ready(&self) -> bool {
+        self.inner.is_ready().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An executor whose every run succeeds.
+    struct Succeed;
+
+    #[async_trait]
+    impl Executor for Succeed {
+        async fn start(&self, _container: &str) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn wait(&self, _container: &str) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn wait_healthy(&self, _container: &str) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn control(&self, _container: &str, _op: ControlOp) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn prune(&self, _prune: &Prune) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn stop(&self, _container: &str, _grace: Duration) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn copy(
+            &self,
+            _container: &str,
+            _copy: &str,
+            _env: &BTreeMap<String, String>,
+            _command: &[String],
+            _labels: &BTreeMap<String, String>,
+        ) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn find_service(&self, service: &ComposeService) -> Result<String, RunError> {
+            Ok(service.service.clone())
+        }
+
+        async fn pull(&self, _image: &str, _policy: PullPolicy) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn create(
+            &self,
+            _container: &str,
+            _run: &RunImage,
+            _env: &BTreeMap<String, String>,
+            _labels: &BTreeMap<String, String>,
+        ) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn remove(&self, _container: &str) -> Result<(), RunError> {
+            Ok(())
+        }
+
+        async fn exec(
+            &self,
+            _container: &str,
+            _command: &[String],
+            _env: &BTreeMap<String, String>,
+        ) -> Result<(), RunError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), anyhow::Error> {
+        assert_eq!(
+            FaultRule::from_str("backup-*=exit:3@25%")?,
+            FaultRule {
+                pattern: Glob::new("backup-*"),
+                faults: Faults::Random {
+                    fault: Fault::Exit(3),
+                    percent: 25
+                }
+            }
+        );
+        assert_eq!(
+            FaultRule::from_str("report=ok,start,hang")?.faults,
+            Faults::Script(vec![Fault::None, Fault::StartFailure, Fault::Hang])
+        );
+
+        assert!(FaultRule::from_str("report").is_err());
+        assert!(FaultRule::from_str("report=exit").is_err());
+        assert!(FaultRule::from_str("report=hang@150%").is_err());
+        assert!(FaultRule::from_str("report=ok,start@50%").is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_script() -> Result<(), anyhow::Error> {
+        let rules = vec![FaultRule::from_str("report=ok,start,exit:2")?];
+        let chaos = ChaosExecutor::new(Succeed, rules, 0);
+
+        let mut outcomes = Vec::new();
+
+        for _ in 0..4 {
+            let outcome = match chaos.start("report").await {
+                Ok(()) => chaos.wait("report").await,
+                Err(error) => Err(error),
+            };
+
+            outcomes.push(match outcome {
+                Ok(()) => String::from("ok"),
+                Err(RunError::Start(_)) => String::from("start"),
+                Err(RunError::Exit { status_code }) => format!("exit:{status_code}"),
+                Err(error) => error.to_string(),
+            });
+        }
+
+        assert_eq!(outcomes, ["ok", "start", "exit:2", "ok"]);
+
+        // Other containers are left alone.
+        chaos.start("backup").await?;
+        chaos.wait("backup").await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random() {
+        let faults = |seed| {
+            let rules = vec![FaultRule::from_str("*=start@50%").unwrap()];
+            let chaos = ChaosExecutor::new(Succeed, rules, seed);
+
+            (0..100)
+                .map(|_| chaos.choose("backup"))
+                .filter(|fault| *fault == Fault::StartFailure)
+                .count()
+        };
+
+        let count = faults(7);
+        assert!((25..75).contains(&count));
+        assert_eq!(faults(7), count);
+    }
+}
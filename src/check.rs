@@ -0,0 +1,245 @@
+//! Validation of a set of jobs beyond what parsing catches, for checking
+//! crontabs before deploying them.
+
+use std::collections::{BTreeSet, HashSet};
+
+use bollard::{query_parameters::ListContainersOptionsBuilder, Docker};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{job::Job, trigger::TriggerSpec};
+
+/// Something wrong with a job that stops it from running as intended.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum Problem {
+    #[error("Job {job} runs after {dependency}, which doesn't exist, so it will never run")]
+    UnknownDependency { job: String, dependency: String },
+    #[error("Job {job} will never fall due again")]
+    NeverDue { job: String },
+    #[error("Job {job} uses container {container}, which can't be inspected: {reason}")]
+    MissingContainer {
+        job: String,
+        container: String,
+        reason: String,
+    },
+    #[error(
+        "Job {job} uses container {container}, which doesn't exist{}",
+        did_you_mean(.suggestion)
+    )]
+    UnknownContainer {
+        job: String,
+        container: String,
+        /// The existing container with the most similar name, if any is
+        /// close enough to be a likely typo.
+        suggestion: Option<String>,
+    },
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(name) => format!(" (did you mean {name}?)"),
+        None => String::new(),
+    }
+}
+
+/// Find problems with `jobs` that can be seen without contacting Docker, as
+/// of `now`.
+pub fn check(jobs: &[Job], now: DateTime<Utc>) -> Vec<Problem> {
+    let names: HashSet<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+    let mut problems = Vec::new();
+
+    for job in jobs {
+        match &job.schedule {
+            TriggerSpec::After(dependencies) => {
+                for dependency in dependencies {
+                    if !names.contains(dependency.as_str()) {
+                        problems.push(Problem::UnknownDependency {
+                            job: job.name.clone(),
+                            dependency: dependency.clone(),
+                        });
+                    }
+                }
+            }
+            TriggerSpec::Cron(_) if !job.disabled && job.next_cron_fire(now).is_none() => {
+                problems.push(Problem::NeverDue {
+                    job: job.name.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    problems
+}
+
+/// Every existing container that a job uses, whether to run or as a hook.
+pub fn containers(job: &Job) -> BTreeSet<&str> {
+    job.before
+        .iter()
+        .chain(&job.after)
+        .chain(&job.on_failure)
+        .map(String::as_str)
+        .chain(job.action.existing_container())
+        .collect()
+}
+
+/// The names of all of Docker's containers, running or not.
+pub async fn existing_containers(docker: &Docker) -> Result<Vec<String>, bollard::errors::Error> {
+    let options = ListContainersOptionsBuilder::new().all(true).build();
+    let containers = docker.list_containers(Some(options)).await?;
+
+    Ok(containers
+        .into_iter()
+        .flat_map(|container| container.names.unwrap_or_default())
+        .map(|name| name.trim_start_matches('/').to_string())
+        .collect())
+}
+
+/// Find the containers used by `jobs` that aren't among `existing`, with the
+/// closest existing name to each in case it's a typo.
+pub fn unknown_containers<'a>(
+    jobs: impl IntoIterator<Item = &'a Job>,
+    existing: &[String],
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for job in jobs {
+        for container in containers(job) {
+            if existing.iter().any(|name| name == container) {
+                continue;
+            }
+
+            // Allow about one typo for every three characters.
+            let suggestion = existing
+                .iter()
+                .map(|name| (edit_distance(container, name), name))
+                .filter(|&(distance, _)| distance <= container.chars().count().div_ceil(3))
+                .min()
+                .map(|(_, name)| name.clone());
+
+            problems.push(Problem::UnknownContainer {
+                job: job.name.clone(),
+                container: container.to_string(),
+                suggestion,
+            });
+        }
+    }
+
+    problems
+}
+
+/// How many characters must be inserted, deleted or replaced to turn `a`
+/// into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &b) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a != b);
+
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use crate::job::Action;
+
+    use super::*;
+
+    #[test]
+    fn test_check() -> Result<(), anyhow::Error> {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let job = |name: &str, schedule: &str| {
+            Job::new(name)
+                .schedule(schedule)
+                .action(Action::Start(String::from(name)))
+        };
+        let jobs = vec![
+            job("backup", "0 0 3 * * *").before("stop-app").build()?,
+            job("upload", "@after backup").build()?,
+            job("notify", "@after upload,report").build()?,
+            job("leap", "0 0 0 30 2 *").build()?,
+            job("finished", "@daily")
+                .not_after(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+                .build()?,
+        ];
+
+        assert_eq!(
+            check(&jobs, now),
+            [
+                Problem::UnknownDependency {
+                    job: String::from("notify"),
+                    dependency: String::from("report"),
+                },
+                Problem::NeverDue {
+                    job: String::from("leap"),
+                },
+                Problem::NeverDue {
+                    job: String::from("finished"),
+                },
+            ]
+        );
+        assert_eq!(
+            containers(&jobs[0]).into_iter().collect::<Vec<_>>(),
+            ["backup", "stop-app"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_containers() -> Result<(), anyhow::Error> {
+        let jobs = vec![
+            Job::new("backup")
+                .schedule("@daily")
+                .action(Action::Start(String::from("db-bakcup")))
+                .before("quiesce")
+                .build()?,
+            Job::new("report")
+                .schedule("@daily")
+                .action(Action::Start(String::from("report")))
+                .build()?,
+        ];
+        let existing = [String::from("db-backup"), String::from("web")];
+        let problems = unknown_containers(&jobs, &existing);
+
+        assert_eq!(
+            problems,
+            [
+                Problem::UnknownContainer {
+                    job: String::from("backup"),
+                    container: String::from("db-bakcup"),
+                    suggestion: Some(String::from("db-backup")),
+                },
+                Problem::UnknownContainer {
+                    job: String::from("backup"),
+                    container: String::from("quiesce"),
+                    suggestion: None,
+                },
+                Problem::UnknownContainer {
+                    job: String::from("report"),
+                    container: String::from("report"),
+                    suggestion: None,
+                },
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "Job backup uses container db-bakcup, which doesn't exist (did you mean db-backup?)"
+        );
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Waiting for wall-clock times while the clock itself may change.
+//!
+//! Timers count monotonic time, which doesn't follow NTP steps or manual
+//! changes to the system clock, and which may stop while the host is
+//! suspended or the container is paused. Long waits are therefore made in
+//! steps of at most [`MAX_SLEEP`], checking the wall clock after each, so
+//! that a run is never more than a step late however the clock moves.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::time::Instant;
+
+/// The longest that a scheduler sleeps before checking the wall clock again.
+pub const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// How far the wall clock may drift from monotonic time between checks
+/// before it counts as having jumped.
+const JUMP_THRESHOLD: chrono::Duration = chrono::Duration::seconds(5);
+
+/// How long to sleep, at most, before checking whether `at` has arrived.
+pub fn sleep_step(at: DateTime<Utc>) -> Duration {
+    (at - Utc::now())
+        .to_std()
+        .unwrap_or_default()
+        .min(MAX_SLEEP)
+}
+
+/// Notices the wall clock moving other than in step with monotonic time.
+#[derive(Clone, Copy, Debug)]
+pub struct ClockWatch {
+    wall: DateTime<Utc>,
+    mono: Instant,
+}
+
+impl Default for ClockWatch {
+    fn default() -> Self {
+        ClockWatch {
+            wall: Utc::now(),
+            mono: Instant::now(),
+        }
+    }
+}
+
+impl ClockWatch {
+    /// How far the wall clock has jumped since the last check, if by more
+    /// than a few seconds. Forward jumps are positive.
+    pub fn jumped(&mut self) -> Option<chrono::Duration> {
+        let (wall, mono) = (Utc::now(), Instant::now());
+        let elapsed = chrono::Duration::from_std(mono - self.mono).unwrap_or_default();
+        let jump = wall - (self.wall + elapsed);
+
+        self.wall = wall;
+        self.mono = mono;
+
+        (jump.abs() > JUMP_THRESHOLD).then_some(jump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clock_watch() {
+        let mut watch = ClockWatch::default();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(watch.jumped(), None);
+
+        // A check made long ago by the wall clock, but just now by the timer.
+        watch.wall -= chrono::Duration::hours(1);
+        assert_eq!(watch.jumped().map(|jump| jump.num_hours()), Some(1));
+
+        assert_eq!(
+            sleep_step(Utc::now() - chrono::Duration::hours(1)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            sleep_step(Utc::now() + chrono::Duration::hours(1)),
+            MAX_SLEEP
+        );
+    }
+}
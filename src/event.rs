@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Lifecycle events emitted by the scheduler for every run of every job.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    /// The job's container was started.
+    Started {
+        job: String,
+        container: String,
+        time: DateTime<Utc>,
+    },
+    /// The run completed successfully.
+    Succeeded {
+        job: String,
+        container: String,
+        time: DateTime<Utc>,
+    },
+    /// The run failed, either at startup or on exit.
+    Failed {
+        job: String,
+        container: String,
+        time: DateTime<Utc>,
+        error: String,
+        /// The last lines of the container's output, if it exited with a
+        /// failure status.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        output: Vec<String>,
+    },
+}
+
+impl JobEvent {
+    /// Name of the job that this event relates to.
+    pub fn job(&self) -> &str {
+        match self {
+            JobEvent::Started { job, .. } => job,
+            JobEvent::Succeeded { job, .. } => job,
+            JobEvent::Failed { job, .. } => job,
+        }
+    }
+
+    /// Name of the container that this event relates to.
+    pub fn container(&self) -> &str {
+        match self {
+            JobEvent::Started { container, .. } => container,
+            JobEvent::Succeeded { container, .. } => container,
+            JobEvent::Failed { container, .. } => container,
+        }
+    }
+
+    /// Short machine-readable name for the kind of event, matching the
+    /// `event` tag in its serialized form.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobEvent::Started { .. } => "started",
+            JobEvent::Succeeded { .. } => "succeeded",
+            JobEvent::Failed { .. } => "failed",
+        }
+    }
+}
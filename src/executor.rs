@@ -0,0 +1,765 @@
+//! The backend that actually starts job containers and waits for them.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bollard::{
+    container::LogOutput,
+    errors::Error::DockerContainerWaitError,
+    exec::StartExecResults,
+    models::{ContainerCreateBody, ExecConfig, HealthStatusEnum, HostConfig},
+    query_parameters::{
+        CreateContainerOptionsBuilder, CreateImageOptionsBuilder, InspectContainerOptions,
+        ListContainersOptionsBuilder, LogsOptionsBuilder, PruneContainersOptionsBuilder,
+        PruneImagesOptionsBuilder, PruneVolumesOptionsBuilder, RemoveContainerOptions,
+        RemoveContainerOptionsBuilder, RestartContainerOptions, StartContainerOptions,
+        StatsOptions, StopContainerOptions, WaitContainerOptions,
+    },
+    Docker,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
+use tracing::{debug, info};
+
+use crate::{
+    job::{ComposeService, ControlOp, Mount, Prune, PruneTarget, PullPolicy, RunImage},
+    scheduler::RunError,
+};
+
+/// How often to check on a container that is waiting to become healthy.
+const HEALTH_POLL: Duration = Duration::from_secs(1);
+
+/// Resources consumed by one run of a container.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// The highest memory usage seen, in bytes.
+    pub peak_memory_bytes: u64,
+    /// Total CPU time used across all cores.
+    #[serde(with = "humantime_serde")]
+    pub cpu_time: Duration,
+}
+
+/// Which of a container's output streams a line was written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+#[async_trait]
+pub trait Executor: Send + Sync {
+    /// Start an existing container.
+    async fn start(&self, container: &str) -> Result<(), RunError>;
+
+    /// Wait for a started container to exit, failing unless it exits with
+    /// status zero. Timeouts are applied by the caller.
+    async fn wait(&self, container: &str) -> Result<(), RunError>;
+
+    /// Wait for a started container's healthcheck to report it healthy,
+    /// failing if it reports it unhealthy, the container exits or it has no
+    /// healthcheck. Deadlines are applied by the caller.
+    async fn wait_healthy(&self, container: &str) -> Result<(), RunError>;
+
+    /// Ask a running container to stop, killing it if it's still running
+    /// after `grace`. A pending [`Executor::wait`] then fails.
+    async fn stop(&self, container: &str, grace: Duration) -> Result<(), RunError>;
+
+    /// Stop, restart, pause or unpause a container, using Docker's own stop
+    /// timeout when stopping or restarting it.
+    async fn control(&self, container: &str, op: ControlOp) -> Result<(), RunError>;
+
+    /// Remove unused images, containers or volumes, logging how much space
+    /// that freed.
+    async fn prune(&self, prune: &Prune) -> Result<(), RunError>;
+
+    /// Create a stopped copy of a container named `copy`, with `env` set on
+    /// top of the original's environment, `command` replacing its command
+    /// unless empty and `labels` added to its labels, replacing any existing
+    /// container of that name.
+    async fn copy(
+        &self,
+        container: &str,
+        copy: &str,
+        env: &BTreeMap<String, String>,
+        command: &[String],
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError>;
+
+    /// Find the container of a Compose service, by the labels that Compose
+    /// gives it.
+    async fn find_service(&self, service: &ComposeService) -> Result<String, RunError>;
+
+    /// Pull `image` from its registry if `policy` calls for it, logging the
+    /// progress.
+    async fn pull(&self, image: &str, policy: PullPolicy) -> Result<(), RunError>;
+
+    /// Create a stopped container named `container` to run `run`, with `env`
+    /// set on top of the image's environment and `labels` on top of its
+    /// labels, replacing any existing container of that name.
+    async fn create(
+        &self,
+        container: &str,
+        run: &RunImage,
+        env: &BTreeMap<String, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError>;
+
+    /// Remove a stopped container.
+    async fn remove(&self, container: &str) -> Result<(), RunError>;
+
+    /// Run `command` in a running container with `env` set, and wait for it
+    /// to exit, failing unless it exits with status zero. Its output is
+    /// logged.
+    async fn exec(
+        &self,
+        container: &str,
+        command: &[String],
+        env: &BTreeMap<String, String>,
+    ) -> Result<(), RunError>;
+
+    /// Keep `usage` updated with a running container's resource usage. This
+    /// may return once the container exits or may never return, so callers
+    /// should stop polling it once [`Executor::wait`] completes. Does nothing
+    /// by default.
+    async fn sample_usage(&self, _container: &str, _usage: &Mutex<ResourceUsage>) {}
+
+    /// Pass each line that a running container writes from `since` onwards
+    /// to `line`, returning once the container exits. Callers should stop
+    /// polling it shortly after [`Executor::wait`] completes, in case it
+    /// doesn't. Does nothing by default.
+    async fn follow_logs(
+        &self,
+        _container: &str,
+        _since: DateTime<Utc>,
+        _line: &(dyn for<'a> Fn(OutputStream, &'a str) + Sync),
+    ) {
+    }
+
+    /// Up to the last `lines` lines that a container wrote, oldest first,
+    /// for explaining why it failed. Empty by default, or if they can't be
+    /// read.
+    async fn log_tail(&self, _container: &str, _lines: usize) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Whether the backend can be reached, such as after losing the
+    /// connection to it mid-run. Always true by default.
+    async fn is_ready(&self) -> bool {
+        true
+    }
+}
+
+#[async_trait]
+impl<T: Executor + ?Sized> Executor for Arc<T> {
+    async fn start(&self, container: &str) -> Result<(), RunError> {
+        (**self).start(container).await
+    }
+
+    async fn wait(&self, container: &str) -> Result<(), RunError> {
+        (**self).wait(container).await
+    }
+
+    async fn wait_healthy(&self, container: &str) -> Result<(), RunError> {
+        (**self).wait_healthy(container).await
+    }
+
+    async fn control(&self, container: &str, op: ControlOp) -> Result<(), RunError> {
+        (**self).control(container, op).await
+    }
+
+    async fn prune(&self, prune: &Prune) -> Result<(), RunError> {
+        (**self).prune(prune).await
+    }
+
+    async fn stop(&self, container: &str, grace: Duration) -> Result<(), RunError> {
+        (**self).stop(container, grace).await
+    }
+
+    async fn copy(
+        &self,
+        container: &str,
+        copy: &str,
+        env: &BTreeMap<String, String>,
+        command: &[String],
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        (**self).copy(container, copy, env, command, labels).await
+    }
+
+    async fn find_service(&self, service: &ComposeService) -> Result<String, RunError> {
+        (**self).find_service(service).await
+    }
+
+    async fn pull(&self, image: &str, policy: PullPolicy) -> Result<(), RunError> {
+        (**self).pull(image, policy).await
+    }
+
+    async fn create(
+        &self,
+        container: &str,
+        run: &RunImage,
+        env: &BTreeMap<String, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        (**self).create(container, run, env, labels).await
+    }
+
+    async fn remove(&self, container: &str) -> Result<(), RunError> {
+        (**self).remove(container).await
+    }
+
+    async fn exec(
+        &self,
+        container: &str,
+        command: &[String],
+        env: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        (**self).exec(container, command, env).await
+    }
+
+    async fn sample_usage(&self, container: &str, usage: &Mutex<ResourceUsage>) {
+        (**self).sample_usage(container, usage).await
+    }
+
+    async fn follow_logs(
+        &self,
+        container: &str,
+        since: DateTime<Utc>,
+        line: &(dyn for<'a> Fn(OutputStream, &'a str) + Sync),
+    ) {
+        (**self).follow_logs(container, since, line).await
+    }
+
+    async fn log_tail(&self, container: &str, lines: usize) -> Vec<String> {
+        (**self).log_tail(container, lines).await
+    }
+
+    async fn is_ready(&self) -> bool {
+        (**self).is_ready().await
+    }
+}
+
+/// Runs jobs through the Docker API.
+pub struct DockerExecutor {
+    docker: Docker,
+}
+
+impl DockerExecutor {
+    pub fn new(docker: Docker) -> Self {
+        DockerExecutor { docker }
+    }
+}
+
+#[async_trait]
+impl Executor for DockerExecutor {
+    async fn start(&self, container: &str) -> Result<(), RunError> {
+        self.docker
+            .start_container(container, None::<StartContainerOptions>)
+            .await
+            .map_err(RunError::Start)
+    }
+
+    async fn wait(&self, container: &str) -> Result<(), RunError> {
+        let mut wait = self
+            .docker
+            .wait_container(container, None::<WaitContainerOptions>);
+
+        // Overly elaborate scheme of potential failure responses...
+
+        match wait.next().await {
+            None => Err(RunError::NoResponse),
+            Some(result) => match result {
+                Err(error) => match error {
+                    DockerContainerWaitError {
+                        error: error_msg,
+                        code: status_code,
+                    } => {
+                        if error_msg.is_empty() {
+                            Err(RunError::Exit { status_code })
+                        } else {
+                            Err(RunError::WaitMessage(error_msg))
+                        }
+                    }
+                    _ => Err(RunError::Wait(error)),
+                },
+                Ok(_) => Ok(()),
+            },
+        }
+    }
+
+    async fn wait_healthy(&self, container: &str) -> Result<(), RunError> {
+        let unhealthy = |reason: &str| RunError::Unhealthy {
+            reason: reason.to_string(),
+        };
+
+        loop {
+            let state = self
+                .docker
+                .inspect_container(container, None::<InspectContainerOptions>)
+                .await
+                .map_err(RunError::Wait)?
+                .state
+                .unwrap_or_default();
+
+            if state.running != Some(true) {
+                return Err(unhealthy("the container exited"));
+            }
+
+            match state.health.and_then(|health| health.status) {
+                Some(HealthStatusEnum::HEALTHY) => return Ok(()),
+                Some(HealthStatusEnum::UNHEALTHY) => {
+                    return Err(unhealthy("its healthcheck failed"))
+                }
+                Some(HealthStatusEnum::STARTING) => sleep(HEALTH_POLL).await,
+                _ => return Err(unhealthy("it has no healthcheck")),
+            }
+        }
+    }
+
+    async fn stop(&self, container: &str, grace: Duration) -> Result<(), RunError> {
+        let options = StopContainerOptions {
+            t: Some(grace.as_secs().try_into().unwrap_or(i32::MAX)),
+            signal: None,
+        };
+
+        self.docker
+            .stop_container(container, Some(options))
+            .await
+            .map_err(RunError::Stop)
+    }
+
+    async fn control(&self, container: &str, op: ControlOp) -> Result<(), RunError> {
+        let result = match op {
+            ControlOp::Stop => {
+                self.docker
+                    .stop_container(container, None::<StopContainerOptions>)
+                    .await
+            }
+            ControlOp::Restart => {
+                self.docker
+                    .restart_container(container, None::<RestartContainerOptions>)
+                    .await
+            }
+            ControlOp::Pause => self.docker.pause_container(container).await,
+            ControlOp::Unpause => self.docker.unpause_container(container).await,
+        };
+
+        result.map_err(|source| RunError::Control { op, source })
+    }
+
+    async fn prune(&self, prune: &Prune) -> Result<(), RunError> {
+        let filters = prune.filters();
+        let error = |source| RunError::Prune {
+            target: prune.target,
+            source,
+        };
+
+        let (removed, space_reclaimed) = match prune.target {
+            PruneTarget::Images => {
+                let options = PruneImagesOptionsBuilder::new().filters(&filters).build();
+                let response = self
+                    .docker
+                    .prune_images(Some(options))
+                    .await
+                    .map_err(error)?;
+
+                (
+                    response.images_deleted.map_or(0, |deleted| deleted.len()),
+                    response.space_reclaimed,
+                )
+            }
+            PruneTarget::Containers => {
+                let options = PruneContainersOptionsBuilder::new()
+                    .filters(&filters)
+                    .build();
+                let response = self
+                    .docker
+                    .prune_containers(Some(options))
+                    .await
+                    .map_err(error)?;
+
+                (
+                    response
+                        .containers_deleted
+                        .map_or(0, |deleted| deleted.len()),
+                    response.space_reclaimed,
+                )
+            }
+            PruneTarget::Volumes => {
+                let options = PruneVolumesOptionsBuilder::new().filters(&filters).build();
+                let response = self
+                    .docker
+                    .prune_volumes(Some(options))
+                    .await
+                    .map_err(error)?;
+
+                (
+                    response.volumes_deleted.map_or(0, |deleted| deleted.len()),
+                    response.space_reclaimed,
+                )
+            }
+        };
+
+        info!(
+            target = %prune.target,
+            removed,
+            space_reclaimed_bytes = space_reclaimed.unwrap_or(0),
+            "Pruned unused objects"
+        );
+
+        Ok(())
+    }
+
+    async fn copy(
+        &self,
+        container: &str,
+        copy: &str,
+        env: &BTreeMap<String, String>,
+        command: &[String],
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        // The copy from the last run is kept until now so that its logs can
+        // be read. It may not exist, so failing to remove it is fine.
+        let options = RemoveContainerOptionsBuilder::new().force(true).build();
+        let _ = self.docker.remove_container(copy, Some(options)).await;
+
+        let original = self
+            .docker
+            .inspect_container(container, None::<InspectContainerOptions>)
+            .await
+            .map_err(RunError::Copy)?;
+        let config = original.config.unwrap_or_default();
+        let body = ContainerCreateBody {
+            hostname: config.hostname,
+            domainname: config.domainname,
+            user: config.user,
+            tty: config.tty,
+            open_stdin: config.open_stdin,
+            env: Some(merge_env(config.env.unwrap_or_default(), env)),
+            cmd: match command {
+                [] => config.cmd,
+                command => Some(command.to_vec()),
+            },
+            healthcheck: config.healthcheck,
+            image: config.image,
+            volumes: config.volumes,
+            working_dir: config.working_dir,
+            entrypoint: config.entrypoint,
+            // Compose would take the copy for one of its own containers.
+            labels: Some(
+                config
+                    .labels
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|(label, _)| !label.starts_with("com.docker.compose."))
+                    .chain(labels.clone())
+                    .collect(),
+            ),
+            stop_signal: config.stop_signal,
+            stop_timeout: config.stop_timeout,
+            shell: config.shell,
+            host_config: original.host_config,
+            ..ContainerCreateBody::default()
+        };
+        let options = CreateContainerOptionsBuilder::new().name(copy).build();
+
+        self.docker
+            .create_container(Some(options), body)
+            .await
+            .map(|_| ())
+            .map_err(RunError::Copy)
+    }
+
+    async fn find_service(&self, service: &ComposeService) -> Result<String, RunError> {
+        // One-off containers are those of `docker compose run`.
+        let labels = vec![
+            format!("com.docker.compose.project={}", service.project),
+            format!("com.docker.compose.service={}", service.service),
+            String::from("com.docker.compose.oneoff=False"),
+        ];
+        let filters = HashMap::from([("label", labels)]);
+        let options = ListContainersOptionsBuilder::new()
+            .all(true)
+            .filters(&filters)
+            .build();
+        let containers = self
+            .docker
+            .list_containers(Some(options))
+            .await
+            .map_err(RunError::FindService)?;
+
+        // A scaled service has several containers, of which the first is
+        // used.
+        containers
+            .into_iter()
+            .flat_map(|container| container.names.unwrap_or_default())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .min()
+            .ok_or_else(|| RunError::NoService {
+                project: service.project.clone(),
+                service: service.service.clone(),
+            })
+    }
+
+    async fn pull(&self, image: &str, policy: PullPolicy) -> Result<(), RunError> {
+        match policy {
+            PullPolicy::Never => return Ok(()),
+            PullPolicy::Missing if self.docker.inspect_image(image).await.is_ok() => return Ok(()),
+            PullPolicy::Always | PullPolicy::Missing => {}
+        }
+
+        // Without a tag or digest, Docker would pull every tag of the image.
+        let name = image.rsplit('/').next().unwrap_or(image);
+        let mut options = CreateImageOptionsBuilder::new().from_image(image);
+        if !name.contains([':', '@']) {
+            options = options.tag("latest");
+        }
+
+        info!(image, "Pulling image");
+
+        let mut progress = self.docker.create_image(Some(options.build()), None, None);
+
+        while let Some(update) = progress.next().await {
+            let update = update.map_err(RunError::Pull)?;
+            let Some(status) = update.status else {
+                continue;
+            };
+
+            // Per-layer progress bars are too chatty to log by default.
+            match update.progress {
+                Some(progress) => debug!(image, status, progress, "Pull progress"),
+                None => info!(image, status, "Pull progress"),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn create(
+        &self,
+        container: &str,
+        run: &RunImage,
+        env: &BTreeMap<String, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        // As with copies, the last run's container is kept until now.
+        let options = RemoveContainerOptionsBuilder::new().force(true).build();
+        let _ = self.docker.remove_container(container, Some(options)).await;
+
+        let body = ContainerCreateBody {
+            image: Some(run.image.clone()),
+            cmd: (!run.command.is_empty()).then(|| run.command.clone()),
+            env: Some(merge_env(Vec::new(), env)),
+            labels: Some(labels.clone().into_iter().collect()),
+            host_config: Some(HostConfig {
+                // Binds take volume names as well as host paths.
+                binds: Some(run.mounts.iter().map(Mount::to_string).collect()),
+                nano_cpus: run.limits.nano_cpus.map(saturating_i64),
+                memory: run.limits.memory.map(saturating_i64),
+                pids_limit: run.limits.pids.map(saturating_i64),
+                network_mode: run.network.clone(),
+                ..HostConfig::default()
+            }),
+            ..ContainerCreateBody::default()
+        };
+        let options = CreateContainerOptionsBuilder::new().name(container).build();
+
+        self.docker
+            .create_container(Some(options), body)
+            .await
+            .map(|_| ())
+            .map_err(RunError::Create)
+    }
+
+    async fn remove(&self, container: &str) -> Result<(), RunError> {
+        self.docker
+            .remove_container(container, None::<RemoveContainerOptions>)
+            .await
+            .map_err(RunError::Remove)
+    }
+
+    async fn exec(
+        &self,
+        container: &str,
+        command: &[String],
+        env: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        let config = ExecConfig {
+            cmd: Some(command.to_vec()),
+            env: Some(merge_env(Vec::new(), env)),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..ExecConfig::default()
+        };
+        let exec = self
+            .docker
+            .create_exec(container, config)
+            .await
+            .map_err(RunError::Exec)?;
+
+        if let StartExecResults::Attached { mut output, .. } = self
+            .docker
+            .start_exec(&exec.id, None)
+            .await
+            .map_err(RunError::Exec)?
+        {
+            while let Some(chunk) = output.next().await {
+                let chunk = chunk.map_err(RunError::Exec)?;
+
+                for line in String::from_utf8_lossy(&chunk.into_bytes()).lines() {
+                    info!(container, line, "Command output");
+                }
+            }
+        }
+
+        let inspect = self
+            .docker
+            .inspect_exec(&exec.id)
+            .await
+            .map_err(RunError::Exec)?;
+
+        match inspect.exit_code {
+            Some(0) => Ok(()),
+            Some(status_code) => Err(RunError::Exit { status_code }),
+            None => Err(RunError::NoResponse),
+        }
+    }
+
+    async fn sample_usage(&self, container: &str, usage: &Mutex<ResourceUsage>) {
+        let options = StatsOptions {
+            stream: true,
+            one_shot: false,
+        };
+        let mut stats = self.docker.stats(container, Some(options));
+
+        while let Some(Ok(sample)) = stats.next().await {
+            // Docker reports the container's cumulative CPU time, which
+            // starts from zero each time the container is started.
+            let memory = sample.memory_stats.as_ref().map_or(0, |memory| {
+                memory.usage.unwrap_or(0).max(memory.max_usage.unwrap_or(0))
+            });
+            let cpu_nanos = sample
+                .cpu_stats
+                .as_ref()
+                .and_then(|cpu| cpu.cpu_usage.as_ref())
+                .and_then(|cpu| cpu.total_usage)
+                .unwrap_or(0);
+
+            let mut usage = usage.lock().unwrap();
+            usage.peak_memory_bytes = usage.peak_memory_bytes.max(memory);
+            usage.cpu_time = usage.cpu_time.max(Duration::from_nanos(cpu_nanos));
+        }
+    }
+
+    async fn follow_logs(
+        &self,
+        container: &str,
+        since: DateTime<Utc>,
+        line: &(dyn for<'a> Fn(OutputStream, &'a str) + Sync),
+    ) {
+        let options = LogsOptionsBuilder::new()
+            .follow(true)
+            .stdout(true)
+            .stderr(true)
+            .since(since.timestamp().try_into().unwrap_or(i32::MAX))
+            .build();
+        let mut logs = self.docker.logs(container, Some(options));
+
+        while let Some(Ok(output)) = logs.next().await {
+            let (stream, message) = match output {
+                LogOutput::StdErr { message } => (OutputStream::Stderr, message),
+                LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                    (OutputStream::Stdout, message)
+                }
+                LogOutput::StdIn { .. } => continue,
+            };
+
+            for text in String::from_utf8_lossy(&message).lines() {
+                line(stream, text);
+            }
+        }
+    }
+
+    async fn log_tail(&self, container: &str, lines: usize) -> Vec<String> {
+        let options = LogsOptionsBuilder::new()
+            .stdout(true)
+            .stderr(true)
+            .tail(&lines.to_string())
+            .build();
+        let mut logs = self.docker.logs(container, Some(options));
+        let mut tail = Vec::new();
+
+        while let Some(Ok(output)) = logs.next().await {
+            if let LogOutput::StdIn { .. } = output {
+                continue;
+            }
+
+            let text = String::from_utf8_lossy(&output.into_bytes()).into_owned();
+            tail.extend(text.lines().map(str::to_string));
+        }
+
+        // A line may have been written in several chunks.
+        let skip = tail.len().saturating_sub(lines);
+        tail.drain(..skip);
+
+        tail
+    }
+
+    async fn is_ready(&self) -> bool {
+        self.docker.ping().await.is_ok()
+    }
+}
+
+/// Docker takes resource limits as signed integers.
+fn saturating_i64(n: u64) -> i64 {
+    n.try_into().unwrap_or(i64::MAX)
+}
+
+/// Docker's `KEY=value` environment with the variables in `env` replacing or
+/// added to those of the same name.
+fn merge_env(original: Vec<String>, env: &BTreeMap<String, String>) -> Vec<String> {
+    let mut merged: Vec<String> = original
+        .into_iter()
+        .filter(|var| {
+            let name = var.split_once('=').map_or(var.as_str(), |(name, _)| name);
+            !env.contains_key(name)
+        })
+        .collect();
+
+    merged.extend(env.iter().map(|(name, value)| format!("{name}={value}")));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_env() {
+        let env = BTreeMap::from([
+            (String::from("MODE"), String::from("full")),
+            (String::from("TARGET"), String::from("s3://backups")),
+        ]);
+        let original = vec![String::from("PATH=/usr/bin"), String::from("MODE=quick")];
+
+        assert_eq!(
+            merge_env(original, &env),
+            ["PATH=/usr/bin", "MODE=full", "TARGET=s3://backups"]
+        );
+    }
+}
@@ -0,0 +1,1229 @@
+//! Conversion of jobs into other schedulers' formats, for migrating away from
+//! a single Docker host.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use bollard::{query_parameters::InspectContainerOptions, Docker};
+use thiserror::Error;
+
+use crate::{
+    crontab::{
+        default_name, format_bytes, format_cpus, shift_weekdays, write_env, CronSchedule,
+        JobOptions,
+    },
+    job::{Action, Job, Limits, Overlap, PruneTarget, PullPolicy},
+    timezone::Timezone,
+    trigger::TriggerSpec,
+};
+
+/// Why a job, or part of one, couldn't be exported faithfully.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ExportWarning {
+    #[error("Job {job} was skipped, as its schedule can't be exported: {reason}")]
+    Schedule { job: String, reason: String },
+    #[error("Job {job} was skipped, as its action can't be exported: {reason}")]
+    Action { job: String, reason: String },
+    #[error("Job {job} has {what}, which have no equivalent and were left out")]
+    Dropped { job: String, what: &'static str },
+}
+
+/// What a container runs, as far as another scheduler needs to know.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContainerSpec {
+    pub image: String,
+    pub entrypoint: Vec<String>,
+    pub cmd: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl ContainerSpec {
+    /// A placeholder for a container that couldn't be inspected, using its
+    /// name as the image name.
+    pub fn placeholder(container: &str) -> Self {
+        ContainerSpec {
+            image: container.to_string(),
+            ..ContainerSpec::default()
+        }
+    }
+}
+
+/// Look up what an existing container runs.
+pub async fn inspect(
+    docker: &Docker,
+    container: &str,
+) -> Result<ContainerSpec, bollard::errors::Error> {
+    let response = docker
+        .inspect_container(container, None::<InspectContainerOptions>)
+        .await?;
+    let config = response.config.unwrap_or_default();
+
+    Ok(ContainerSpec {
+        image: config.image.unwrap_or_else(|| container.to_string()),
+        entrypoint: config.entrypoint.unwrap_or_default(),
+        cmd: config.cmd.unwrap_or_default(),
+        env: config
+            .env
+            .unwrap_or_default()
+            .iter()
+            .map(|var| match var.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => (var.clone(), String::new()),
+            })
+            .collect(),
+    })
+}
+
+/// Why a schedule using Quartz day specifiers can't be exported.
+const DAY_SPECIFIERS: &str = "L, W and # day specifiers aren't supported";
+
+/// Convert a six- or seven-field schedule into standard five-field cron,
+/// which can't express seconds or years.
+pub fn five_field(schedule: &CronSchedule) -> Result<String, String> {
+    let source = schedule.to_string();
+
+    if schedule.has_day_specifiers() {
+        return Err(String::from(DAY_SPECIFIERS));
+    }
+
+    if source.starts_with('@') {
+        return match source.as_str() {
+            "@yearly" | "@monthly" | "@weekly" | "@daily" | "@hourly" => Ok(source),
+            _ => Err(format!("unsupported alias {source}")),
+        };
+    }
+
+    let fields: Vec<&str> = source.split_whitespace().collect();
+
+    if !matches!(fields.len(), 6 | 7) {
+        return Err(format!("unexpected number of fields in {source:?}"));
+    }
+
+    if fields[0] != "0" {
+        return Err(String::from("it runs at seconds other than zero"));
+    }
+
+    if fields.get(6).is_some_and(|year| *year != "*") {
+        return Err(String::from("it is restricted to certain years"));
+    }
+
+    Ok(format!(
+        "{} {} {} {} {}",
+        fields[1],
+        fields[2],
+        fields[3],
+        fields[4],
+        shift_weekdays(fields[5], -1)
+    ))
+}
+
+/// Turn a job or container name into a valid Kubernetes object name. CronJob
+/// names are limited to 52 characters, so that the names of the Jobs created
+/// from them fit within the usual 63.
+fn k8s_name(name: &str) -> String {
+    let name: String = name
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    let name = name[..name.len().min(52)].trim_end_matches('-');
+
+    if name.is_empty() {
+        String::from("job")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Quote a string for YAML. JSON strings are valid YAML scalars, which saves
+/// having to reason about YAML's many quoting rules.
+fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+fn quote_list(items: &[String]) -> String {
+    let items: Vec<_> = items.iter().map(|item| quote(item)).collect();
+
+    format!("[{}]", items.join(", "))
+}
+
+fn write_container(out: &mut String, indent: &str, name: &str, spec: &ContainerSpec) {
+    writeln!(out, "{indent}- name: {}", quote(&k8s_name(name))).unwrap();
+    writeln!(out, "{indent}  image: {}", quote(&spec.image)).unwrap();
+
+    if !spec.entrypoint.is_empty() {
+        writeln!(out, "{indent}  command: {}", quote_list(&spec.entrypoint)).unwrap();
+    }
+
+    if !spec.cmd.is_empty() {
+        writeln!(out, "{indent}  args: {}", quote_list(&spec.cmd)).unwrap();
+    }
+
+    if !spec.env.is_empty() {
+        writeln!(out, "{indent}  env:").unwrap();
+
+        for (name, value) in &spec.env {
+            writeln!(out, "{indent}    - name: {}", quote(name)).unwrap();
+            writeln!(out, "{indent}      value: {}", quote(value)).unwrap();
+        }
+    }
+}
+
+/// The IANA name of a time zone, which is all that Kubernetes and systemd
+/// understand. Fixed offsets only have one if they are whole hours.
+fn zone_name(timezone: &Timezone) -> Result<String, String> {
+    let Timezone::Fixed(offset) = timezone else {
+        return Ok(timezone.to_string());
+    };
+    let seconds = offset.local_minus_utc();
+
+    match seconds {
+        0 => Ok(String::from("UTC")),
+        // The Etc zones have their signs inverted, so that Etc/GMT-2 is two
+        // hours ahead of UTC.
+        _ if seconds % 3600 == 0 => Ok(format!("Etc/GMT{:+}", -seconds / 3600)),
+        _ => Err(format!("the time zone {timezone} has no IANA name")),
+    }
+}
+
+fn cron_schedule(job: &Job) -> Result<&CronSchedule, String> {
+    match &job.schedule {
+        TriggerSpec::Cron(schedule) => Ok(schedule),
+        TriggerSpec::Interval(_) => Err(String::from("fixed intervals aren't supported")),
+        TriggerSpec::File(_) => Err(String::from("file triggers aren't supported")),
+        TriggerSpec::Reboot => Err(String::from("@reboot isn't supported")),
+        TriggerSpec::After(_) => Err(String::from("@after isn't supported")),
+        TriggerSpec::At(_) => Err(String::from("one-off times aren't supported")),
+    }
+}
+
+/// Render `jobs` as a normalized crontab, with runs of whitespace collapsed
+/// and each job's name in a comment above it when that differs from its
+/// container. Settings that crontabs can't express are left out with a
+/// warning, as are jobs that aren't on cron schedules.
+pub fn to_crontab(jobs: &[Job]) -> (String, Vec<ExportWarning>) {
+    let mut out = String::new();
+    let mut warnings = Vec::new();
+    let mut env = BTreeMap::new();
+
+    for job in jobs {
+        let schedule = match cron_schedule(job) {
+            Ok(schedule) => schedule.to_string(),
+            Err(_)
+                if matches!(
+                    job.schedule,
+                    TriggerSpec::Reboot | TriggerSpec::After(_) | TriggerSpec::At(_)
+                ) =>
+            {
+                job.schedule.to_string()
+            }
+            Err(reason) => {
+                warnings.push(ExportWarning::Schedule {
+                    job: job.name.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        let dropped = [
+            (!job.before.is_empty(), "before hooks"),
+            (!job.after.is_empty(), "after hooks"),
+        ];
+
+        for (_, what) in dropped.into_iter().filter(|(dropped, _)| *dropped) {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what,
+            });
+        }
+
+        let mut command = job.action.to_string();
+        for arg in &job.command {
+            write!(command, " {arg}").unwrap();
+        }
+
+        write_env(&mut out, &mut env, &job.env);
+
+        // The options block ends at a bracket and is split at commas.
+        let name = if job.name.contains([',', ']']) {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "names containing commas or brackets",
+            });
+            None
+        } else {
+            (job.name != default_name(&command)).then(|| job.name.clone())
+        };
+        if job.disabled {
+            out.push('!');
+        }
+
+        if let Some(timezone) = &job.timezone {
+            write!(out, "CRON_TZ={timezone} ").unwrap();
+        }
+
+        let schedule: Vec<&str> = schedule.split_whitespace().collect();
+        let options = JobOptions {
+            name,
+            overlap: job.overlap,
+            retry: job.retry,
+            timeout: job.timeout,
+            jitter: job.jitter,
+            healthy_within: job.healthy_within,
+            max_runs: job.max_runs,
+            run_on_start: job.run_on_start,
+            on_failure: job.on_failure.clone(),
+            not_before: job.not_before,
+            not_after: job.not_after,
+            pull: match &job.action {
+                Action::Run(run) if run.pull != PullPolicy::default() => Some(run.pull),
+                _ => None,
+            },
+            auto_remove: matches!(&job.action, Action::Run(run) if run.auto_remove),
+            // Written as `KEY=value` lines instead.
+            env: BTreeMap::new(),
+            volumes: match &job.action {
+                Action::Run(run) => run.mounts.clone(),
+                _ => Vec::new(),
+            },
+            limits: match &job.action {
+                Action::Run(run) => run.limits,
+                _ => Limits::default(),
+            },
+            network: match &job.action {
+                Action::Run(run) => run.network.clone(),
+                _ => None,
+            },
+            success_codes: job.success_codes.clone(),
+        };
+
+        write!(out, "{} ", schedule.join(" ")).unwrap();
+
+        if !options.is_default() {
+            write!(out, "[{options}] ").unwrap();
+        }
+
+        if let Some(host) = &job.host {
+            write!(out, "@host:{host} ").unwrap();
+        }
+
+        writeln!(out, "{command}").unwrap();
+    }
+
+    (out, warnings)
+}
+
+/// Render `jobs` as a multi-document YAML stream of Kubernetes CronJobs.
+/// `containers` supplies the details of each container named by the jobs.
+///
+/// Jobs are exported with a `backoffLimit` of their number of retries, though
+/// Kubernetes has its own backoff delays, and with the `concurrencyPolicy`
+/// matching their overlap policy, except that Kubernetes can't queue runs so
+/// skips them instead. Before hooks become init containers. Jobs that can't be converted are skipped with a warning.
+pub fn to_k8s(
+    jobs: &[Job],
+    namespace: Option<&str>,
+    containers: impl Fn(&str) -> ContainerSpec,
+) -> (String, Vec<ExportWarning>) {
+    let mut out = String::new();
+    let mut warnings = Vec::new();
+
+    for job in jobs {
+        let schedule = cron_schedule(job)
+            .and_then(five_field)
+            .and_then(|schedule| {
+                let timezone = job.timezone.as_ref().map(zone_name).transpose()?;
+
+                Ok((schedule, timezone))
+            });
+        let (schedule, timezone) = match schedule {
+            Ok(schedule) => schedule,
+            Err(reason) => {
+                warnings.push(ExportWarning::Schedule {
+                    job: job.name.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+        let mut spec = match &job.action {
+            Action::Start(container) if job.command.is_empty() => containers(container),
+            Action::Start(container) => ContainerSpec {
+                cmd: job.command.clone(),
+                ..containers(container)
+            },
+            Action::Run(run) if !run.mounts.is_empty() => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("mounts need volumes that docker-cron can't define"),
+                });
+                continue;
+            }
+            Action::Run(run) => ContainerSpec {
+                image: run.image.clone(),
+                cmd: run.command.clone(),
+                ..ContainerSpec::default()
+            },
+            Action::Exec(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("commands in running containers aren't supported"),
+                });
+                continue;
+            }
+            Action::Compose(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("Compose services aren't supported"),
+                });
+                continue;
+            }
+            Action::Local(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("local commands aren't supported"),
+                });
+                continue;
+            }
+            Action::Control(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("pods can't change other containers' state"),
+                });
+                continue;
+            }
+            Action::Prune(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("pods can't prune the node's Docker objects"),
+                });
+                continue;
+            }
+        };
+
+        if matches!(&job.action, Action::Run(run) if !run.limits.is_unlimited()) {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "resource limits",
+            });
+        }
+
+        // Pods share the cluster's network.
+        if matches!(&job.action, Action::Run(run) if run.network.is_some()) {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "Docker networks",
+            });
+        }
+
+        if !job.after.is_empty() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "after hooks",
+            });
+        }
+
+        if job.on_failure.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "on-failure containers",
+            });
+        }
+
+        if job.not_before.is_some() || job.not_after.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "start and end dates",
+            });
+        }
+
+        if job.run_on_start {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "runs on startup",
+            });
+        }
+
+        if job.max_runs.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "run limits",
+            });
+        }
+
+        if !job.success_codes.is_empty() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "success exit codes",
+            });
+        }
+
+        if job.healthy_within.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "healthcheck completion",
+            });
+        }
+
+        let concurrency_policy = match job.overlap {
+            Overlap::Skip => "Forbid",
+            Overlap::Queue => {
+                warnings.push(ExportWarning::Dropped {
+                    job: job.name.clone(),
+                    what: "queued overlapping runs",
+                });
+                "Forbid"
+            }
+            Overlap::Replace => "Replace",
+        };
+
+        let container = job.action.container();
+
+        out.push_str("---\n");
+        out.push_str("apiVersion: batch/v1\n");
+        out.push_str("kind: CronJob\n");
+        out.push_str("metadata:\n");
+        writeln!(out, "  name: {}", quote(&k8s_name(&job.name))).unwrap();
+
+        if let Some(namespace) = namespace {
+            writeln!(out, "  namespace: {}", quote(namespace)).unwrap();
+        }
+
+        out.push_str("  labels:\n");
+        out.push_str("    app.kubernetes.io/managed-by: docker-cron-export\n");
+        out.push_str("spec:\n");
+        writeln!(out, "  schedule: {}", quote(&schedule)).unwrap();
+
+        if let Some(timezone) = &timezone {
+            writeln!(out, "  timeZone: {}", quote(timezone)).unwrap();
+        }
+
+        writeln!(out, "  concurrencyPolicy: {concurrency_policy}").unwrap();
+
+        if job.disabled {
+            out.push_str("  suspend: true\n");
+        }
+
+        out.push_str("  jobTemplate:\n");
+        out.push_str("    spec:\n");
+        writeln!(out, "      backoffLimit: {}", job.retry.attempts).unwrap();
+
+        if let Some(timeout) = job.timeout {
+            writeln!(
+                out,
+                "      activeDeadlineSeconds: {}",
+                timeout.as_secs().max(1)
+            )
+            .unwrap();
+        }
+
+        out.push_str("      template:\n");
+        out.push_str("        spec:\n");
+        out.push_str("          restartPolicy: Never\n");
+
+        if !job.before.is_empty() {
+            out.push_str("          initContainers:\n");
+
+            for hook in &job.before {
+                write_container(&mut out, "            ", hook, &containers(hook));
+            }
+        }
+
+        for (name, value) in &job.env {
+            spec.env.retain(|(existing, _)| existing != name);
+            spec.env.push((name.clone(), value.clone()));
+        }
+
+        out.push_str("          containers:\n");
+        write_container(&mut out, "            ", container, &spec);
+    }
+
+    (out, warnings)
+}
+
+/// The values allowed in each field of a six- or seven-field schedule.
+const FIELD_RANGES: [(u32, u32); 7] = [
+    (0, 59),
+    (0, 59),
+    (0, 23),
+    (1, 31),
+    (1, 12),
+    (1, 7),
+    (1970, 2099),
+];
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+fn field_value(field: usize, value: &str) -> Result<u32, String> {
+    let names: &[&str] = match field {
+        4 => &MONTHS,
+        5 => &WEEKDAYS,
+        _ => &[],
+    };
+    let prefix = value.get(..3).unwrap_or(value);
+
+    if let Some(idx) = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(prefix))
+    {
+        return Ok(idx as u32 + 1);
+    }
+
+    value
+        .parse()
+        .map_err(|_| format!("unsupported value {value:?}"))
+}
+
+/// Expand one field of a cron schedule into the values it matches, or `None`
+/// if it matches everything.
+fn expand_field(field: usize, spec: &str) -> Result<Option<Vec<u32>>, String> {
+    if matches!(spec, "*" | "?") {
+        return Ok(None);
+    }
+
+    let (min, max) = FIELD_RANGES[field];
+    let mut values = Vec::new();
+
+    for item in spec.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (field_value(field, start)?, field_value(field, end)?),
+            // A bare start with a step repeats until the end of the range.
+            None if step.is_some() => (field_value(field, range)?, max),
+            None => {
+                let value = field_value(field, range)?;
+                (value, value)
+            }
+        };
+        let step: u32 = match step {
+            Some(step) => step
+                .parse()
+                .ok()
+                .filter(|step| *step > 0)
+                .ok_or_else(|| format!("invalid step in {item:?}"))?,
+            None => 1,
+        };
+
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+
+    Ok(Some(values))
+}
+
+/// Convert a schedule into a systemd calendar event, as described in
+/// systemd.time(7), in the given time zone or else UTC.
+pub fn on_calendar(schedule: &CronSchedule, timezone: Option<&Timezone>) -> Result<String, String> {
+    if schedule.has_day_specifiers() {
+        return Err(String::from(DAY_SPECIFIERS));
+    }
+
+    let source = schedule.to_string();
+    let source = match source.as_str() {
+        "@yearly" => "0 0 0 1 1 *",
+        "@monthly" => "0 0 0 1 * *",
+        "@weekly" => "0 0 0 * * 1",
+        "@daily" => "0 0 0 * * *",
+        "@hourly" => "0 0 * * * *",
+        source if source.starts_with('@') => return Err(format!("unsupported alias {source}")),
+        source => source,
+    };
+    let fields: Vec<&str> = source.split_whitespace().collect();
+
+    if !matches!(fields.len(), 6 | 7) {
+        return Err(format!("unexpected number of fields in {source:?}"));
+    }
+
+    let mut parts = Vec::new();
+
+    for (idx, field) in fields.iter().enumerate() {
+        let part = match expand_field(idx, field)? {
+            None => String::from("*"),
+            Some(values) if idx == 5 => values
+                .iter()
+                .map(|day| WEEKDAYS[(*day as usize - 1) % 7])
+                .collect::<Vec<_>>()
+                .join(","),
+            Some(values) => values
+                .iter()
+                .map(|value| format!("{value:02}"))
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+
+        parts.push(part);
+    }
+
+    let year = parts.get(6).map_or("*", String::as_str);
+    let date = format!("{year}-{}-{}", parts[4], parts[3]);
+    let time = format!("{}:{}:{}", parts[2], parts[1], parts[0]);
+
+    let timezone = zone_name(timezone.unwrap_or(&Timezone::Utc))?;
+
+    Ok(match parts[5].as_str() {
+        "*" => format!("{date} {time} {timezone}"),
+        weekdays => format!("{weekdays} {date} {time} {timezone}"),
+    })
+}
+
+/// Turn a job name into something usable in a systemd unit name.
+fn unit_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    format!("docker-cron-{name}")
+}
+
+/// Render `jobs` as pairs of systemd `.service` and `.timer` units, returned
+/// as `(file name, contents)`. Each service runs `docker start --attach` on
+/// the job's container, so the unit fails if the container does, with before
+/// hooks as `ExecStartPre` and after hooks as `ExecStopPost` commands.
+pub fn to_systemd(jobs: &[Job], docker: &str) -> (Vec<(String, String)>, Vec<ExportWarning>) {
+    let mut units = Vec::new();
+    let mut warnings = Vec::new();
+
+    for job in jobs {
+        let timer = match &job.schedule {
+            TriggerSpec::Cron(schedule) => {
+                on_calendar(schedule, job.timezone.as_ref()).map(|event| {
+                    // systemd defaults to an accuracy of a minute.
+                    format!("OnCalendar={event}\nAccuracySec=1s\n")
+                })
+            }
+            TriggerSpec::Interval(period) => {
+                let period = humantime::format_duration(*period);
+                Ok(format!(
+                    "OnActiveSec={period}\nOnUnitActiveSec={period}\nAccuracySec=1s\n"
+                ))
+            }
+            TriggerSpec::File(_) => Err(String::from("file triggers aren't supported")),
+            // Timers are started at boot, along with docker.service.
+            TriggerSpec::Reboot => Ok(String::from("OnBootSec=0\n")),
+            TriggerSpec::After(_) => Err(String::from("@after isn't supported")),
+            TriggerSpec::At(at) => Ok(format!(
+                "OnCalendar={} UTC\nAccuracySec=1s\n",
+                at.format("%Y-%m-%d %H:%M:%S")
+            )),
+        };
+        let timer = match timer {
+            Ok(timer) => timer,
+            Err(reason) => {
+                warnings.push(ExportWarning::Schedule {
+                    job: job.name.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        let exec_start = match &job.action {
+            Action::Start(container) => format!("start --attach {container}"),
+            Action::Run(run) => {
+                let mut exec_start = String::from("run --rm");
+
+                if run.pull != PullPolicy::default() {
+                    write!(exec_start, " --pull {}", run.pull).unwrap();
+                }
+
+                for mount in &run.mounts {
+                    write!(exec_start, " -v {mount}").unwrap();
+                }
+
+                let flags = [
+                    ("cpus", run.limits.nano_cpus.map(format_cpus)),
+                    ("memory", run.limits.memory.map(format_bytes)),
+                    ("pids-limit", run.limits.pids.map(|pids| pids.to_string())),
+                    ("network", run.network.clone()),
+                ];
+
+                for (flag, value) in flags {
+                    if let Some(value) = value {
+                        write!(exec_start, " --{flag} {value}").unwrap();
+                    }
+                }
+
+                write!(exec_start, " --name {} {}", run.container, run.image).unwrap();
+
+                for arg in &run.command {
+                    write!(exec_start, " {arg}").unwrap();
+                }
+
+                exec_start
+            }
+            Action::Exec(exec) => {
+                let mut exec_start = format!("exec {}", exec.container);
+
+                for arg in &exec.command {
+                    write!(exec_start, " {arg}").unwrap();
+                }
+
+                exec_start
+            }
+            // Only docker-cron itself finds a service's container by its
+            // labels.
+            Action::Compose(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("Compose services aren't supported"),
+                });
+                continue;
+            }
+            // Shell commands would need quoting for systemd, and are simpler
+            // written as services by hand.
+            Action::Local(_) => {
+                warnings.push(ExportWarning::Action {
+                    job: job.name.clone(),
+                    reason: String::from("local commands aren't supported"),
+                });
+                continue;
+            }
+            Action::Control(control) => format!("{} {}", control.op, control.container),
+            Action::Prune(prune) => {
+                let object = match prune.target {
+                    PruneTarget::Images => "image",
+                    PruneTarget::Containers => "container",
+                    PruneTarget::Volumes => "volume",
+                };
+                let mut exec_start = format!("{object} prune -f");
+
+                if prune.all {
+                    exec_start.push_str(" -a");
+                }
+
+                if let Some(until) = prune.until {
+                    write!(exec_start, " --filter until={}s", until.as_secs()).unwrap();
+                }
+
+                for label in &prune.labels {
+                    write!(exec_start, " --filter label={label}").unwrap();
+                }
+
+                for label in &prune.excluded_labels {
+                    write!(exec_start, " --filter label!={label}").unwrap();
+                }
+
+                exec_start
+            }
+        };
+
+        // A timer never starts a service that's still running.
+
+        if job.overlap != Overlap::Skip {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "queued or replaced overlapping runs",
+            });
+        }
+
+        if job.retry.attempts > 0 {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "retries",
+            });
+        }
+
+        if job.on_failure.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "on-failure containers",
+            });
+        }
+
+        if job.not_before.is_some() || job.not_after.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "start and end dates",
+            });
+        }
+
+        if job.run_on_start {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "runs on startup",
+            });
+        }
+
+        if job.max_runs.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "run limits",
+            });
+        }
+
+        if !job.env.is_empty() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "environment variables",
+            });
+        }
+
+        if job.healthy_within.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "healthcheck completion",
+            });
+        }
+
+        // Every unit runs the same docker binary against the same daemon.
+        if job.host.is_some() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "Docker host selections",
+            });
+        }
+
+        // `docker start` can't change a container's command.
+        if !job.command.is_empty() {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "command overrides",
+            });
+        }
+
+        // Whether a timer runs depends on whether it's enabled, which units
+        // can't say for themselves.
+        if job.disabled {
+            warnings.push(ExportWarning::Dropped {
+                job: job.name.clone(),
+                what: "disabled markers",
+            });
+        }
+
+        let name = unit_name(&job.name);
+        let mut service = String::new();
+
+        writeln!(service, "[Unit]").unwrap();
+        writeln!(service, "Description=docker-cron job {}", job.name).unwrap();
+        writeln!(service, "Requires=docker.service").unwrap();
+        writeln!(service, "After=docker.service").unwrap();
+        writeln!(service).unwrap();
+        writeln!(service, "[Service]").unwrap();
+        writeln!(service, "Type=oneshot").unwrap();
+
+        for hook in &job.before {
+            writeln!(service, "ExecStartPre={docker} start --attach {hook}").unwrap();
+        }
+
+        writeln!(service, "ExecStart={docker} {exec_start}").unwrap();
+
+        for hook in &job.after {
+            writeln!(service, "ExecStopPost={docker} start --attach {hook}").unwrap();
+        }
+
+        if let Some(timeout) = job.timeout {
+            writeln!(service, "TimeoutStartSec={}", timeout.as_secs().max(1)).unwrap();
+        }
+
+        if !job.success_codes.is_empty() {
+            let codes: Vec<String> = job.success_codes.iter().map(i64::to_string).collect();
+            writeln!(service, "SuccessExitStatus={}", codes.join(" ")).unwrap();
+        }
+
+        let mut timer_unit = String::new();
+
+        writeln!(timer_unit, "[Unit]").unwrap();
+        writeln!(
+            timer_unit,
+            "Description=Schedule for docker-cron job {}",
+            job.name
+        )
+        .unwrap();
+        writeln!(timer_unit).unwrap();
+        writeln!(timer_unit, "[Timer]").unwrap();
+        timer_unit.push_str(&timer);
+        writeln!(timer_unit).unwrap();
+        writeln!(timer_unit, "[Install]").unwrap();
+        writeln!(timer_unit, "WantedBy=timers.target").unwrap();
+
+        units.push((format!("{name}.service"), service));
+        units.push((format!("{name}.timer"), timer_unit));
+    }
+
+    (units, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use crate::{
+        crontab::read_crontab,
+        job::{Action, Prune, RunImage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_five_field() {
+        let convert = |spec| five_field(&CronSchedule::from_str(spec).unwrap());
+
+        assert_eq!(convert("0 30 3 * * *"), Ok(String::from("30 3 * * *")));
+        assert_eq!(convert("0 0 9 * * 2-6"), Ok(String::from("0 9 * * 1-5")));
+        assert_eq!(convert("0 0 9 * * 1,7"), Ok(String::from("0 9 * * 0,6")));
+        assert_eq!(convert("0 0 9 * * Mon"), Ok(String::from("0 9 * * Mon")));
+        assert_eq!(convert("0 0 0 1 1 * *"), Ok(String::from("0 0 1 1 *")));
+        assert!(convert("*/10 * * * * *").is_err());
+        assert!(convert("0 0 0 1 1 * 2030").is_err());
+        assert!(convert("0 0 0 L * *").is_err());
+    }
+
+    #[test]
+    fn test_to_k8s() -> Result<(), anyhow::Error> {
+        let jobs = vec![
+            Job::new("Nightly backup")
+                .schedule("0 0 3 * * *")
+                .action(Action::Start(String::from("backup")))
+                .timeout(Duration::from_secs(600))
+                .before("snapshot")
+                .overlap(Overlap::Replace)
+                .env("TARGET", "s3://backups")
+                .build()?,
+            Job::new("poll")
+                .schedule("@every 5m")
+                .action(Action::Start(String::from("poll")))
+                .build()?,
+        ];
+
+        let (yaml, warnings) = to_k8s(&jobs, Some("batch"), |container| ContainerSpec {
+            image: format!("example/{container}:latest"),
+            cmd: vec![String::from("--quiet")],
+            ..ContainerSpec::default()
+        });
+
+        assert_eq!(
+            yaml,
+            r#"---
+apiVersion: batch/v1
+kind: CronJob
+metadata:
+  name: "nightly-backup"
+  namespace: "batch"
+  labels:
+    app.kubernetes.io/managed-by: docker-cron-export
+spec:
+  schedule: "0 3 * * *"
+  concurrencyPolicy: Replace
+  jobTemplate:
+    spec:
+      backoffLimit: 0
+      activeDeadlineSeconds: 600
+      template:
+        spec:
+          restartPolicy: Never
+          initContainers:
+            - name: "snapshot"
+              image: "example/snapshot:latest"
+              args: ["--quiet"]
+          containers:
+            - name: "backup"
+              image: "example/backup:latest"
+              args: ["--quiet"]
+              env:
+                - name: "TARGET"
+                  value: "s3://backups"
+"#
+        );
+        assert_eq!(
+            warnings,
+            [ExportWarning::Schedule {
+                job: String::from("poll"),
+                reason: String::from("fixed intervals aren't supported")
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_crontab() -> Result<(), anyhow::Error> {
+        let mut jobs: Vec<Job> =
+            read_crontab("0  5 0 * * *   daily\n@monthly   monthly\n@reboot  warm\n")?
+                .into_iter()
+                .map(Job::from)
+                .collect();
+        jobs.push(
+            Job::new("nightly")
+                .schedule("0 0 3 * * *")
+                .action(Action::Start(String::from("backup")))
+                .timeout(Duration::from_secs(60))
+                .before("snapshot")
+                .overlap(Overlap::Queue)
+                .build()?,
+        );
+        jobs.push(
+            Job::new("poll")
+                .schedule("@every 5m")
+                .action(Action::Start(String::from("poll")))
+                .build()?,
+        );
+
+        let (text, warnings) = to_crontab(&jobs);
+
+        assert_eq!(
+            text,
+            concat!(
+                "0 5 0 * * * daily\n@monthly monthly\n@reboot warm\n",
+                "0 0 3 * * * [name=nightly,overlap=queue,timeout=1m] backup\n"
+            )
+        );
+        assert_eq!(read_crontab(&text)?[3].name(), "nightly");
+        assert_eq!(
+            warnings,
+            [
+                ExportWarning::Dropped {
+                    job: String::from("nightly"),
+                    what: "before hooks"
+                },
+                ExportWarning::Schedule {
+                    job: String::from("poll"),
+                    reason: String::from("fixed intervals aren't supported")
+                }
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_calendar() {
+        let convert = |spec| on_calendar(&CronSchedule::from_str(spec).unwrap(), None);
+
+        assert_eq!(convert("0 30 3 * * *").as_deref(), Ok("*-*-* 03:30:00 UTC"));
+        assert_eq!(
+            convert("*/20 0 9-17/4 * Jan,Jul 2-6").as_deref(),
+            Ok("Mon,Tue,Wed,Thu,Fri *-01,07-* 09,13,17:00:00,20,40 UTC")
+        );
+        assert_eq!(convert("@weekly").as_deref(), Ok("Sun *-*-* 00:00:00 UTC"));
+        assert_eq!(
+            convert("0 0 0 1 1 * 2030").as_deref(),
+            Ok("2030-01-01 00:00:00 UTC")
+        );
+
+        let schedule = CronSchedule::from_str("0 0 3 * * *").unwrap();
+        let timezone = Timezone::from_str("+02:00").unwrap();
+        assert_eq!(
+            on_calendar(&schedule, Some(&timezone)).as_deref(),
+            Ok("*-*-* 03:00:00 Etc/GMT-2")
+        );
+    }
+
+    #[test]
+    fn test_to_systemd() -> Result<(), anyhow::Error> {
+        let jobs = vec![
+            Job::new("backup")
+                .schedule("0 0 3 * * *")
+                .action(Action::Start(String::from("db-backup")))
+                .timeout(Duration::from_secs(600))
+                .before("snapshot")
+                .after("cleanup")
+                .build()?,
+            Job::new("poll")
+                .schedule("@every 5m")
+                .action(Action::Start(String::from("poll")))
+                .build()?,
+        ];
+
+        let (units, warnings) = to_systemd(&jobs, "/usr/bin/docker");
+
+        assert!(warnings.is_empty());
+        assert_eq!(
+            units
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "docker-cron-backup.service",
+                "docker-cron-backup.timer",
+                "docker-cron-poll.service",
+                "docker-cron-poll.timer"
+            ]
+        );
+        assert_eq!(
+            units[0].1,
+            "[Unit]
+Description=docker-cron job backup
+Requires=docker.service
+After=docker.service
+
+[Service]
+Type=oneshot
+ExecStartPre=/usr/bin/docker start --attach snapshot
+ExecStart=/usr/bin/docker start --attach db-backup
+ExecStopPost=/usr/bin/docker start --attach cleanup
+TimeoutStartSec=600
+"
+        );
+        assert_eq!(
+            units[1].1,
+            "[Unit]
+Description=Schedule for docker-cron job backup
+
+[Timer]
+OnCalendar=*-*-* 03:00:00 UTC
+AccuracySec=1s
+
+[Install]
+WantedBy=timers.target
+"
+        );
+        assert!(units[3].1.contains("OnActiveSec=5m\nOnUnitActiveSec=5m\n"));
+
+        let run = RunImage::new("ghcr.io/acme/report:2", "report")
+            .command(vec![String::from("--daily")])
+            .limits(Limits {
+                memory: Some(512 << 20),
+                ..Limits::default()
+            })
+            .network("backend");
+        let job = Job::new("report")
+            .schedule("@daily")
+            .action(Action::Run(run))
+            .build()?;
+        let (units, _) = to_systemd(&[job], "docker");
+
+        assert!(units[0].1.contains(
+            "ExecStart=docker run --rm --memory 512m --network backend \
+             --name docker-cron-report ghcr.io/acme/report:2 --daily\n"
+        ));
+
+        let job = Job::new("prune-images")
+            .schedule("@weekly")
+            .action(Action::Prune(Prune::from_str(
+                "images all until=24h label!=keep",
+            )?))
+            .build()?;
+        let (units, _) = to_systemd(&[job], "docker");
+
+        assert!(units[0].1.contains(
+            "ExecStart=docker image prune -f -a --filter until=86400s --filter label!=keep\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_k8s_name() {
+        assert_eq!(k8s_name("My_Job.1"), "my-job-1");
+        assert_eq!(k8s_name("--"), "job");
+        assert_eq!(k8s_name(&"x".repeat(80)).len(), 52);
+    }
+}
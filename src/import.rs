@@ -0,0 +1,315 @@
+//! Conversion of other schedulers' configuration into crontabs, so that their
+//! users can switch to docker-cron.
+
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+
+use cron::Schedule;
+use thiserror::Error;
+
+use crate::crontab::{shift_weekdays, CronJob, JobOptions};
+
+#[derive(Debug, Error)]
+pub enum OfeliaError {
+    #[error("Line {line_no} is not a section header, key = value pair or comment")]
+    Syntax { line_no: usize },
+    #[error("Setting on line {line_no} is outside of any section")]
+    NoSection { line_no: usize },
+}
+
+/// Why a job, or part of one, couldn't be imported faithfully.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ImportWarning {
+    #[error("Job {job} was skipped: {reason}")]
+    Skipped { job: String, reason: String },
+    #[error("Setting {key:?} of job {job} has no equivalent and was ignored")]
+    Ignored { job: String, key: String },
+    #[error("Global settings were ignored, use docker-cron's own flags instead")]
+    Global,
+}
+
+/// A job converted from another scheduler, keeping the name it had there.
+#[derive(Clone, Debug)]
+pub struct ImportedJob {
+    pub name: String,
+    pub cron_job: CronJob,
+}
+
+struct Section {
+    kind: String,
+    name: String,
+    settings: Vec<(String, String)>,
+}
+
+/// Split a gcfg-style INI file into sections such as `[job-run "backup"]`.
+fn parse_ini(text: &str) -> Result<Vec<Section>, OfeliaError> {
+    let mut sections: Vec<Section> = Vec::new();
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let (kind, name) = match header.split_once(char::is_whitespace) {
+                Some((kind, name)) => (kind, name.trim().trim_matches('"')),
+                None => (header, ""),
+            };
+
+            sections.push(Section {
+                kind: kind.to_ascii_lowercase(),
+                name: name.to_string(),
+                settings: Vec::new(),
+            });
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(OfeliaError::Syntax { line_no })?;
+        let section = sections
+            .last_mut()
+            .ok_or(OfeliaError::NoSection { line_no })?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+
+        section
+            .settings
+            .push((key.trim().to_ascii_lowercase(), value.to_string()));
+    }
+
+    Ok(sections)
+}
+
+/// Express a fixed interval as a cron schedule, if it evenly divides the
+/// minute, hour or day that it repeats within.
+fn interval_to_cron(period: Duration) -> Option<String> {
+    let secs = period.as_secs();
+
+    if period.subsec_nanos() != 0 || secs == 0 {
+        return None;
+    }
+
+    match secs {
+        1..60 if 60 % secs == 0 => Some(format!("*/{secs} * * * * *")),
+        60..3600 if secs % 60 == 0 && 3600 % secs == 0 => {
+            Some(format!("0 */{} * * * *", secs / 60))
+        }
+        3600..86400 if secs % 3600 == 0 && 86400 % secs == 0 => {
+            Some(format!("0 0 */{} * * *", secs / 3600))
+        }
+        86400 => Some(String::from("0 0 0 * * *")),
+        _ => None,
+    }
+}
+
+/// Convert an Ofelia schedule, which uses five or six fields with Sunday as
+/// day 0, into a docker-cron one.
+fn convert_schedule(spec: &str) -> Result<Schedule, String> {
+    let spec = spec.trim();
+
+    let converted = if let Some(period) = spec.strip_prefix("@every ") {
+        let period = humantime::parse_duration(period.trim())
+            .map_err(|error| format!("invalid interval: {error}"))?;
+
+        interval_to_cron(period)
+            .ok_or_else(|| format!("the interval {spec:?} can't be written as a cron schedule"))?
+    } else if spec == "@midnight" {
+        String::from("@daily")
+    } else if spec.starts_with('@') {
+        spec.to_string()
+    } else {
+        let mut fields: Vec<String> = spec.split_whitespace().map(String::from).collect();
+
+        if fields.len() == 5 {
+            fields.insert(0, String::from("0"));
+        }
+
+        if fields.len() != 6 {
+            return Err(format!("expected five or six fields in {spec:?}"));
+        }
+
+        fields[5] = shift_weekdays(&fields[5], 1);
+        fields.join(" ")
+    };
+
+    Schedule::from_str(&converted).map_err(|error| format!("invalid schedule {spec:?}: {error}"))
+}
+
+/// Ofelia settings that describe what docker-cron always does anyway.
+const HARMLESS_SETTINGS: &[&str] = &["no-overlap"];
+
+/// Translate an Ofelia or Chadburn INI config into crontab jobs.
+///
+/// Only `job-run` jobs with a `container` setting have an equivalent, as
+/// docker-cron starts existing containers. Other job types are skipped with a
+/// warning, as are settings that don't carry over. Fixed intervals become
+/// cron schedules when they evenly divide a minute, hour or day.
+pub fn from_ofelia(text: &str) -> Result<(Vec<ImportedJob>, Vec<ImportWarning>), OfeliaError> {
+    let mut jobs = Vec::new();
+    let mut warnings = Vec::new();
+
+    for section in parse_ini(text)? {
+        let job = section.name.clone();
+
+        match section.kind.as_str() {
+            "global" => {
+                if !section.settings.is_empty() {
+                    warnings.push(ImportWarning::Global);
+                }
+                continue;
+            }
+            "job-run" => {}
+            kind => {
+                warnings.push(ImportWarning::Skipped {
+                    job,
+                    reason: format!("{kind} jobs aren't supported"),
+                });
+                continue;
+            }
+        }
+
+        let mut schedule = None;
+        let mut container = None;
+        let mut ignored = Vec::new();
+
+        for (key, value) in &section.settings {
+            match key.as_str() {
+                "schedule" => schedule = Some(value.as_str()),
+                "container" => container = Some(value.clone()),
+                key if HARMLESS_SETTINGS.contains(&key) => {}
+                key => ignored.push(ImportWarning::Ignored {
+                    job: job.clone(),
+                    key: key.to_string(),
+                }),
+            }
+        }
+
+        let Some(container) = container else {
+            warnings.push(ImportWarning::Skipped {
+                job,
+                reason: String::from("it creates a new container rather than starting one"),
+            });
+            continue;
+        };
+
+        let schedule = match schedule.map(convert_schedule) {
+            Some(Ok(schedule)) => schedule,
+            Some(Err(reason)) => {
+                warnings.push(ImportWarning::Skipped { job, reason });
+                continue;
+            }
+            None => {
+                warnings.push(ImportWarning::Skipped {
+                    job,
+                    reason: String::from("it has no schedule"),
+                });
+                continue;
+            }
+        };
+
+        warnings.extend(ignored);
+        jobs.push(ImportedJob {
+            name: job,
+            cron_job: CronJob {
+                schedule: schedule.into(),
+                command: container,
+                timezone: None,
+                options: JobOptions::default(),
+                env: BTreeMap::new(),
+                disabled: false,
+                host: None,
+            },
+        });
+    }
+
+    Ok((jobs, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_ofelia() -> Result<(), anyhow::Error> {
+        let (jobs, warnings) = from_ofelia(
+            r#"
+                [global]
+                slack-webhook = https://hooks.slack.com/x
+
+                ; Weekdays at half past two
+                [job-run "backup"]
+                schedule = 30 2 * * 1-5
+                container = db-backup
+                no-overlap = true
+
+                [job-run "report"]
+                schedule = @every 15m
+                container = "report"
+                delete = false
+
+                [job-run "fresh"]
+                schedule = @hourly
+                image = alpine
+
+                [job-exec "flush"]
+                schedule = @daily
+                container = cache
+                command = flush
+            "#,
+        )?;
+
+        let lines: Vec<_> = jobs
+            .iter()
+            .map(|job| format!("{} {}", job.name, job.cron_job))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "backup 0 30 2 * * 2-6 db-backup",
+                "report 0 */15 * * * * report"
+            ]
+        );
+
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(warnings[0], ImportWarning::Global);
+        assert_eq!(
+            warnings[1],
+            ImportWarning::Ignored {
+                job: String::from("report"),
+                key: String::from("delete")
+            }
+        );
+        assert!(matches!(&warnings[2], ImportWarning::Skipped { job, .. } if job == "fresh"));
+        assert!(matches!(&warnings[3], ImportWarning::Skipped { job, .. } if job == "flush"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interval_to_cron() {
+        let convert = |secs| interval_to_cron(Duration::from_secs(secs));
+
+        assert_eq!(convert(10).as_deref(), Some("*/10 * * * * *"));
+        assert_eq!(convert(7200).as_deref(), Some("0 0 */2 * * *"));
+        assert_eq!(convert(86400).as_deref(), Some("0 0 0 * * *"));
+        assert_eq!(convert(45 * 60), None);
+        assert_eq!(convert(7), None);
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let Err(OfeliaError::Syntax { line_no: 2 }) = from_ofelia("[job-run \"x\"]\nnonsense")
+        else {
+            panic!()
+        };
+        let Err(OfeliaError::NoSection { line_no: 1 }) = from_ofelia("schedule = @daily") else {
+            panic!()
+        };
+    }
+}
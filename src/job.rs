@@ -0,0 +1,1181 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    timezone::Timezone,
+    trigger::{CronTrigger, Trigger, TriggerSpec, TriggerSpecError},
+};
+
+/// What docker-cron does to a container when a job fires.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Start an existing container and wait for it to exit.
+    Start(String),
+    /// Create a fresh container from an image, start it and wait for it to
+    /// exit.
+    Run(RunImage),
+    /// Run a command in a container that is already running, and wait for
+    /// it to exit.
+    Exec(ExecCommand),
+    /// Start the container of a Docker Compose service and wait for it to
+    /// exit.
+    Compose(ComposeService),
+    /// Run a shell command on the host, without Docker, and wait for it to
+    /// exit.
+    Local(String),
+    /// Stop, restart, pause or unpause a container, such as a service that
+    /// only needs to run during the day, without waiting for it to exit.
+    Control(ControlContainer),
+    /// Remove unused images, containers or volumes from the Docker host.
+    Prune(Prune),
+}
+
+impl Action {
+    /// Name of the container that this action operates on, or of the service
+    /// for a Compose action, whose container is only found when it runs.
+    /// Empty for a local command or a prune, which have no container.
+    pub fn container(&self) -> &str {
+        match self {
+            Action::Start(container) => container,
+            Action::Run(run) => &run.container,
+            Action::Exec(exec) => &exec.container,
+            Action::Compose(compose) => &compose.service,
+            Action::Local(_) | Action::Prune(_) => "",
+            Action::Control(control) => &control.container,
+        }
+    }
+
+    /// The container that must already exist for this action to run, unless
+    /// it creates its own or finds it when it runs.
+    pub fn existing_container(&self) -> Option<&str> {
+        match self {
+            Action::Start(container)
+            | Action::Exec(ExecCommand { container, .. })
+            | Action::Control(ControlContainer { container, .. }) => Some(container),
+            Action::Run(_) | Action::Compose(_) | Action::Local(_) | Action::Prune(_) => None,
+        }
+    }
+}
+
+/// Written as the command of a crontab entry.
+impl Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Start(container) => write!(f, "{container}"),
+            Action::Run(run) => write!(f, "run:{}{}", run.image, Args(&run.command)),
+            Action::Exec(exec) => write!(f, "exec:{}{}", exec.container, Args(&exec.command)),
+            Action::Compose(compose) => {
+                write!(f, "compose:{}/{}", compose.project, compose.service)
+            }
+            Action::Local(command) => write!(f, "local:{command}"),
+            Action::Control(control) => write!(f, "{}:{}", control.op, control.container),
+            Action::Prune(prune) => write!(f, "prune:{prune}"),
+        }
+    }
+}
+
+/// A change to make to a container's state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlOp {
+    Stop,
+    Restart,
+    Pause,
+    Unpause,
+}
+
+impl ControlOp {
+    pub const ALL: [ControlOp; 4] = [
+        ControlOp::Stop,
+        ControlOp::Restart,
+        ControlOp::Pause,
+        ControlOp::Unpause,
+    ];
+}
+
+/// Written as the prefix of a crontab command, before the colon.
+impl Display for ControlOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ControlOp::Stop => "stop",
+            ControlOp::Restart => "restart",
+            ControlOp::Pause => "pause",
+            ControlOp::Unpause => "unpause",
+        })
+    }
+}
+
+/// A container whose state a job changes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlContainer {
+    pub op: ControlOp,
+    pub container: String,
+}
+
+/// What kind of unused Docker objects to remove.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruneTarget {
+    Images,
+    Containers,
+    Volumes,
+}
+
+impl Display for PruneTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PruneTarget::Images => "images",
+            PruneTarget::Containers => "containers",
+            PruneTarget::Volumes => "volumes",
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid prune target {0:?}, expected images, containers or volumes")]
+pub struct InvalidPruneTargetError(String);
+
+impl FromStr for PruneTarget {
+    type Err = InvalidPruneTargetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "images" => Ok(PruneTarget::Images),
+            "containers" => Ok(PruneTarget::Containers),
+            "volumes" => Ok(PruneTarget::Volumes),
+            _ => Err(InvalidPruneTargetError(s.to_string())),
+        }
+    }
+}
+
+/// Unused Docker objects to remove, narrowed down by Docker's prune filters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Prune {
+    pub target: PruneTarget,
+    /// Remove every unused image rather than only dangling ones, or every
+    /// unused volume rather than only anonymous ones.
+    #[serde(default)]
+    pub all: bool,
+    /// Only remove objects created at least this long ago. Docker can't
+    /// filter volumes by age.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub until: Option<Duration>,
+    /// Only remove objects with these labels, each `KEY` or `KEY=VALUE`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Never remove objects with these labels.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_labels: Vec<String>,
+}
+
+impl Prune {
+    pub fn new(target: PruneTarget) -> Self {
+        Prune {
+            target,
+            all: false,
+            until: None,
+            labels: Vec::new(),
+            excluded_labels: Vec::new(),
+        }
+    }
+
+    /// The filters for Docker's prune endpoints, as for `docker image prune
+    /// --filter`, with `all` expressed as the filter that Docker takes for
+    /// it.
+    pub fn filters(&self) -> HashMap<&'static str, Vec<String>> {
+        let mut filters = HashMap::new();
+
+        if self.all {
+            match self.target {
+                PruneTarget::Images => filters.insert("dangling", vec![String::from("false")]),
+                PruneTarget::Volumes => filters.insert("all", vec![String::from("true")]),
+                PruneTarget::Containers => None,
+            };
+        }
+
+        if let Some(until) = self.until {
+            filters.insert("until", vec![format!("{}s", until.as_secs())]);
+        }
+
+        if !self.labels.is_empty() {
+            filters.insert("label", self.labels.clone());
+        }
+
+        if !self.excluded_labels.is_empty() {
+            filters.insert("label!", self.excluded_labels.clone());
+        }
+
+        filters
+    }
+}
+
+/// Written as in a crontab, after `prune:`, e.g. `images all until=24h`.
+impl Display for Prune {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.target)?;
+
+        if self.all {
+            write!(f, " all")?;
+        }
+
+        if let Some(until) = self.until {
+            write!(f, " until={}", humantime::format_duration(until))?;
+        }
+
+        for label in &self.labels {
+            write!(f, " label={label}")?;
+        }
+
+        for label in &self.excluded_labels {
+            write!(f, " label!={label}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid prune filter {0:?}")]
+pub struct InvalidPruneError(String);
+
+/// Parses what follows `prune:` in a crontab, a target and then any of
+/// `all`, `until=<duration>`, `label=<label>` and `label!=<label>`.
+impl FromStr for Prune {
+    type Err = InvalidPruneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let target = words.next().unwrap_or_default();
+        let mut prune = Prune::new(
+            target
+                .parse()
+                .map_err(|_| InvalidPruneError(target.to_string()))?,
+        );
+
+        for word in words {
+            let invalid = || InvalidPruneError(word.to_string());
+
+            match word.split_once('=') {
+                None if word == "all" && prune.target != PruneTarget::Containers => {
+                    prune.all = true
+                }
+                Some(("until", until)) if prune.target != PruneTarget::Volumes => {
+                    prune.until = Some(humantime::parse_duration(until).map_err(|_| invalid())?)
+                }
+                Some(("label", label)) if !label.is_empty() => prune.labels.push(label.to_string()),
+                Some(("label!", label)) if !label.is_empty() => {
+                    prune.excluded_labels.push(label.to_string())
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(prune)
+    }
+}
+
+/// Writes each argument of a command after a space.
+struct Args<'a>(&'a [String]);
+
+impl Display for Args<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|arg| write!(f, " {arg}"))
+    }
+}
+
+/// A container for each run to be created from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunImage {
+    pub image: String,
+    /// Replaces the image's command, unless empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// Name of the container to create. It replaces the previous run's
+    /// container, which is kept until then so that its logs can be read.
+    pub container: String,
+    /// Remove the container as soon as the run finishes, instead.
+    #[serde(default)]
+    pub auto_remove: bool,
+    /// When to pull the image before creating the container.
+    #[serde(default)]
+    pub pull: PullPolicy,
+    /// Host paths and volumes to mount into the container.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<Mount>,
+    #[serde(default, skip_serializing_if = "Limits::is_unlimited")]
+    pub limits: Limits,
+    /// Docker network to attach the container to, instead of the default
+    /// bridge network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+}
+
+impl RunImage {
+    /// Run `image` with its own command, in a container named after the
+    /// job.
+    pub fn new(image: impl Into<String>, job: &str) -> Self {
+        RunImage {
+            image: image.into(),
+            command: Vec::new(),
+            container: run_container_name(job),
+            auto_remove: false,
+            pull: PullPolicy::default(),
+            mounts: Vec::new(),
+            limits: Limits::default(),
+            network: None,
+        }
+    }
+
+    pub fn command(mut self, command: Vec<String>) -> Self {
+        self.command = command;
+        self
+    }
+
+    pub fn auto_remove(mut self, auto_remove: bool) -> Self {
+        self.auto_remove = auto_remove;
+        self
+    }
+
+    pub fn pull(mut self, pull: PullPolicy) -> Self {
+        self.pull = pull;
+        self
+    }
+
+    /// Mount a host path or volume into the container. May be repeated.
+    pub fn mount(mut self, mount: Mount) -> Self {
+        self.mounts.push(mount);
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn network(mut self, network: impl Into<String>) -> Self {
+        self.network = Some(network.into());
+        self
+    }
+}
+
+/// Resources that a container may use, as enforced by Docker.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    /// CPU time, in billionths of a CPU, e.g. 1.5 CPUs is 1,500,000,000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nano_cpus: Option<u64>,
+    /// Memory in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u64>,
+    /// How many processes and threads may run at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids: Option<u64>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Limits::default()
+    }
+}
+
+/// A host path or named volume to mount into a container, written as for
+/// `docker run -v`: `source:target`, optionally followed by `:ro` or `:rw`.
+/// A source starting with `/` is a host path, and any other source names a
+/// volume.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mount {
+    pub source: String,
+    pub target: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid mount {0:?}, expected source:target with an absolute target")]
+pub struct InvalidMountError(String);
+
+impl FromStr for Mount {
+    type Err = InvalidMountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidMountError(s.to_string());
+        let mut parts = s.split(':');
+        let (Some(source), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let read_only = match parts.next() {
+            None | Some("rw") => false,
+            Some("ro") => true,
+            Some(_) => return Err(invalid()),
+        };
+
+        // Docker allows the same characters in volume names as in container
+        // names.
+        let host_path = source.starts_with('/');
+        let volume = !source.is_empty()
+            && !source.starts_with(['.', '-'])
+            && source
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+
+        if !(host_path || volume) || !target.starts_with('/') || parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Mount {
+            source: source.to_string(),
+            target: target.to_string(),
+            read_only,
+        })
+    }
+}
+
+impl Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source, self.target)?;
+
+        if self.read_only {
+            write!(f, ":ro")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// When to pull a job's image from its registry before each run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullPolicy {
+    /// Always pull, so that a moving tag such as `latest` is kept up to date.
+    Always,
+    /// Only pull if the image isn't present yet.
+    #[default]
+    Missing,
+    /// Never pull. The run fails if the image isn't present.
+    Never,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown pull policy {0:?}, expected always, missing or never")]
+pub struct UnknownPullPolicyError(String);
+
+impl FromStr for PullPolicy {
+    type Err = UnknownPullPolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(PullPolicy::Always),
+            "missing" => Ok(PullPolicy::Missing),
+            "never" => Ok(PullPolicy::Never),
+            _ => Err(UnknownPullPolicyError(s.to_string())),
+        }
+    }
+}
+
+impl Display for PullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PullPolicy::Always => write!(f, "always"),
+            PullPolicy::Missing => write!(f, "missing"),
+            PullPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// A command to run in an existing container, like `docker exec`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecCommand {
+    pub container: String,
+    pub command: Vec<String>,
+}
+
+/// A service of a Docker Compose project. Compose replaces a service's
+/// container whenever its configuration changes, so the container is found
+/// by Compose's labels each time the job runs rather than by name.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposeService {
+    pub project: String,
+    pub service: String,
+}
+
+/// The name of the container created for a job's runs. Docker only allows
+/// letters, digits, `_`, `.` and `-` in names, so anything else becomes `-`.
+fn run_container_name(job: &str) -> String {
+    let job: String = job
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    format!("docker-cron-{job}")
+}
+
+/// What to do when a job falls due while its previous run is still going.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overlap {
+    /// Skip the new run, with a warning.
+    #[default]
+    Skip,
+    /// Run again as soon as the previous run finishes. At most one run is
+    /// queued at a time.
+    Queue,
+    /// Stop the previous run's container, then start it afresh.
+    Replace,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown overlap policy {0:?}, expected skip, queue or replace")]
+pub struct UnknownOverlapError(String);
+
+impl FromStr for Overlap {
+    type Err = UnknownOverlapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Overlap::Skip),
+            "queue" => Ok(Overlap::Queue),
+            "replace" => Ok(Overlap::Replace),
+            _ => Err(UnknownOverlapError(s.to_string())),
+        }
+    }
+}
+
+impl Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overlap::Skip => write!(f, "skip"),
+            Overlap::Queue => write!(f, "queue"),
+            Overlap::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+/// How to retry a run that failed to start or exited unsuccessfully, before
+/// giving up until the job is next due.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Retry {
+    /// How many times to retry, after the first attempt.
+    #[serde(default)]
+    pub attempts: u32,
+    /// How long to wait before the first retry. The wait doubles for each
+    /// retry after that.
+    #[serde(default = "default_backoff", with = "humantime_serde")]
+    pub backoff: Duration,
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 0,
+            backoff: default_backoff(),
+        }
+    }
+}
+
+impl Retry {
+    /// How long to wait before the given retry, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        // Too many doublings for a u32 waits as long as possible, unless
+        // there is no backoff to double.
+        match 2u32.checked_pow(retry.saturating_sub(1)) {
+            Some(factor) => self.backoff.saturating_mul(factor),
+            None if self.backoff.is_zero() => Duration::ZERO,
+            None => Duration::MAX,
+        }
+    }
+}
+
+/// A fully resolved job, ready to be handed to the scheduler.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub name: String,
+    pub schedule: TriggerSpec,
+    pub action: Action,
+    /// Name of the Docker host that the job's containers are on, out of
+    /// those the scheduler was given, or the default host if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timeout: Option<Duration>,
+    /// Count a run as done once its container's healthcheck reports it
+    /// healthy, failing if that takes longer than this, instead of waiting
+    /// for it to exit. For containers that run a service rather than a
+    /// batch job. The container is left running.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub healthy_within: Option<Duration>,
+    /// Containers to run to completion, in order, before the main container.
+    /// If any of them fails then the run is abandoned.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    /// Containers to run after the main container, whether it succeeded or
+    /// not.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    /// Container to run when the main container exits unsuccessfully, such
+    /// as to clean up or raise an alert.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
+    /// Environment variables to set in the main container, on top of those
+    /// it was created with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Replaces the main container's command, unless empty. Image and exec
+    /// jobs carry their command in their action instead.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// Time zone that a cron schedule is evaluated in, or UTC if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Timezone>,
+    #[serde(default)]
+    pub overlap: Overlap,
+    #[serde(default)]
+    pub retry: Retry,
+    /// Exit statuses of the main container or command that count as
+    /// success as well as zero, for tools that exit non-zero to mean
+    /// "nothing to do".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub success_codes: Vec<i64>,
+    /// Longest random delay to add to each run, so that many hosts running
+    /// the same job don't all start it at once. Overrides the scheduler's
+    /// splay.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub jitter: Option<Duration>,
+    /// Stop scheduling the job once it has succeeded this many times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runs: Option<u64>,
+    /// Run once as soon as the scheduler starts, as well as on schedule.
+    #[serde(default)]
+    pub run_on_start: bool,
+    /// When the job starts running. Earlier runs are skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    /// When the job stops running for good.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<DateTime<Utc>>,
+    /// Keep the job in the job set, where tooling can still see it, but
+    /// never schedule it.
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+impl Job {
+    /// Start building a job with the given name.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(name: impl Into<String>) -> JobBuilder {
+        JobBuilder {
+            name: name.into(),
+            schedule: None,
+            action: None,
+            host: None,
+            timeout: None,
+            healthy_within: None,
+            before: Vec::new(),
+            after: Vec::new(),
+            on_failure: None,
+            env: BTreeMap::new(),
+            command: Vec::new(),
+            timezone: None,
+            overlap: Overlap::default(),
+            retry: Retry::default(),
+            success_codes: Vec::new(),
+            jitter: None,
+            max_runs: None,
+            run_on_start: false,
+            not_before: None,
+            not_after: None,
+            disabled: false,
+        }
+    }
+
+    /// Create a fresh trigger that fires according to the job's schedule, in
+    /// the job's time zone.
+    pub fn trigger(&self) -> Box<dyn Trigger> {
+        match (&self.schedule, &self.timezone) {
+            (TriggerSpec::Cron(schedule), Some(timezone)) => {
+                Box::new(CronTrigger::new((**schedule).clone()).in_timezone(timezone.clone()))
+            }
+            (spec, _) => spec.build(),
+        }
+    }
+
+    /// Whether a run whose container or command exited with `status_code`
+    /// succeeded.
+    pub fn succeeds_with(&self, status_code: i64) -> bool {
+        status_code == 0 || self.success_codes.contains(&status_code)
+    }
+
+    /// Whether a run due at `at` falls within the job's start and end dates.
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        self.not_before.is_none_or(|not_before| at >= not_before)
+            && self.not_after.is_none_or(|not_after| at <= not_after)
+    }
+
+    /// The next time strictly after `after` that a cron job is due, or `None`
+    /// for jobs that don't run on a cron schedule or have passed their end
+    /// date.
+    pub fn next_cron_fire(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let TriggerSpec::Cron(schedule) = &self.schedule else {
+            return None;
+        };
+        let after = match self.not_before {
+            Some(not_before) => after.max(not_before - chrono::Duration::seconds(1)),
+            None => after,
+        };
+
+        self.timezone
+            .as_ref()
+            .unwrap_or(&Timezone::Utc)
+            .next_after(schedule, after)
+            .filter(|next| self.is_active_at(*next))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum JobBuildError {
+    #[error("Job {name} has no schedule")]
+    MissingSchedule { name: String },
+    #[error("Job {name} has no action")]
+    MissingAction { name: String },
+    #[error("Job {name} has an invalid schedule")]
+    InvalidSchedule {
+        name: String,
+        source: TriggerSpecError,
+    },
+}
+
+/// Fluent builder for [`Job`], e.g.
+///
+/// ```
+/// use docker_cron::job::{Action, Job};
+/// use std::time::Duration;
+///
+/// let job = Job::new("backup")
+///     .schedule("0 0 3 * * *")
+///     .action(Action::Start("db-backup".into()))
+///     .timeout(Duration::from_secs(3600))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(job.action.container(), "db-backup");
+/// ```
+#[derive(Clone, Debug)]
+pub struct JobBuilder {
+    name: String,
+    schedule: Option<String>,
+    action: Option<Action>,
+    host: Option<String>,
+    timeout: Option<Duration>,
+    healthy_within: Option<Duration>,
+    before: Vec<String>,
+    after: Vec<String>,
+    on_failure: Option<String>,
+    env: BTreeMap<String, String>,
+    command: Vec<String>,
+    timezone: Option<Timezone>,
+    overlap: Overlap,
+    retry: Retry,
+    success_codes: Vec<i64>,
+    jitter: Option<Duration>,
+    max_runs: Option<u64>,
+    run_on_start: bool,
+    not_before: Option<DateTime<Utc>>,
+    not_after: Option<DateTime<Utc>>,
+    disabled: bool,
+}
+
+impl JobBuilder {
+    /// Six-field cron expression, @alias, `@every <duration>` or
+    /// `@file <path>`. Parsed when the job is built.
+    pub fn schedule(mut self, spec: impl Into<String>) -> Self {
+        self.schedule = Some(spec.into());
+        self
+    }
+
+    pub fn action(mut self, action: Action) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// Run the job's containers on the named Docker host instead of the
+    /// default one.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Fail a run that is still going after this long, stopping its
+    /// container or killing its local command. Exec commands can't be
+    /// stopped, so they are left to finish.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Count a run as done once its container becomes healthy, if it does
+    /// within `deadline`, rather than when it exits.
+    pub fn healthy_within(mut self, deadline: Duration) -> Self {
+        self.healthy_within = Some(deadline);
+        self
+    }
+
+    /// Run a hook container before the main container. May be repeated.
+    pub fn before(mut self, container: impl Into<String>) -> Self {
+        self.before.push(container.into());
+        self
+    }
+
+    /// Run a hook container after the main container. May be repeated.
+    pub fn after(mut self, container: impl Into<String>) -> Self {
+        self.after.push(container.into());
+        self
+    }
+
+    /// Run a container when the main container exits unsuccessfully.
+    pub fn on_failure(mut self, container: impl Into<String>) -> Self {
+        self.on_failure = Some(container.into());
+        self
+    }
+
+    /// Set an environment variable in the main container. May be repeated.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Run the main container with this command instead of its own.
+    pub fn command(mut self, command: Vec<String>) -> Self {
+        self.command = command;
+        self
+    }
+
+    /// Evaluate a cron schedule in this time zone rather than UTC.
+    pub fn timezone(mut self, timezone: Timezone) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    /// What to do if the job falls due while it is still running.
+    pub fn overlap(mut self, overlap: Overlap) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Retry failed runs this many times, waiting `backoff` before the first
+    /// retry and twice as long before each one after it.
+    pub fn retry(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.retry = Retry { attempts, backoff };
+        self
+    }
+
+    /// Count these exit statuses as success, as well as zero.
+    pub fn success_codes(mut self, codes: Vec<i64>) -> Self {
+        self.success_codes = codes;
+        self
+    }
+
+    /// Delay each run by a random time less than `max`.
+    pub fn jitter(mut self, max: Duration) -> Self {
+        self.jitter = Some(max);
+        self
+    }
+
+    /// Stop scheduling the job once it has succeeded `runs` times.
+    pub fn max_runs(mut self, runs: u64) -> Self {
+        self.max_runs = Some(runs);
+        self
+    }
+
+    /// Also run the job once when the scheduler starts.
+    pub fn run_on_start(mut self, run: bool) -> Self {
+        self.run_on_start = run;
+        self
+    }
+
+    /// Skip runs that fall due before this time.
+    pub fn not_before(mut self, start: DateTime<Utc>) -> Self {
+        self.not_before = Some(start);
+        self
+    }
+
+    /// Stop running the job after this time.
+    pub fn not_after(mut self, end: DateTime<Utc>) -> Self {
+        self.not_after = Some(end);
+        self
+    }
+
+    /// Never schedule the job.
+    pub fn disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    pub fn build(self) -> Result<Job, JobBuildError> {
+        let Some(spec) = self.schedule else {
+            return Err(JobBuildError::MissingSchedule { name: self.name });
+        };
+
+        let Some(action) = self.action else {
+            return Err(JobBuildError::MissingAction { name: self.name });
+        };
+
+        let schedule = match TriggerSpec::from_str(&spec) {
+            Ok(schedule) => schedule,
+            Err(source) => {
+                return Err(JobBuildError::InvalidSchedule {
+                    name: self.name,
+                    source,
+                })
+            }
+        };
+
+        Ok(Job {
+            name: self.name,
+            schedule,
+            action,
+            host: self.host,
+            timeout: self.timeout,
+            healthy_within: self.healthy_within,
+            before: self.before,
+            after: self.after,
+            on_failure: self.on_failure,
+            env: self.env,
+            command: self.command,
+            timezone: self.timezone,
+            overlap: self.overlap,
+            retry: self.retry,
+            success_codes: self.success_codes,
+            jitter: self.jitter,
+            max_runs: self.max_runs,
+            run_on_start: self.run_on_start,
+            not_before: self.not_before,
+            not_after: self.not_after,
+            disabled: self.disabled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let job = Job::new("backup")
+            .schedule("0 0 3 * * *")
+            .action(Action::Start(String::from("db-backup")))
+            .timeout(Duration::from_secs(60))
+            .before("quiesce")
+            .after("unquiesce")
+            .build()
+            .unwrap();
+
+        assert_eq!(job.name, "backup");
+        assert_eq!(job.schedule.to_string(), "0 0 3 * * *");
+        assert_eq!(job.action, Action::Start(String::from("db-backup")));
+        assert_eq!(job.timeout, Some(Duration::from_secs(60)));
+        assert_eq!(job.before, ["quiesce"]);
+        assert_eq!(job.after, ["unquiesce"]);
+        assert!(job.succeeds_with(0));
+        assert!(!job.succeeds_with(1));
+    }
+
+    #[test]
+    fn test_success_codes() -> Result<(), anyhow::Error> {
+        let job = Job::new("sync")
+            .schedule("@hourly")
+            .action(Action::Start(String::from("sync")))
+            .success_codes(vec![1])
+            .build()?;
+
+        assert!(job.succeeds_with(0));
+        assert!(job.succeeds_with(1));
+        assert!(!job.succeeds_with(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde() -> Result<(), anyhow::Error> {
+        let job: Job = serde_json::from_str(
+            r#"{"name":"backup","schedule":"@daily","action":{"start":"db-backup"},"timeout":"5m"}"#,
+        )?;
+
+        assert_eq!(job.schedule.to_string(), "@daily");
+        assert_eq!(job.action, Action::Start(String::from("db-backup")));
+        assert_eq!(job.timeout, Some(Duration::from_secs(300)));
+
+        let json = serde_json::to_string(&job)?;
+        let job: Job = serde_json::from_str(&json)?;
+
+        assert_eq!(job.name, "backup");
+        assert_eq!(job.schedule.to_string(), "@daily");
+        assert!(job.timezone.is_none());
+        assert_eq!(job.retry, Retry::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune() -> Result<(), anyhow::Error> {
+        let prune = Prune::from_str("images  all until=24h label=env=dev label!=keep")?;
+
+        assert_eq!(prune.target, PruneTarget::Images);
+        assert!(prune.all);
+        assert_eq!(prune.until, Some(Duration::from_secs(86400)));
+        assert_eq!(
+            prune.to_string(),
+            "images all until=1day label=env=dev label!=keep"
+        );
+        assert_eq!(
+            prune.filters(),
+            HashMap::from([
+                ("dangling", vec![String::from("false")]),
+                ("until", vec![String::from("86400s")]),
+                ("label", vec![String::from("env=dev")]),
+                ("label!", vec![String::from("keep")]),
+            ])
+        );
+        assert_eq!(Prune::from_str(&prune.to_string())?, prune);
+
+        for s in [
+            "networks",
+            "volumes until=1h",
+            "containers all",
+            "images label=",
+        ] {
+            assert!(Prune::from_str(s).is_err(), "{s}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount() -> Result<(), anyhow::Error> {
+        let mount = Mount::from_str("/srv/data:/data:ro")?;
+        assert_eq!(
+            mount,
+            Mount {
+                source: String::from("/srv/data"),
+                target: String::from("/data"),
+                read_only: true,
+            }
+        );
+        assert_eq!(mount.to_string(), "/srv/data:/data:ro");
+        assert_eq!(
+            Mount::from_str("backups:/backups:rw")?.to_string(),
+            "backups:/backups"
+        );
+
+        for invalid in [
+            "/data",
+            "data:relative",
+            "./data:/data",
+            ":/data",
+            "a:/b:rx",
+            "a:/b:ro:x",
+        ] {
+            assert!(Mount::from_str(invalid).is_err(), "{invalid}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let retry = Retry {
+            attempts: 3,
+            backoff: Duration::from_secs(5),
+        };
+
+        assert_eq!(retry.delay(1), Duration::from_secs(5));
+        assert_eq!(retry.delay(3), Duration::from_secs(20));
+        assert_eq!(retry.delay(100), Duration::MAX);
+    }
+
+    #[test]
+    fn test_next_cron_fire() -> Result<(), anyhow::Error> {
+        let after = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")?.with_timezone(&Utc);
+        let job = Job::new("backup")
+            .schedule("0 0 3 * * *")
+            .action(Action::Start(String::from("db-backup")))
+            .timezone(Timezone::from_str("-05:00")?)
+            .build()?;
+
+        assert_eq!(
+            job.next_cron_fire(after).map(|t| t.to_rfc3339()),
+            Some(String::from("2024-01-01T08:00:00+00:00"))
+        );
+
+        let job = Job {
+            not_before: Some(DateTime::parse_from_rfc3339("2024-02-01T08:00:00Z")?.into()),
+            not_after: Some(DateTime::parse_from_rfc3339("2024-02-02T06:00:00Z")?.into()),
+            ..job
+        };
+
+        assert_eq!(
+            job.next_cron_fire(after).map(|t| t.to_rfc3339()),
+            Some(String::from("2024-02-01T08:00:00+00:00"))
+        );
+        assert_eq!(job.next_cron_fire(job.not_before.unwrap()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let Err(JobBuildError::MissingSchedule { .. }) = Job::new("a")
+            .action(Action::Start(String::from("a")))
+            .build()
+        else {
+            panic!()
+        };
+
+        let Err(JobBuildError::MissingAction { .. }) = Job::new("a").schedule("@daily").build()
+        else {
+            panic!()
+        };
+
+        let Err(JobBuildError::InvalidSchedule { .. }) = Job::new("a")
+            .schedule("bogus")
+            .action(Action::Start(String::from("a")))
+            .build()
+        else {
+            panic!()
+        };
+    }
+}
@@ -0,0 +1,376 @@
+//! Jobs described in YAML, as an alternative to a crontab for jobs with more
+//! options than fit comfortably on one line, e.g.
+//!
+//! ```yaml
+//! jobs:
+//!   - name: nightly-backup
+//!     schedule: "0 0 3 * * *"
+//!     container: db-backup
+//!     timezone: Europe/London
+//!     timeout: 30m
+//!     retries: 3
+//!     env:
+//!       BUCKET: s3://backups
+//! ```
+//!
+//! Jobs take the same options as crontab entries, under the same names, and
+//! load as the [`CronJob`] that the equivalent crontab entry would.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    crontab::{from_five_field, parse_bytes, parse_cpus, parse_date, CronJob, JobOptions},
+    job::{Limits, Mount, Overlap, Prune, PullPolicy, Retry},
+    timezone::Timezone,
+    trigger::{TriggerSpec, TriggerSpecError},
+};
+
+#[derive(Debug, Error)]
+pub enum JobFileError {
+    #[error("Error reading job file {path}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[cfg(feature = "yaml")]
+    #[error("Job file {path} is not valid YAML")]
+    Yaml {
+        path: PathBuf,
+        source: serde_yaml_ng::Error,
+    },
+    #[error("Job {job} has an invalid schedule")]
+    InvalidSchedule {
+        job: String,
+        source: TriggerSpecError,
+    },
+    #[error("Job {job} has an invalid {option}")]
+    InvalidOption { job: String, option: &'static str },
+    #[error("Job name {0:?} is already taken")]
+    DuplicateName(String),
+}
+
+/// One job, as written in a job file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobEntry {
+    /// A crontab schedule, in five or six fields, or an `@` alias.
+    pub schedule: String,
+    pub container: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<Timezone>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub overlap: Overlap,
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default, with = "humantime_serde")]
+    pub backoff: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub jitter: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub healthy_within: Option<Duration>,
+    #[serde(default)]
+    pub max_runs: Option<u64>,
+    #[serde(default)]
+    pub run_on_start: bool,
+    #[serde(default)]
+    pub on_failure: Option<String>,
+    /// An RFC 3339 timestamp, or a date meaning midnight UTC.
+    #[serde(default)]
+    pub not_before: Option<String>,
+    #[serde(default)]
+    pub not_after: Option<String>,
+    #[serde(default)]
+    pub disabled: bool,
+    /// When to pull the image of a `run:` job.
+    #[serde(default)]
+    pub pull: Option<PullPolicy>,
+    #[serde(default)]
+    pub auto_remove: bool,
+    /// Mounts for a `run:` job, each written as for `docker run -v`.
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    /// A size in bytes, optionally followed by `k`, `m` or `g`.
+    #[serde(default)]
+    pub memory: Option<String>,
+    #[serde(default)]
+    pub pids_limit: Option<u64>,
+    #[serde(default)]
+    pub network: Option<String>,
+    /// Exit statuses that count as success, as well as zero.
+    #[serde(default)]
+    pub success_codes: Vec<i64>,
+    /// Named Docker host to run on, instead of the default one.
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+impl TryFrom<JobEntry> for CronJob {
+    type Error = JobFileError;
+
+    fn try_from(entry: JobEntry) -> Result<Self, Self::Error> {
+        let job = entry.name.as_ref().unwrap_or(&entry.container).clone();
+        let spec = entry.schedule.trim();
+        let spec = if !spec.starts_with('@') && spec.split_whitespace().count() == 5 {
+            from_five_field(spec)
+        } else {
+            spec.to_string()
+        };
+        let schedule =
+            TriggerSpec::from_str(&spec).map_err(|source| JobFileError::InvalidSchedule {
+                job: job.clone(),
+                source,
+            })?;
+        let date = |date: Option<String>, option| {
+            date.map(|date| {
+                parse_date(&date).ok_or_else(|| JobFileError::InvalidOption {
+                    job: job.clone(),
+                    option,
+                })
+            })
+            .transpose()
+        };
+
+        if !entry.container.starts_with("run:") {
+            let image_options = [
+                ("pull", entry.pull.is_some()),
+                ("auto_remove", entry.auto_remove),
+                ("volumes", !entry.volumes.is_empty()),
+                ("network", entry.network.is_some()),
+            ];
+
+            if let Some((option, _)) = image_options.into_iter().find(|&(_, set)| set) {
+                return Err(JobFileError::InvalidOption {
+                    job: job.clone(),
+                    option,
+                });
+            }
+        }
+
+        // Local commands don't run on any Docker host.
+        if entry.host.as_ref().is_some_and(|host| {
+            host.is_empty()
+                || host.contains(char::is_whitespace)
+                || entry.container.starts_with("local:")
+        }) {
+            return Err(JobFileError::InvalidOption {
+                job: job.clone(),
+                option: "host",
+            });
+        }
+
+        if let Some(Err(_)) = entry.container.strip_prefix("prune:").map(Prune::from_str) {
+            return Err(JobFileError::InvalidOption {
+                job: job.clone(),
+                option: "container",
+            });
+        }
+
+        let volumes = entry
+            .volumes
+            .iter()
+            .map(|volume| Mount::from_str(volume))
+            .collect::<Result<_, _>>()
+            .map_err(|_| JobFileError::InvalidOption {
+                job: job.clone(),
+                option: "volumes",
+            })?;
+
+        let invalid = |option| JobFileError::InvalidOption {
+            job: job.clone(),
+            option,
+        };
+        let limits = Limits {
+            nano_cpus: entry
+                .cpus
+                .map(|cpus| parse_cpus(&cpus.to_string()).ok_or_else(|| invalid("cpus")))
+                .transpose()?,
+            memory: entry
+                .memory
+                .map(|memory| parse_bytes(&memory).ok_or_else(|| invalid("memory")))
+                .transpose()?,
+            pids: match entry.pids_limit {
+                Some(0) => return Err(invalid("pids_limit")),
+                pids => pids,
+            },
+        };
+
+        Ok(CronJob {
+            schedule,
+            command: entry.container,
+            timezone: entry.timezone,
+            options: JobOptions {
+                name: entry.name,
+                overlap: entry.overlap,
+                retry: Retry {
+                    attempts: entry.retries,
+                    backoff: entry.backoff.unwrap_or(Retry::default().backoff),
+                },
+                timeout: entry.timeout,
+                jitter: entry.jitter,
+                healthy_within: entry.healthy_within,
+                max_runs: entry.max_runs,
+                run_on_start: entry.run_on_start,
+                on_failure: entry.on_failure,
+                not_before: date(entry.not_before, "not_before")?,
+                not_after: date(entry.not_after, "not_after")?,
+                pull: entry.pull,
+                auto_remove: entry.auto_remove,
+                env: BTreeMap::new(),
+                volumes,
+                limits,
+                network: entry.network,
+                success_codes: entry.success_codes,
+            },
+            env: entry.env,
+            disabled: entry.disabled,
+            host: entry.host,
+        })
+    }
+}
+
+/// Convert job file entries into jobs, checking that no two share a name.
+pub fn to_cron_jobs(entries: Vec<JobEntry>) -> Result<Vec<CronJob>, JobFileError> {
+    let mut jobs: Vec<CronJob> = Vec::new();
+
+    for entry in entries {
+        let job = CronJob::try_from(entry)?;
+
+        if let Some(name) = &job.options.name {
+            if jobs
+                .iter()
+                .any(|other| other.options.name.as_ref() == Some(name))
+            {
+                return Err(JobFileError::DuplicateName(name.clone()));
+            }
+        }
+
+        jobs.push(job);
+    }
+
+    Ok(jobs)
+}
+
+#[cfg(feature = "yaml")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct YamlFile {
+    #[serde(default)]
+    jobs: Vec<JobEntry>,
+}
+
+/// Parse the text of a YAML job file.
+#[cfg(feature = "yaml")]
+pub fn read_yaml(text: &str, path: &Path) -> Result<Vec<CronJob>, JobFileError> {
+    let file: YamlFile = serde_yaml_ng::from_str(text).map_err(|source| JobFileError::Yaml {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    to_cron_jobs(file.jobs)
+}
+
+#[cfg(feature = "yaml")]
+pub fn load_yaml(path: &Path) -> Result<Vec<CronJob>, JobFileError> {
+    let text = std::fs::read_to_string(path).map_err(|source| JobFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    read_yaml(&text, path)
+}
+
+/// Whether a path names a YAML job file rather than a crontab.
+pub fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml")
+}
+
+#[cfg(all(test, feature = "yaml"))]
+mod tests {
+    use crate::job::Job;
+
+    use super::*;
+
+    #[test]
+    fn test_read_yaml() -> Result<(), anyhow::Error> {
+        let jobs = read_yaml(
+            concat!(
+                "jobs:\n",
+                "  - name: nightly-backup\n",
+                "    schedule: \"0 3 * * *\"\n",
+                "    container: db-backup\n",
+                "    timeout: 30m\n",
+                "    retries: 3\n",
+                "    not_before: 2024-06-01\n",
+                "    env:\n",
+                "      BUCKET: s3://backups\n",
+                "  - schedule: \"@after nightly-backup\"\n",
+                "    container: upload\n",
+                "    overlap: queue\n",
+            ),
+            Path::new("jobs.yaml"),
+        )?;
+
+        assert_eq!(
+            jobs[0].to_string(),
+            "0 0 3 * * * [name=nightly-backup,retries=3,timeout=30m,\
+                not_before=2024-06-01T00:00:00Z] db-backup"
+        );
+        assert_eq!(jobs[0].env["BUCKET"], "s3://backups");
+        assert_eq!(
+            jobs[1].to_string(),
+            "@after nightly-backup [overlap=queue] upload"
+        );
+        assert_eq!(Job::from(jobs[1].clone()).name, "upload");
+
+        let Err(JobFileError::Yaml { .. }) = read_yaml(
+            "jobs:\n  - schedule: \"@daily\"\n    container: a\n    retires: 3\n",
+            Path::new("jobs.yaml"),
+        ) else {
+            panic!()
+        };
+
+        let Err(JobFileError::InvalidOption {
+            option: "not_after",
+            ..
+        }) = read_yaml(
+            "jobs:\n  - schedule: \"@daily\"\n    container: a\n    not_after: June\n",
+            Path::new("jobs.yaml"),
+        )
+        else {
+            panic!()
+        };
+
+        let Err(JobFileError::InvalidOption {
+            option: "container",
+            ..
+        }) = read_yaml(
+            "jobs:\n  - schedule: \"@daily\"\n    container: prune:networks\n",
+            Path::new("jobs.yaml"),
+        )
+        else {
+            panic!()
+        };
+
+        assert!(is_yaml(Path::new("/etc/docker-cron/jobs.yml")));
+        assert!(!is_yaml(Path::new("/etc/crontab")));
+
+        Ok(())
+    }
+}
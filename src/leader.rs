@@ -0,0 +1,242 @@
+//! Leader election between docker-cron replicas through a lease file on a
+//! shared volume, so that only one replica runs jobs at a time.
+//!
+//! The leader rewrites the lease every third of its time-to-live. A standby
+//! replica takes over once the lease has gone stale, i.e. once the leader has
+//! failed to renew it for a whole TTL. Replicas only read and rewrite the
+//! lease while holding an advisory lock on a file next to it, so that two of
+//! them can't both find it stale and take it. The operating system releases
+//! the lock when its holder dies, and NFS supports it through its lock
+//! manager.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use fs4::fs_std::FileExt;
+use serde::{Deserialize, Serialize};
+use tokio::{task::spawn_blocking, time::sleep};
+use tracing::{info, warn};
+
+use crate::{
+    job::Job,
+    policy::{RunPolicy, Verdict},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    holder: String,
+    expires: DateTime<Utc>,
+}
+
+/// Exclusive access to the lease, released when dropped.
+struct LeaseLock {
+    _file: File,
+}
+
+impl LeaseLock {
+    /// Take the lock at `path`, or return `None` if another replica holds it.
+    /// The file itself is never removed, as a replica could otherwise lock
+    /// one that another had already replaced.
+    fn try_lock(path: &Path) -> std::io::Result<Option<LeaseLock>> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        Ok(file
+            .try_lock_exclusive()?
+            .then_some(LeaseLock { _file: file }))
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+
+    path.with_file_name(name)
+}
+
+pub struct LeaderLease {
+    path: PathBuf,
+    id: String,
+    ttl: Duration,
+    is_leader: AtomicBool,
+}
+
+impl LeaderLease {
+    /// `id` must be unique among the replicas sharing the lease file.
+    pub fn new(path: PathBuf, id: String, ttl: Duration) -> Arc<Self> {
+        Arc::new(LeaderLease {
+            path,
+            id,
+            ttl,
+            is_leader: AtomicBool::new(false),
+        })
+    }
+
+    /// A reasonably unique replica ID: the hostname (which is the container ID
+    /// under Docker) plus our PID.
+    pub fn default_id() -> String {
+        let host = fs::read_to_string("/etc/hostname")
+            .map(|name| name.trim().to_string())
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .unwrap_or_else(|_| String::from("unknown"));
+
+        format!("{host}-{}", std::process::id())
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Relaxed)
+    }
+
+    fn read_lease(&self) -> Option<Lease> {
+        let bytes = fs::read(&self.path).ok()?;
+
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn try_acquire(&self) -> std::io::Result<bool> {
+        let now = Utc::now();
+        let lease = self.read_lease();
+        let held = lease
+            .as_ref()
+            .is_some_and(|lease| lease.holder == self.id && lease.expires > now);
+
+        // While another replica has the lock, whoever holds the lease now
+        // keeps it until the next attempt.
+
+        let Some(_lock) = LeaseLock::try_lock(&sibling(&self.path, ".lock"))? else {
+            return Ok(held);
+        };
+
+        // Read the lease again, since it may have changed before we got the
+        // lock.
+
+        if let Some(lease) = self.read_lease() {
+            if lease.holder != self.id && lease.expires > now {
+                return Ok(false);
+            }
+        }
+
+        let lease = Lease {
+            holder: self.id.clone(),
+            expires: now + self.ttl,
+        };
+        let tmp_path = sibling(&self.path, &format!(".{}.tmp", self.id));
+
+        fs::write(&tmp_path, serde_json::to_vec(&lease)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(true)
+    }
+
+    /// Keep trying to acquire or renew the lease until the task is dropped.
+    /// The lease is read and written on the blocking thread pool, since the
+    /// shared volume may be slow to answer.
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            let lease = self.clone();
+            let attempt = spawn_blocking(move || lease.try_acquire())
+                .await
+                .expect("leader lease update panicked");
+
+            let leader = match attempt {
+                Ok(leader) => leader,
+                Err(error) => {
+                    warn!(error = %error, path = %self.path.display(), "Failed to update leader lease");
+                    false
+                }
+            };
+
+            if leader != self.is_leader.swap(leader, Ordering::Relaxed) {
+                if leader {
+                    info!(id = self.id, "Became leader, running jobs");
+                } else {
+                    info!(id = self.id, "Lost leadership, standing by");
+                }
+            }
+
+            sleep(self.ttl / 3).await;
+        }
+    }
+}
+
+#[async_trait]
+impl RunPolicy for LeaderLease {
+    async fn check(&self, _job: &Job, _due: DateTime<Utc>) -> Verdict {
+        if self.is_leader() {
+            Verdict::allow()
+        } else {
+            Verdict::veto("this replica is not the leader")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_lease_lock() -> Result<(), anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("docker-cron-{}.lock", std::process::id()));
+        let lock = LeaseLock::try_lock(&path)?;
+
+        assert!(lock.is_some());
+        assert!(LeaseLock::try_lock(&path)?.is_none());
+
+        drop(lock);
+        assert!(LeaseLock::try_lock(&path)?.is_some());
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_racing_replicas() -> Result<(), anyhow::Error> {
+        let dir = std::env::temp_dir().join(format!("docker-cron-leader-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join("lease.json");
+        let ttl = Duration::from_secs(30);
+        let leases = ["a", "b"].map(|id| LeaderLease::new(path.clone(), id.to_string(), ttl));
+
+        // Replicas that find the lease free at the same moment must not both
+        // take it.
+
+        for _ in 0..100 {
+            let _ = fs::remove_file(&path);
+            let barrier = Barrier::new(leases.len());
+            let leaders = thread::scope(|scope| {
+                let attempts = leases.each_ref().map(|lease| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        lease.try_acquire()
+                    })
+                });
+
+                attempts.map(|attempt| attempt.join().unwrap())
+            });
+
+            let leaders: Vec<bool> = leaders.into_iter().collect::<Result<_, _>>()?;
+            assert_eq!(leaders.iter().filter(|leader| **leader).count(), 1);
+        }
+
+        // The lock file stays behind, but nothing holds it.
+        assert!(LeaseLock::try_lock(&sibling(&path, ".lock"))?.is_some());
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+//! Start Docker containers on a cron-compatible schedule.
+//!
+//! The `docker-cron` binary is a thin wrapper around this library, which can
+//! also be embedded into other programs that want to schedule containers.
+
+pub mod allowlist;
+pub mod blackout;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod check;
+pub mod clock;
+#[cfg(feature = "daemon")]
+pub mod config;
+pub mod crontab;
+#[cfg(feature = "discovery")]
+pub mod discovery;
+pub mod docker;
+pub mod event;
+pub mod executor;
+#[cfg(feature = "export")]
+pub mod export;
+#[cfg(feature = "import")]
+pub mod import;
+pub mod job;
+pub mod jobfile;
+pub mod leader;
+pub mod local;
+pub mod notify;
+pub mod policy;
+pub mod random;
+#[cfg(feature = "redis")]
+pub mod redis_lock;
+pub mod scheduler;
+#[cfg(feature = "daemon")]
+pub mod script_hook;
+pub mod shard;
+#[cfg(feature = "simulate")]
+pub mod simulate;
+#[cfg(unix)]
+pub mod ssh;
+pub mod state;
+#[cfg(any(test, feature = "test-harness"))]
+pub mod testing;
+pub mod timezone;
+pub mod trigger;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "watch")]
+pub mod watch;
@@ -0,0 +1,48 @@
+//! Running jobs as shell commands on the host instead of in containers, for
+//! hosts that mix the two and for trying out crontabs without Docker.
+
+use std::{collections::BTreeMap, process::Stdio};
+
+use tokio::process::Command;
+
+use crate::scheduler::RunError;
+
+/// Run `command` with `sh -c`, with `env` set on top of docker-cron's own
+/// environment, and wait for it to exit. The process is killed if the
+/// returned future is dropped, such as when the run times out.
+pub async fn run_local(command: &str, env: &BTreeMap<String, String>) -> Result<(), RunError> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(RunError::Local)?;
+
+    if status.success() {
+        return Ok(());
+    }
+
+    // A process killed by a signal has no exit status.
+    Err(RunError::Exit {
+        status_code: status.code().map_or(-1, i64::from),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_local() {
+        let env = BTreeMap::from([(String::from("GREETING"), String::from("hello"))]);
+
+        assert!(run_local("test \"$GREETING\" = hello", &env).await.is_ok());
+        assert!(matches!(
+            run_local("exit 3", &env).await,
+            Err(RunError::Exit { status_code: 3 })
+        ));
+    }
+}
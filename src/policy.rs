@@ -0,0 +1,46 @@
+//! Policies that are consulted before every run and can veto it.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::job::Job;
+
+/// The outcome of checking a run against a policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Verdict {
+    /// If set, the run is skipped and this reason is logged.
+    #[serde(default)]
+    pub veto: Option<String>,
+    /// Extra key/value pairs that are logged alongside the run.
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Verdict {
+    pub fn allow() -> Self {
+        Verdict::default()
+    }
+
+    pub fn veto(reason: impl Into<String>) -> Self {
+        Verdict {
+            veto: Some(reason.into()),
+            annotations: BTreeMap::new(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait RunPolicy: Send + Sync {
+    /// Decide whether a job that fell due at `due` may run now.
+    async fn check(&self, job: &Job, due: DateTime<Utc>) -> Verdict;
+}
+
+#[async_trait]
+impl<T: RunPolicy + ?Sized> RunPolicy for Arc<T> {
+    async fn check(&self, job: &Job, due: DateTime<Utc>) -> Verdict {
+        (**self).check(job, due).await
+    }
+}
@@ -0,0 +1,126 @@
+//! Reproducible randomness for jitter and similar features.
+//!
+//! Every job draws from its own generator, derived from a scheduler-wide
+//! seed and the job's name. Runs are therefore reproducible for a given seed
+//! no matter how job tasks happen to interleave.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::shard::fnv1a;
+
+/// A small, fast SplitMix64 generator. Not suitable for cryptography.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// The generator used for the job named `job` under `seed`.
+    pub fn for_job(seed: u64, job: &str) -> Self {
+        Rng::new(seed ^ fnv1a(job.as_bytes()))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed value in `0..bound`, or zero if `bound` is zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            return 0;
+        }
+
+        // Multiply-shift rather than modulo, to avoid favouring small values.
+        ((u128::from(self.next_u64()) * u128::from(bound)) >> 64) as u64
+    }
+
+    /// A uniformly distributed duration in `0..max`, to millisecond precision.
+    pub fn duration_below(&mut self, max: Duration) -> Duration {
+        let millis = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+
+        Duration::from_millis(self.below(millis))
+    }
+}
+
+/// A seed that differs between runs, for when none was asked for.
+pub fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+    Rng::new(nanos ^ u64::from(std::process::id())).next_u64()
+}
+
+/// A random version 4 UUID, such as to identify a run. Unlike the other
+/// randomness here it doesn't depend on the seed, since ids from different
+/// processes mustn't repeat.
+pub fn uuid_v4() -> String {
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+
+    let calls = CALLS.fetch_add(1, Ordering::Relaxed);
+    let mut rng = Rng::new(random_seed() ^ calls.wrapping_mul(0x9e3779b97f4a7c15));
+
+    // The version is 4 and the variant is 0b10.
+    let high = (rng.next_u64() & !0xf000) | 0x4000;
+    let low = (rng.next_u64() >> 2) | (1 << 63);
+
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        // Reference values for SplitMix64 seeded with zero.
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220a8397b1dcdaf);
+        assert_eq!(rng.next_u64(), 0x6e789e6aa1b965f4);
+
+        let mut a = Rng::for_job(42, "backup");
+        let mut b = Rng::for_job(42, "backup");
+        let mut c = Rng::for_job(42, "report");
+        let a_values: Vec<_> = (0..8).map(|_| a.next_u64()).collect();
+        let b_values: Vec<_> = (0..8).map(|_| b.next_u64()).collect();
+        let c_values: Vec<_> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(a_values, b_values);
+        assert_ne!(a_values, c_values);
+
+        let mut rng = Rng::new(7);
+        assert!((0..1000).all(|_| rng.below(10) < 10));
+        assert_eq!(rng.below(0), 0);
+        assert!(rng.duration_below(Duration::from_secs(5)) < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_uuid_v4() {
+        let a = uuid_v4();
+        let b = uuid_v4();
+
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 36);
+        assert_eq!(&a[14..15], "4");
+        assert!(matches!(&a[19..20], "8" | "9" | "a" | "b"));
+    }
+}
@@ -0,0 +1,71 @@
+//! Per-occurrence locking through Redis, for fleets of docker-cron instances
+//! that share one logical crontab across several hosts.
+//!
+//! Before each run every instance tries to claim a key derived from the job
+//! name and the time that the run fell due. Only the instance that claims the
+//! key runs the job, so each occurrence executes exactly once. This relies on
+//! every instance agreeing on due times, so it is only meaningful for cron
+//! schedules.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::{aio::ConnectionManager, Client};
+use tracing::warn;
+
+use crate::{
+    job::Job,
+    policy::{RunPolicy, Verdict},
+};
+
+pub struct RedisLock {
+    conn: ConnectionManager,
+    node: String,
+    ttl: Duration,
+}
+
+impl RedisLock {
+    /// `node` identifies this instance in lock values, for debugging. Locks
+    /// are held for `ttl`, which should comfortably exceed the clock skew
+    /// between instances.
+    pub async fn connect(
+        url: &str,
+        node: String,
+        ttl: Duration,
+    ) -> Result<Self, redis::RedisError> {
+        let client = Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+
+        Ok(RedisLock { conn, node, ttl })
+    }
+}
+
+#[async_trait]
+impl RunPolicy for RedisLock {
+    async fn check(&self, job: &Job, due: DateTime<Utc>) -> Verdict {
+        let key = format!("docker-cron:lock:{}:{}", job.name, due.timestamp());
+        let mut conn = self.conn.clone();
+
+        let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&self.node)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await;
+
+        match result {
+            Ok(Some(_)) => Verdict::allow(),
+            Ok(None) => Verdict::veto("occurrence already claimed by another instance"),
+            Err(error) => {
+                // Failing closed risks a missed run, but failing open risks
+                // every instance running the job at once.
+
+                warn!(error = %error, key, "Failed to claim Redis lock");
+                Verdict::veto("could not reach Redis to claim occurrence")
+            }
+        }
+    }
+}
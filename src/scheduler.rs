@@ -0,0 +1,2967 @@
+use std::{
+    any::Any,
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+};
+
+use bollard::Docker;
+use chrono::prelude::*;
+use thiserror::Error;
+use tokio::{
+    select,
+    sync::{broadcast, mpsc, Semaphore},
+    task::{spawn_blocking, AbortHandle, JoinSet},
+    time::{sleep, timeout, Duration, Instant},
+};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::{
+    blackout::{BlackoutAction, Blackouts},
+    clock::{sleep_step, ClockWatch},
+    event::JobEvent,
+    executor::{DockerExecutor, Executor, OutputStream, ResourceUsage},
+    job::{Action, ControlOp, ExecCommand, Job, Overlap, PruneTarget, RunImage},
+    local::run_local,
+    notify::{describe, Notifier},
+    policy::RunPolicy,
+    random::{random_seed, uuid_v4, Rng},
+    state::{InFlight, MemoryStore, StateError, StateStore},
+    trigger::{DependencyTrigger, Trigger, TriggerSpec},
+};
+
+/// How many events a slow subscriber may fall behind before it misses some.
+const EVENT_CAPACITY: usize = 256;
+
+/// How long to wait before restarting a job task that panicked, so that a
+/// job which panics straight away doesn't spin.
+const PANIC_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Bounds on the delay between checks for Docker to come back after the
+/// connection to it was lost.
+const RECONNECT_INITIAL: Duration = Duration::from_millis(500);
+const RECONNECT_MAX: Duration = Duration::from_secs(15);
+
+/// How long a run that has timed out, or is being replaced by a newer one, is
+/// given to stop before it's killed.
+const STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// How long to keep following a container's logs after it exits, for the
+/// last lines it wrote to arrive.
+const LOG_DRAIN: Duration = Duration::from_secs(1);
+
+/// Reasons why a single run of a job did not succeed.
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error("Failed to start container")]
+    Start(#[source] bollard::errors::Error),
+    #[error("Job did not succeed (exit status {status_code})")]
+    Exit { status_code: i64 },
+    #[error("Container wait request returned error message: {0}")]
+    WaitMessage(String),
+    #[error("Error waiting for container completion")]
+    Wait(#[source] bollard::errors::Error),
+    #[error("No response to poll request on Docker API")]
+    NoResponse,
+    #[error("Failed to stop container")]
+    Stop(#[source] bollard::errors::Error),
+    #[error("Failed to copy container to set its environment")]
+    Copy(#[source] bollard::errors::Error),
+    #[error("Failed to find container of Compose service")]
+    FindService(#[source] bollard::errors::Error),
+    #[error("Compose service {project}/{service} has no container")]
+    NoService { project: String, service: String },
+    #[error("Failed to pull image")]
+    Pull(#[source] bollard::errors::Error),
+    #[error("Failed to create container from image")]
+    Create(#[source] bollard::errors::Error),
+    #[error("Failed to remove container")]
+    Remove(#[source] bollard::errors::Error),
+    #[error("Failed to run command in container")]
+    Exec(#[source] bollard::errors::Error),
+    #[error("Failed to run local command")]
+    Local(#[source] std::io::Error),
+    #[error("Failed to {op} container")]
+    Control {
+        op: ControlOp,
+        source: bollard::errors::Error,
+    },
+    #[error("Failed to prune unused {target}")]
+    Prune {
+        target: PruneTarget,
+        source: bollard::errors::Error,
+    },
+    #[error("No Docker host named {host}")]
+    UnknownHost { host: String },
+    #[error("Failed to journal run")]
+    Journal(#[source] StateError),
+    #[error("Job timed out after {}s", timeout.as_secs())]
+    TimedOut { timeout: Duration },
+    #[error("Container did not become healthy: {reason}")]
+    Unhealthy { reason: String },
+    #[error("Pre-run hook container {container} failed")]
+    PreHook {
+        container: String,
+        source: Box<RunError>,
+    },
+    #[error("Post-run hook container {container} failed")]
+    PostHook {
+        container: String,
+        source: Box<RunError>,
+    },
+}
+
+impl RunError {
+    /// Whether the job's retry policy applies, which it does to failures of
+    /// the job's own container but not to timeouts or hook failures.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            RunError::Start(_)
+                | RunError::Pull(_)
+                | RunError::Create(_)
+                | RunError::Exec(_)
+                | RunError::Control { .. }
+                | RunError::Prune { .. }
+                | RunError::Exit { .. }
+                | RunError::Unhealthy { .. }
+        )
+    }
+
+    /// Whether the run failed because the connection to Docker was lost,
+    /// such as when the daemon restarts.
+    pub fn is_disconnect(&self) -> bool {
+        use bollard::errors::Error;
+
+        match self {
+            RunError::NoResponse => true,
+            RunError::Start(error)
+            | RunError::Wait(error)
+            | RunError::Stop(error)
+            | RunError::Copy(error)
+            | RunError::FindService(error)
+            | RunError::Pull(error)
+            | RunError::Create(error)
+            | RunError::Remove(error)
+            | RunError::Exec(error)
+            | RunError::Control { source: error, .. }
+            | RunError::Prune { source: error, .. } => matches!(
+                error,
+                Error::IOError { .. } | Error::HyperLegacyError { .. } | Error::RequestTimeoutError
+            ),
+            _ => false,
+        }
+    }
+}
+
+type JobHook = Box<dyn Fn(&Job) + Send + Sync>;
+type FailureHook = Box<dyn Fn(&Job, &RunError) + Send + Sync>;
+
+#[derive(Default)]
+struct Hooks {
+    on_start: Vec<JobHook>,
+    on_success: Vec<JobHook>,
+    on_failure: Vec<FailureHook>,
+}
+
+/// State shared between all of a scheduler's job tasks.
+struct Context {
+    executor: Box<dyn Executor>,
+    hosts: HashMap<String, Box<dyn Executor>>,
+    hooks: Hooks,
+    events: broadcast::Sender<JobEvent>,
+    state: Arc<dyn StateStore>,
+    policies: Vec<Box<dyn RunPolicy>>,
+    restart_on_panic: bool,
+    record_usage: bool,
+    stream_logs: bool,
+    log_rate_limit: Option<u32>,
+    failure_log_lines: usize,
+    /// The end of the output of runs that failed, by run id, until they're
+    /// reported.
+    failure_logs: std::sync::Mutex<HashMap<String, Vec<String>>>,
+    /// Permits for runs in progress, if their number is limited.
+    run_slots: Option<Semaphore>,
+    blackouts: Option<Blackouts>,
+    seed: u64,
+    splay: Option<Duration>,
+    reconnect: Option<Duration>,
+    task_panics: AtomicU64,
+}
+
+impl Context {
+    /// The executor for a job's containers, which is its host's if it names
+    /// one.
+    fn executor(&self, job: &Job) -> Result<&dyn Executor, RunError> {
+        let Some(host) = &job.host else {
+            return Ok(&*self.executor);
+        };
+
+        match self.hosts.get(host) {
+            Some(executor) => Ok(&**executor),
+            None => Err(RunError::UnknownHost { host: host.clone() }),
+        }
+    }
+
+    fn emit(&self, event: JobEvent) {
+        // Sending only fails if nobody is subscribed, which is fine.
+        let _ = self.events.send(event);
+    }
+
+    /// Build a fresh trigger for a job, wiring `@after` jobs up to the
+    /// events of the jobs they depend on.
+    fn trigger(&self, job: &Job) -> Box<dyn Trigger> {
+        match &job.schedule {
+            TriggerSpec::After(jobs) => Box::new(DependencyTrigger::new(
+                jobs.clone(),
+                self.events.subscribe(),
+            )),
+            _ => job.trigger(),
+        }
+    }
+
+    /// Whether a job has already succeeded as many times as it may run.
+    fn is_used_up(&self, job: &Job) -> bool {
+        let Some(max_runs) = job.max_runs else {
+            return false;
+        };
+
+        match self.state.load(&job.name) {
+            Ok(state) if state.successes >= max_runs => {
+                info!(
+                    job = job.name,
+                    max_runs, "Job has reached its maximum runs, job finished"
+                );
+                true
+            }
+            Ok(_) => false,
+            Err(error) => {
+                warn!(error = %error, "Failed to load job state, assuming runs remain");
+                false
+            }
+        }
+    }
+
+    fn is_paused(&self, job: &Job) -> bool {
+        match self.state.load(&job.name) {
+            Ok(state) => state.paused,
+            Err(error) => {
+                warn!(error = %error, "Failed to load job state, assuming not paused");
+                false
+            }
+        }
+    }
+
+    /// Consult every policy, returning the first veto reason if any.
+    async fn vetoed(&self, job: &Job, due: DateTime<Utc>) -> Option<String> {
+        for policy in &self.policies {
+            let verdict = policy.check(job, due).await;
+
+            if !verdict.annotations.is_empty() {
+                info!(annotations = ?verdict.annotations, "Run annotated by policy");
+            }
+
+            if verdict.veto.is_some() {
+                return verdict.veto;
+            }
+        }
+
+        None
+    }
+
+    /// Write ahead what is about to happen to a run, so that it can be
+    /// recognised if docker-cron crashes before the run finishes. Returns
+    /// once the record is on disk, waiting off the runtime as the store may
+    /// be on a slow volume.
+    async fn journal(&self, job: &Job, in_flight: InFlight) -> Result<(), StateError> {
+        let state = self.state.clone();
+        let name = job.name.clone();
+
+        spawn_blocking(move || {
+            state.update(&name, &mut |state| state.in_flight = Some(in_flight))?;
+            state.flush()
+        })
+        .await
+        .expect("journal write panicked")
+    }
+
+    /// Report whether a job is paused, and report and clear any run that was
+    /// interrupted when docker-cron last stopped.
+    fn recover(&self, job: &Job) {
+        let in_flight = match self.state.load(&job.name) {
+            Ok(state) => {
+                if state.paused {
+                    info!(
+                        job = job.name,
+                        "Job is paused and will not run until resumed"
+                    );
+                }
+
+                state.in_flight
+            }
+            Err(error) => {
+                warn!(job = job.name, error = %error, "Failed to load job state");
+                return;
+            }
+        };
+
+        let Some(InFlight { due, started }) = in_flight else {
+            return;
+        };
+
+        match started {
+            Some(started) => warn!(
+                job = job.name,
+                %due,
+                %started,
+                "Job was running when docker-cron stopped, its outcome is unknown"
+            ),
+            None => warn!(
+                job = job.name,
+                %due,
+                "Job was due to start when docker-cron stopped, it may not have run"
+            ),
+        }
+
+        let result = self
+            .state
+            .update(&job.name, &mut |state| state.in_flight = None);
+
+        if let Err(error) = result {
+            warn!(job = job.name, error = %error, "Failed to clear job journal");
+        }
+    }
+
+    /// The first time that a cron job fell due after its last successful run,
+    /// if that has already passed.
+    fn missed_run(&self, job: &Job) -> Option<DateTime<Utc>> {
+        let last_success = match self.state.load(&job.name) {
+            Ok(state) => state.last_success?,
+            Err(error) => {
+                warn!(job = job.name, error = %error, "Failed to load job state, not catching up");
+                return None;
+            }
+        };
+
+        let missed = job
+            .next_cron_fire(last_success)
+            .filter(|missed| *missed <= Utc::now())?;
+
+        info!(
+            job = job.name,
+            %last_success,
+            %missed,
+            "Job fell due while docker-cron was down, catching up"
+        );
+
+        Some(missed)
+    }
+
+    fn record_usage(&self, job: &Job, usage: ResourceUsage) {
+        info!(
+            peak_memory_bytes = usage.peak_memory_bytes,
+            cpu_millis = usage.cpu_time.as_millis() as u64,
+            "Run resource usage"
+        );
+
+        let result = self.state.update(&job.name, &mut |state| {
+            state.last_usage = Some(usage);
+        });
+
+        if let Err(error) = result {
+            warn!(error = %error, "Failed to save resource usage");
+        }
+    }
+
+    fn record_run(&self, job: &Job, success: bool) {
+        let now = Utc::now();
+        let result = self.state.update(&job.name, &mut |state| {
+            state.last_run = Some(now);
+            state.in_flight = None;
+
+            if success {
+                state.last_success = Some(now);
+                state.successes += 1;
+            }
+        });
+
+        if let Err(error) = result {
+            warn!(error = %error, "Failed to save job state");
+        }
+    }
+}
+
+/// Runs a set of jobs, calling any registered hooks around each run.
+pub struct Scheduler {
+    executor: Box<dyn Executor>,
+    hosts: HashMap<String, Box<dyn Executor>>,
+    jobs: Vec<(Job, Option<Box<dyn Trigger>>)>,
+    hooks: Hooks,
+    events: broadcast::Sender<JobEvent>,
+    state: Box<dyn StateStore>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    policies: Vec<Box<dyn RunPolicy>>,
+    low_memory: bool,
+    restart_on_panic: bool,
+    record_usage: bool,
+    stream_logs: bool,
+    log_rate_limit: Option<u32>,
+    failure_log_lines: usize,
+    max_concurrent: Option<usize>,
+    catch_up: bool,
+    blackouts: Option<Blackouts>,
+    seed: u64,
+    splay: Option<Duration>,
+    reconnect: Option<Duration>,
+}
+
+fn subscribe(events: &broadcast::Sender<JobEvent>) -> impl Stream<Item = JobEvent> {
+    BroadcastStream::new(events.subscribe()).filter_map(|result| match result {
+        Ok(event) => Some(event),
+        Err(error) => {
+            warn!(error = %error, "Event subscriber fell behind");
+            None
+        }
+    })
+}
+
+impl Scheduler {
+    pub fn new(docker: Docker) -> Self {
+        Scheduler::with_executor(Box::new(DockerExecutor::new(docker)))
+    }
+
+    /// Create a scheduler that runs jobs through something other than the
+    /// Docker API, such as a fake in tests.
+    pub fn with_executor(executor: Box<dyn Executor>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Scheduler {
+            executor,
+            hosts: HashMap::new(),
+            jobs: Vec::new(),
+            hooks: Hooks::default(),
+            events,
+            state: Box::new(MemoryStore::default()),
+            notifiers: Vec::new(),
+            policies: Vec::new(),
+            low_memory: false,
+            restart_on_panic: false,
+            record_usage: false,
+            stream_logs: false,
+            log_rate_limit: None,
+            failure_log_lines: 0,
+            max_concurrent: None,
+            catch_up: false,
+            blackouts: None,
+            seed: random_seed(),
+            splay: None,
+            reconnect: None,
+        }
+    }
+
+    /// Run the containers of jobs that name `host` through `executor`, such
+    /// as one for another Docker daemon, rather than the default executor.
+    pub fn add_host(&mut self, host: impl Into<String>, executor: Box<dyn Executor>) -> &mut Self {
+        self.hosts.insert(host.into(), executor);
+        self
+    }
+
+    /// Restart a job's task after it panics, instead of leaving the job
+    /// unscheduled until docker-cron restarts. Jobs added with a custom
+    /// trigger are never restarted, since their trigger can't be recreated.
+    pub fn set_restart_on_panic(&mut self, restart: bool) -> &mut Self {
+        self.restart_on_panic = restart;
+        self
+    }
+
+    /// Sample each run's resource usage while it runs, then log it and save
+    /// it in the state store. This costs a stats request per run.
+    pub fn set_record_usage(&mut self, record: bool) -> &mut Self {
+        self.record_usage = record;
+        self
+    }
+
+    /// Log each line that a run's container writes while it runs, tagged
+    /// with the job and run id, so that every job's output ends up in one
+    /// stream. This costs a logs request per run.
+    pub fn set_stream_logs(&mut self, stream: bool) -> &mut Self {
+        self.stream_logs = stream;
+        self
+    }
+
+    /// Log at most `lines` lines of each run's output per second when
+    /// streaming logs, dropping the rest, so that a chatty container can't
+    /// flood the log. Unlimited by default.
+    pub fn set_log_rate_limit(&mut self, lines: Option<u32>) -> &mut Self {
+        self.log_rate_limit = lines;
+        self
+    }
+
+    /// When a run's container exits with a failure status, read the last
+    /// `lines` lines of its output and include them in the warning and the
+    /// failure event, so that the failure can be looked into straight away.
+    /// Off by default.
+    pub fn set_failure_log_lines(&mut self, lines: usize) -> &mut Self {
+        self.failure_log_lines = lines;
+        self
+    }
+
+    /// Limit how many runs may be in progress at once, across all jobs, so
+    /// that many jobs falling due together don't flood Docker with requests.
+    /// Runs over the limit wait for another to finish, and a limit of zero is
+    /// treated as one. Unlimited by default.
+    pub fn set_max_concurrent(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_concurrent = limit;
+        self
+    }
+
+    /// On starting, run each cron job once straight away if it fell due since
+    /// its last successful run, as anacron does, so that downtime doesn't
+    /// cause runs to be missed. Needs a persistent state store to remember
+    /// when jobs last succeeded.
+    pub fn set_catch_up(&mut self, catch_up: bool) -> &mut Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    /// When a run fails because the connection to Docker was lost, such as
+    /// when the daemon restarts, wait up to `patience` for it to come back
+    /// and then run the job again. Off by default, which leaves the job until
+    /// it next falls due.
+    pub fn set_reconnect(&mut self, patience: Option<Duration>) -> &mut Self {
+        self.reconnect = patience;
+        self
+    }
+
+    /// Hold back runs that fall due during maintenance windows.
+    pub fn set_blackouts(&mut self, blackouts: Blackouts) -> &mut Self {
+        self.blackouts = Some(blackouts);
+        self
+    }
+
+    /// Seed all randomized behaviour, such as jitter, so that it is
+    /// reproducible. Defaults to a different seed every time.
+    pub fn set_random_seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn random_seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Delay every run by a random time less than `splay`, so that hosts
+    /// with the same crontab don't all start the same jobs at once. Jobs
+    /// with their own jitter use that instead.
+    pub fn set_splay(&mut self, splay: Option<Duration>) -> &mut Self {
+        self.splay = splay;
+        self
+    }
+
+    /// Drive all cron-scheduled jobs from a single task that evaluates their
+    /// schedules lazily, instead of giving every job its own task. This keeps
+    /// memory use down with thousands of jobs, at the cost of per-job
+    /// cancellation tokens, which are not available for those jobs.
+    pub fn set_low_memory(&mut self, low_memory: bool) -> &mut Self {
+        self.low_memory = low_memory;
+        self
+    }
+
+    /// Check every due run against a policy before starting it.
+    pub fn add_policy(&mut self, policy: Box<dyn RunPolicy>) -> &mut Self {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Send job events to a notification backend.
+    pub fn add_notifier(&mut self, notifier: Box<dyn Notifier>) -> &mut Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    /// Replace the default in-memory state store.
+    pub fn set_state_store(&mut self, state: Box<dyn StateStore>) -> &mut Self {
+        self.state = state;
+        self
+    }
+
+    pub fn add_job(&mut self, job: Job) -> &mut Self {
+        self.jobs.push((job, None));
+        self
+    }
+
+    /// Add a job that is driven by a custom trigger instead of its schedule.
+    pub fn add_job_with_trigger(&mut self, job: Job, trigger: Box<dyn Trigger>) -> &mut Self {
+        self.jobs.push((job, Some(trigger)));
+        self
+    }
+
+    /// Called after a job's container has been started.
+    pub fn on_start(&mut self, hook: impl Fn(&Job) + Send + Sync + 'static) -> &mut Self {
+        self.hooks.on_start.push(Box::new(hook));
+        self
+    }
+
+    /// Called after a run completes successfully.
+    pub fn on_success(&mut self, hook: impl Fn(&Job) + Send + Sync + 'static) -> &mut Self {
+        self.hooks.on_success.push(Box::new(hook));
+        self
+    }
+
+    /// Called after a run fails for any reason, including failure to start.
+    pub fn on_failure(
+        &mut self,
+        hook: impl Fn(&Job, &RunError) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.hooks.on_failure.push(Box::new(hook));
+        self
+    }
+
+    /// Subscribe to lifecycle events for all jobs. Subscribers that fall too
+    /// far behind will skip the events they missed.
+    pub fn events(&self) -> impl Stream<Item = JobEvent> {
+        subscribe(&self.events)
+    }
+
+    /// Spawn one task per job onto the current runtime. Dropping the
+    /// returned handle aborts all of them immediately; use
+    /// [`SchedulerHandle::shutdown`] to stop gracefully instead.
+    pub fn start(self) -> SchedulerHandle {
+        let mut join_set = JoinSet::new();
+
+        for notifier in self.notifiers {
+            join_set.spawn(dispatch_notifications(notifier, subscribe(&self.events)));
+        }
+
+        let ctx = Arc::new(Context {
+            executor: self.executor,
+            hosts: self.hosts,
+            hooks: self.hooks,
+            events: self.events,
+            state: self.state.into(),
+            policies: self.policies,
+            restart_on_panic: self.restart_on_panic,
+            record_usage: self.record_usage,
+            stream_logs: self.stream_logs,
+            log_rate_limit: self.log_rate_limit,
+            failure_log_lines: self.failure_log_lines,
+            failure_logs: std::sync::Mutex::new(HashMap::new()),
+            run_slots: self
+                .max_concurrent
+                .map(|limit| Semaphore::new(limit.max(1))),
+            blackouts: self.blackouts,
+            seed: self.seed,
+            splay: self.splay,
+            reconnect: self.reconnect,
+            task_panics: AtomicU64::new(0),
+        });
+
+        let mut handle = SchedulerHandle {
+            join_set,
+            shutdown: CancellationToken::new(),
+            cancel_tokens: HashMap::new(),
+            ctx,
+            jobs: HashMap::new(),
+            tasks: HashMap::new(),
+            compact: None,
+            low_memory: self.low_memory,
+        };
+
+        let names: HashSet<&str> = self.jobs.iter().map(|(job, _)| job.name.as_str()).collect();
+
+        for (job, _) in &self.jobs {
+            let TriggerSpec::After(dependencies) = &job.schedule else {
+                continue;
+            };
+
+            for dependency in dependencies {
+                if !names.contains(dependency.as_str()) {
+                    warn!(
+                        job = job.name,
+                        dependency, "Job runs after a job that doesn't exist, so will never run"
+                    );
+                }
+            }
+        }
+
+        for (job, trigger) in self.jobs {
+            handle.ctx.recover(&job);
+
+            let missed = if self.catch_up && trigger.is_none() {
+                handle.ctx.missed_run(&job)
+            } else {
+                None
+            };
+
+            // A catch-up run already runs the job straight away.
+            let missed = missed.or_else(|| {
+                job.run_on_start.then(|| {
+                    info!(job = job.name, "Running job on startup");
+                    Utc::now()
+                })
+            });
+
+            // Jobs with a custom trigger can't be recreated, so are left
+            // alone by reloads.
+            if trigger.is_none() {
+                handle.jobs.insert(job.name.clone(), job.clone());
+            }
+
+            if job.disabled {
+                info!(job = job.name, "Job is disabled and will not run");
+                continue;
+            }
+
+            if trigger.is_none() && handle.is_compact(&job) {
+                handle.add_compact(job, missed);
+
+                continue;
+            }
+
+            handle.spawn(job, trigger, missed);
+        }
+
+        handle
+    }
+}
+
+/// What changed when a scheduler's jobs were reloaded, by job name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reload {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Reload {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Controls a running scheduler.
+pub struct SchedulerHandle {
+    join_set: JoinSet<()>,
+    shutdown: CancellationToken,
+    cancel_tokens: HashMap<String, CancellationToken>,
+    ctx: Arc<Context>,
+    /// The jobs that a reload may replace, by name.
+    jobs: HashMap<String, Job>,
+    /// Tasks of jobs that have their own, by job name.
+    tasks: HashMap<String, AbortHandle>,
+    /// Changes to the cron jobs driven by the low-memory task, once there
+    /// are any.
+    compact: Option<mpsc::UnboundedSender<CompactUpdate>>,
+    low_memory: bool,
+}
+
+impl SchedulerHandle {
+    /// Token that is cancelled when the scheduler begins shutting down.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Token that stops the named job from being scheduled again when it is
+    /// cancelled. An in-progress run is allowed to finish.
+    pub fn cancel_token(&self, job: &str) -> Option<CancellationToken> {
+        self.cancel_tokens.get(job).cloned()
+    }
+
+    /// Skip the named job's runs until it is resumed. The pause is recorded
+    /// in the state store, so with a persistent store it survives restarts.
+    pub fn pause(&self, job: &str) -> Result<(), StateError> {
+        info!(job, "Pausing job");
+        self.ctx.state.update(job, &mut |state| state.paused = true)
+    }
+
+    pub fn resume(&self, job: &str) -> Result<(), StateError> {
+        info!(job, "Resuming job");
+        self.ctx
+            .state
+            .update(job, &mut |state| state.paused = false)
+    }
+
+    pub fn is_paused(&self, job: &str) -> Result<bool, StateError> {
+        Ok(self.ctx.state.load(job)?.paused)
+    }
+
+    /// How many times a job's task or run has panicked since startup.
+    pub fn task_panics(&self) -> u64 {
+        self.ctx.task_panics.load(Ordering::Relaxed)
+    }
+
+    /// Replace the scheduled jobs with `jobs`, such as after the crontab has
+    /// been edited. Jobs that were removed or changed have their tasks
+    /// aborted, abandoning any run in progress, though a container that has
+    /// already started is left to finish. Jobs that were added or changed
+    /// are then scheduled afresh, while unchanged jobs carry on undisturbed.
+    pub fn reload(&mut self, jobs: Vec<Job>) -> Reload {
+        // Reap tasks that finished or were aborted by an earlier reload.
+        while self.join_set.try_join_next().is_some() {}
+
+        let mut reload = Reload::default();
+        let mut jobs: HashMap<String, Job> = jobs
+            .into_iter()
+            .map(|job| (job.name.clone(), job))
+            .collect();
+        let mut old_jobs = std::mem::take(&mut self.jobs);
+
+        for (name, old_job) in &old_jobs {
+            match jobs.get(name) {
+                Some(job) if same_job(job, old_job) => continue,
+                Some(_) => reload.changed.push(name.clone()),
+                None => reload.removed.push(name.clone()),
+            }
+
+            self.abort(name);
+        }
+
+        for name in jobs.keys() {
+            if !old_jobs.contains_key(name) {
+                reload.added.push(name.clone());
+            }
+        }
+
+        for (name, job) in jobs.drain() {
+            let unchanged = old_jobs
+                .remove(&name)
+                .is_some_and(|old_job| same_job(&job, &old_job));
+
+            self.jobs.insert(name, job.clone());
+
+            if job.disabled || unchanged {
+                continue;
+            }
+
+            if self.is_compact(&job) {
+                self.add_compact(job, None);
+            } else {
+                self.spawn(job, None, None);
+            }
+        }
+
+        reload.added.sort();
+        reload.changed.sort();
+        reload.removed.sort();
+
+        if reload.is_empty() {
+            debug!("Reloaded jobs, nothing changed");
+        } else {
+            info!(
+                added = ?reload.added,
+                changed = ?reload.changed,
+                removed = ?reload.removed,
+                "Reloaded jobs"
+            );
+        }
+
+        reload
+    }
+
+    /// Whether a job is driven by the low-memory task rather than its own.
+    fn is_compact(&self, job: &Job) -> bool {
+        self.low_memory && matches!(job.schedule, TriggerSpec::Cron(_))
+    }
+
+    fn spawn(
+        &mut self,
+        job: Job,
+        trigger: Option<Box<dyn Trigger>>,
+        missed: Option<DateTime<Utc>>,
+    ) {
+        let cancel = self
+            .cancel_tokens
+            .entry(job.name.clone())
+            .or_insert_with(|| self.shutdown.child_token())
+            .clone();
+        let name = job.name.clone();
+        let task = self
+            .join_set
+            .spawn(supervise(job, trigger, missed, self.ctx.clone(), cancel));
+
+        self.tasks.insert(name, task);
+    }
+
+    /// Have the low-memory task drive a cron job, starting the task if this
+    /// is the first such job.
+    fn add_compact(&mut self, job: Job, missed: Option<DateTime<Utc>>) {
+        let updates = self.compact.get_or_insert_with(|| {
+            let (updates, receiver) = mpsc::unbounded_channel();
+
+            self.join_set.spawn(drive_compact(
+                receiver,
+                self.ctx.clone(),
+                self.shutdown.clone(),
+            ));
+
+            updates
+        });
+
+        // The task only stops when the scheduler shuts down.
+        let _ = updates.send(CompactUpdate::Add(Box::new(job), missed));
+    }
+
+    /// Abort a job's task, or its run in the low-memory task, clearing any
+    /// run it had journaled since that run is being abandoned on purpose.
+    fn abort(&mut self, name: &str) {
+        if let Some(task) = self.tasks.remove(name) {
+            task.abort();
+        }
+
+        if let Some(updates) = &self.compact {
+            let _ = updates.send(CompactUpdate::Remove(name.to_string()));
+        }
+
+        if let Some(cancel) = self.cancel_tokens.remove(name) {
+            cancel.cancel();
+        }
+
+        self.clear_journal(name);
+    }
+
+    fn clear_journal(&self, name: &str) {
+        let result = self
+            .ctx
+            .state
+            .update(name, &mut |state| state.in_flight = None);
+
+        if let Err(error) = result {
+            warn!(job = name, error = %error, "Failed to clear job journal");
+        }
+    }
+
+    /// Stop scheduling new runs and wait up to `deadline` for in-progress
+    /// runs to finish, then abort whatever is left.
+    pub async fn shutdown(mut self, deadline: Duration) {
+        self.shutdown.cancel();
+
+        if !deadline.is_zero() {
+            let drained = timeout(deadline, async {
+                while self.join_set.join_next().await.is_some() {}
+            })
+            .await;
+
+            if drained.is_err() {
+                warn!(
+                    remaining = self.join_set.len(),
+                    "Shutdown deadline passed, aborting remaining runs"
+                );
+            }
+        }
+
+        self.join_set.abort_all();
+    }
+}
+
+async fn dispatch_notifications(notifier: Box<dyn Notifier>, events: impl Stream<Item = JobEvent>) {
+    let mut events = Box::pin(events);
+
+    while let Some(event) = events.next().await {
+        if !notifier.wants(&event) {
+            continue;
+        }
+
+        if let Err(error) = notifier.notify(&event).await {
+            warn!(error = %error, event = describe(&event), "Failed to send notification");
+        }
+    }
+}
+
+async fn wait_container(
+    executor: &dyn Executor,
+    container: &str,
+    limit: Option<Duration>,
+) -> Result<(), RunError> {
+    match limit {
+        None => executor.wait(container).await,
+        Some(limit) => match timeout(limit, executor.wait(container)).await {
+            Ok(result) => result,
+            Err(_) => {
+                // Otherwise a wedged container would go on running, and the
+                // job's later runs would find it still busy.
+                info!(container, "Run timed out, stopping container");
+
+                if let Err(error) = executor.stop(container, STOP_GRACE).await {
+                    warn!(container, error = %error, "Failed to stop timed out container");
+                }
+
+                Err(RunError::TimedOut { timeout: limit })
+            }
+        },
+    }
+}
+
+/// Wait until a run's container is done with, which for jobs judged by their
+/// healthcheck is when it becomes healthy, and otherwise when it exits.
+async fn wait_run(job: &Job, executor: &dyn Executor, container: &str) -> Result<(), RunError> {
+    let Some(deadline) = job.healthy_within else {
+        return wait_container(executor, container, job.timeout).await;
+    };
+
+    timeout(deadline, executor.wait_healthy(container))
+        .await
+        .unwrap_or_else(|_| {
+            Err(RunError::Unhealthy {
+                reason: format!(
+                    "still starting after {}",
+                    humantime::format_duration(deadline)
+                ),
+            })
+        })
+}
+
+async fn run_hook_container(executor: &dyn Executor, container: &str) -> Result<(), RunError> {
+    debug!(hook = container, "Running hook container");
+    executor.start(container).await?;
+    executor.wait(container).await
+}
+
+/// The container that a job's action operates on. A Compose service's
+/// container is found afresh for each run, since Compose replaces it whenever
+/// the service changes.
+async fn action_container(job: &Job, executor: &dyn Executor) -> Result<String, RunError> {
+    match &job.action {
+        Action::Compose(service) => executor.find_service(service).await,
+        action => Ok(action.container().to_string()),
+    }
+}
+
+/// The container that a job's runs actually start, given the one that its
+/// action operates on. Docker can't change the environment or command of an
+/// existing container, so jobs that set either run a copy of their container
+/// instead, which is kept until the next run so that its logs can be read.
+/// Jobs that run an image create their own container.
+fn run_container(job: &Job, original: &str) -> String {
+    match &job.action {
+        Action::Start(_) | Action::Compose(_) if !job.env.is_empty() || !job.command.is_empty() => {
+            format!("{original}-docker-cron")
+        }
+        _ => original.to_string(),
+    }
+}
+
+/// Labels for the containers created for a run, which trace them back to it.
+fn run_labels(job: &Job, run_id: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (String::from("docker-cron.job"), job.name.clone()),
+        (String::from("docker-cron.run-id"), run_id.to_string()),
+    ])
+}
+
+async fn run_main(
+    job: &Job,
+    due: DateTime<Utc>,
+    run_id: &str,
+    ctx: &Context,
+) -> Result<(), RunError> {
+    ctx.journal(job, InFlight { due, started: None })
+        .await
+        .map_err(RunError::Journal)?;
+
+    let executor = ctx.executor(job)?;
+    let original = &action_container(job, executor).await?;
+    let container = &run_container(job, original);
+    let labels = &run_labels(job, run_id);
+
+    match &job.action {
+        Action::Start(_) | Action::Compose(_) if container != original => {
+            executor
+                .copy(original, container, &job.env, &job.command, labels)
+                .await?
+        }
+        Action::Start(_) | Action::Compose(_) => {}
+        Action::Run(run) => {
+            executor.pull(&run.image, run.pull).await?;
+            executor.create(container, run, &job.env, labels).await?
+        }
+        Action::Exec(exec) => {
+            started(job, due, ctx).await;
+            let result = exec_command(executor, exec, &job.env, job.timeout).await;
+            return accept_success_codes(job, result);
+        }
+        Action::Local(command) => {
+            started(job, due, ctx).await;
+            let result = local_command(command, &job.env, job.timeout).await;
+            return accept_success_codes(job, result);
+        }
+        Action::Control(control) => {
+            started(job, due, ctx).await;
+            info!(op = %control.op, "Changing container's state");
+            return executor.control(&control.container, control.op).await;
+        }
+        Action::Prune(prune) => {
+            started(job, due, ctx).await;
+            return executor.prune(prune).await;
+        }
+    }
+
+    let result = accept_success_codes(job, start_and_wait(job, due, container, ctx).await);
+
+    if let (Err(RunError::Exit { .. }), 1..) = (&result, ctx.failure_log_lines) {
+        let output = executor.log_tail(container, ctx.failure_log_lines).await;
+
+        ctx.failure_logs
+            .lock()
+            .unwrap()
+            .insert(run_id.to_string(), output);
+    }
+
+    if let Action::Run(RunImage {
+        auto_remove: true, ..
+    }) = &job.action
+    {
+        if let Err(error) = executor.remove(container).await {
+            warn!(container, error = %error, "Failed to remove container");
+        }
+    }
+
+    result
+}
+
+/// Count a run as having succeeded if it exited with a status that its job
+/// treats as success.
+fn accept_success_codes(job: &Job, result: Result<(), RunError>) -> Result<(), RunError> {
+    match result {
+        Err(RunError::Exit { status_code }) if job.succeeds_with(status_code) => {
+            debug!(status_code, "Exit status counts as success");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Record that a run has started and tell anyone listening.
+async fn started(job: &Job, due: DateTime<Utc>, ctx: &Context) {
+    let in_flight = InFlight {
+        due,
+        started: Some(Utc::now()),
+    };
+
+    // The run is already under way, so it carries on regardless.
+    if let Err(error) = ctx.journal(job, in_flight).await {
+        warn!(error = %error, "Failed to journal run");
+    }
+
+    for hook in &ctx.hooks.on_start {
+        hook(job);
+    }
+
+    ctx.emit(JobEvent::Started {
+        job: job.name.clone(),
+        container: job.action.container().to_string(),
+        time: Utc::now(),
+    });
+}
+
+/// Run a command in a running container. Docker can't stop a command once
+/// it has started short of stopping the whole container, so one that times
+/// out is left to finish by itself.
+async fn exec_command(
+    executor: &dyn Executor,
+    exec: &ExecCommand,
+    env: &BTreeMap<String, String>,
+    limit: Option<Duration>,
+) -> Result<(), RunError> {
+    let run = executor.exec(&exec.container, &exec.command, env);
+
+    match limit {
+        None => run.await,
+        Some(limit) => timeout(limit, run).await.unwrap_or_else(|_| {
+            warn!(
+                container = exec.container,
+                "Command timed out, leaving it running"
+            );
+            Err(RunError::TimedOut { timeout: limit })
+        }),
+    }
+}
+
+/// Run a command on the host, killing it if it times out.
+async fn local_command(
+    command: &str,
+    env: &BTreeMap<String, String>,
+    limit: Option<Duration>,
+) -> Result<(), RunError> {
+    let run = run_local(command, env);
+
+    match limit {
+        None => run.await,
+        Some(limit) => timeout(limit, run)
+            .await
+            .unwrap_or(Err(RunError::TimedOut { timeout: limit })),
+    }
+}
+
+async fn start_and_wait(
+    job: &Job,
+    due: DateTime<Utc>,
+    container: &str,
+    ctx: &Context,
+) -> Result<(), RunError> {
+    let executor = ctx.executor(job)?;
+    let since = Utc::now();
+    executor.start(container).await?;
+    started(job, due, ctx).await;
+
+    if !ctx.record_usage && !ctx.stream_logs {
+        return wait_run(job, executor, container).await;
+    }
+
+    let usage = std::sync::Mutex::new(ResourceUsage::default());
+    let limiter = std::sync::Mutex::new(LogLimiter::new(ctx.log_rate_limit));
+    let log_line = |stream: OutputStream, line: &str| {
+        if limiter.lock().unwrap().allow(Instant::now()) {
+            info!(
+                container,
+                stream = stream.as_str(),
+                line,
+                "Container output"
+            );
+        }
+    };
+
+    let result = {
+        let mut wait = pin!(wait_run(job, executor, container));
+        let mut sampling = pin!(async {
+            if ctx.record_usage {
+                executor.sample_usage(container, &usage).await
+            }
+        });
+        let mut following = pin!(async {
+            if ctx.stream_logs {
+                executor.follow_logs(container, since, &log_line).await
+            }
+        });
+        let mut sampling_done = false;
+        let mut following_done = false;
+
+        let result = loop {
+            select! {
+                result = &mut wait => break result,
+                _ = &mut sampling, if !sampling_done => sampling_done = true,
+                _ = &mut following, if !following_done => following_done = true,
+            }
+        };
+
+        if !following_done {
+            let _ = timeout(LOG_DRAIN, following).await;
+        }
+
+        result
+    };
+
+    limiter.into_inner().unwrap().flush();
+
+    if ctx.record_usage {
+        ctx.record_usage(job, usage.into_inner().unwrap());
+    }
+
+    result
+}
+
+/// Limits how many lines of a run's output are logged each second.
+struct LogLimiter {
+    limit: Option<u32>,
+    window: Option<Instant>,
+    logged: u32,
+    dropped: u64,
+}
+
+impl LogLimiter {
+    fn new(limit: Option<u32>) -> Self {
+        LogLimiter {
+            limit,
+            window: None,
+            logged: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Whether a line written at `now` should be logged.
+    fn allow(&mut self, now: Instant) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+
+        let expired = match self.window {
+            Some(start) => now.duration_since(start) >= Duration::from_secs(1),
+            None => true,
+        };
+
+        if expired {
+            self.flush();
+            self.window = Some(now);
+            self.logged = 0;
+        }
+
+        if self.logged < limit {
+            self.logged += 1;
+            true
+        } else {
+            self.dropped += 1;
+            false
+        }
+    }
+
+    /// Log how many lines were dropped since this was last called.
+    fn flush(&mut self) {
+        if self.dropped > 0 {
+            warn!(
+                dropped = self.dropped,
+                "Dropped container output over the rate limit"
+            );
+            self.dropped = 0;
+        }
+    }
+}
+
+async fn run_job(
+    job: &Job,
+    due: DateTime<Utc>,
+    run_id: &str,
+    ctx: &Context,
+) -> Result<(), RunError> {
+    let executor = ctx.executor(job)?;
+
+    // A failed pre-run hook skips the main container, but post-run hooks
+    // still run so that they can undo whatever the other pre-run hooks did.
+
+    let mut result = match run_pre_hooks(job, executor).await {
+        Ok(()) => run_retrying(job, due, run_id, executor, ctx).await,
+        Err(error) => Err(error),
+    };
+
+    for container in &job.after {
+        if let Err(source) = run_hook_container(executor, container).await {
+            let error = RunError::PostHook {
+                container: container.clone(),
+                source: Box::new(source),
+            };
+
+            match result {
+                Ok(()) => result = Err(error),
+                Err(_) => log_failure(&error, &[]),
+            }
+        }
+    }
+
+    result
+}
+
+async fn run_pre_hooks(job: &Job, executor: &dyn Executor) -> Result<(), RunError> {
+    for container in &job.before {
+        run_hook_container(executor, container)
+            .await
+            .map_err(|source| RunError::PreHook {
+                container: container.clone(),
+                source: Box::new(source),
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Run the main part of a job, retrying it as the job's retry policy allows.
+async fn run_retrying(
+    job: &Job,
+    due: DateTime<Utc>,
+    run_id: &str,
+    executor: &dyn Executor,
+    ctx: &Context,
+) -> Result<(), RunError> {
+    let mut result = run_reconnecting(job, due, run_id, executor, ctx).await;
+    let mut retries = 0;
+
+    while let Err(error) = &result {
+        if retries == job.retry.attempts || !error.is_retryable() {
+            break;
+        }
+
+        retries += 1;
+        let delay = job.retry.delay(retries);
+
+        warn!(
+            error = %error,
+            retry = retries,
+            max_retries = job.retry.attempts,
+            delay_ms = delay.as_millis() as u64,
+            "Run failed, retrying"
+        );
+
+        sleep(delay).await;
+        result = run_reconnecting(job, due, run_id, executor, ctx).await;
+    }
+
+    if result.is_err() && retries > 0 {
+        warn!(
+            retries,
+            "Run failed on every retry, giving up until next due"
+        );
+    }
+
+    result
+}
+
+/// Run the main part of a job, and run it again if it failed because the
+/// connection to Docker was lost and Docker comes back in time.
+async fn run_reconnecting(
+    job: &Job,
+    due: DateTime<Utc>,
+    run_id: &str,
+    executor: &dyn Executor,
+    ctx: &Context,
+) -> Result<(), RunError> {
+    let result = run_main(job, due, run_id, ctx).await;
+
+    let (Err(error), Some(patience)) = (&result, ctx.reconnect) else {
+        return result;
+    };
+
+    if !error.is_disconnect() {
+        return result;
+    }
+
+    warn!(error = %error, "Lost connection to Docker, waiting for it to come back");
+
+    if !wait_until_ready(executor, patience).await {
+        warn!(
+            patience_secs = patience.as_secs(),
+            "Docker didn't come back in time, giving up until next due"
+        );
+
+        return result;
+    }
+
+    info!("Docker is back, running again");
+    run_main(job, due, run_id, ctx).await
+}
+
+/// Check with exponential backoff whether an executor can be reached again,
+/// for up to `patience`.
+async fn wait_until_ready(executor: &dyn Executor, patience: Duration) -> bool {
+    let deadline = Instant::now() + patience;
+    let mut delay = RECONNECT_INITIAL;
+
+    loop {
+        if executor.is_ready().await {
+            return true;
+        }
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            return false;
+        }
+
+        sleep(delay.min(deadline - now)).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+fn log_failure(error: &RunError, output: &[String]) {
+    match error {
+        RunError::Exit { status_code } if !output.is_empty() => warn!(
+            status_code,
+            output = output.join("\n"),
+            "Job did not succeed"
+        ),
+        RunError::Start(error) => warn!(error = ?error, "Failed to start container"),
+        RunError::Exit { status_code } => warn!(status_code, "Job did not succeed"),
+        RunError::WaitMessage(error_msg) => {
+            warn!(error_msg, "Container wait request returned error message")
+        }
+        RunError::Wait(error) => warn!(error = ?error, "Error waiting for container completion"),
+        RunError::NoResponse => warn!("No response to poll request on Docker API"),
+        RunError::Stop(error) => warn!(error = ?error, "Failed to stop container"),
+        RunError::Copy(error) => {
+            warn!(error = ?error, "Failed to copy container to set its environment")
+        }
+        RunError::Create(error) => {
+            warn!(error = ?error, "Failed to create container from image")
+        }
+        RunError::FindService(error) => {
+            warn!(error = ?error, "Failed to find container of Compose service")
+        }
+        RunError::NoService { project, service } => {
+            warn!(project, service, "Compose service has no container")
+        }
+        RunError::Pull(error) => warn!(error = ?error, "Failed to pull image"),
+        RunError::Remove(error) => warn!(error = ?error, "Failed to remove container"),
+        RunError::Exec(error) => warn!(error = ?error, "Failed to run command in container"),
+        RunError::Local(error) => warn!(error = %error, "Failed to run local command"),
+        RunError::Control { op, source } => {
+            warn!(op = %op, error = ?source, "Failed to change container's state")
+        }
+        RunError::Prune { target, source } => {
+            warn!(target = %target, error = ?source, "Failed to prune unused objects")
+        }
+        RunError::UnknownHost { host } => warn!(host, "Job's Docker host is not configured"),
+        RunError::Journal(error) => warn!(error = %error, "Failed to journal run"),
+        RunError::TimedOut { timeout } => {
+            warn!(timeout_secs = timeout.as_secs(), "Job timed out")
+        }
+        RunError::Unhealthy { reason } => warn!(reason, "Container did not become healthy"),
+        RunError::PreHook { container, source } => {
+            warn!(hook = container, error = %source, "Pre-run hook failed, skipping run")
+        }
+        RunError::PostHook { container, source } => {
+            warn!(hook = container, error = %source, "Post-run hook failed")
+        }
+    }
+}
+
+/// Resolves to the panic message instead of unwinding if the wrapped future
+/// panics, so that a panic can be attributed to the job that caused it.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> CatchUnwind<F> {
+    fn new(future: F) -> Self {
+        CatchUnwind(Box::pin(future))
+    }
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(panic_message(payload))),
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+/// Whether two versions of a job are the same, comparing them as serialized
+/// since not everything in a job can be compared directly.
+fn same_job(a: &Job, b: &Job) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Run a job's task, reporting it loudly if it panics and restarting it if
+/// so configured. A `missed` run is caught up on straight away.
+async fn supervise(
+    job: Job,
+    trigger: Option<Box<dyn Trigger>>,
+    mut missed: Option<DateTime<Utc>>,
+    ctx: Arc<Context>,
+    cancel: CancellationToken,
+) {
+    let restartable = ctx.restart_on_panic && trigger.is_none();
+    let mut trigger = trigger.unwrap_or_else(|| ctx.trigger(&job));
+
+    loop {
+        let task = schedule_job(
+            job.clone(),
+            trigger,
+            missed.take(),
+            ctx.clone(),
+            cancel.clone(),
+        );
+
+        let Err(message) = CatchUnwind::new(task).await else {
+            return;
+        };
+
+        ctx.task_panics.fetch_add(1, Ordering::Relaxed);
+        error!(job = job.name, panic = message, "Job task panicked");
+
+        if !restartable {
+            error!(
+                job = job.name,
+                "Job will not run again until docker-cron restarts"
+            );
+
+            return;
+        }
+
+        select! {
+            _ = cancel.cancelled() => return,
+            _ = sleep(PANIC_RESTART_DELAY) => {}
+        }
+
+        info!(job = job.name, "Restarting job task");
+        trigger = ctx.trigger(&job);
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        job = job.name,
+        schedule = job.schedule.to_string(),
+        container = job.action.container()
+    )
+)]
+async fn schedule_job(
+    job: Job,
+    mut trigger: Box<dyn Trigger>,
+    missed: Option<DateTime<Utc>>,
+    ctx: Arc<Context>,
+    cancel: CancellationToken,
+) {
+    debug!("Scheduling job");
+
+    if let Some(not_before) = job.not_before {
+        if Utc::now() < not_before {
+            info!(%not_before, "Job hasn't started yet, waiting");
+        }
+
+        while Utc::now() < not_before {
+            select! {
+                _ = cancel.cancelled() => {
+                    debug!("Job cancelled");
+
+                    return;
+                }
+                _ = sleep(sleep_step(not_before)) => {}
+            }
+        }
+    }
+
+    let mut queued = missed;
+
+    loop {
+        if ctx.is_used_up(&job) {
+            return;
+        }
+
+        let due = match queued.take() {
+            Some(due) => Some(due),
+            None => select! {
+                _ = cancel.cancelled() => {
+                    debug!("Job cancelled");
+
+                    return;
+                }
+                due = trigger.next() => due,
+            },
+        };
+
+        let Some(due) = due else {
+            info!("Trigger will not fire again, job finished");
+
+            return;
+        };
+
+        if job.not_after.is_some_and(|not_after| due > not_after) {
+            info!("Job has passed its end date, job finished");
+
+            return;
+        }
+
+        if !job.is_active_at(due) {
+            debug!(%due, "Run is before the job's start date, skipping");
+
+            continue;
+        }
+
+        debug!("Wakeup");
+
+        let mut run = pin!(fire(&job, due, &ctx));
+        let mut finished = false;
+
+        // Keep the trigger going during the run, so that any times at which
+        // the job falls due meanwhile are handled rather than silently lost.
+
+        loop {
+            select! {
+                _ = &mut run => break,
+                next = trigger.next(), if !finished => match next {
+                    Some(next) => {
+                        if overlapped(&job, next, queued.is_some(), &ctx).await {
+                            queued = Some(next);
+                        }
+                    }
+                    None => finished = true,
+                },
+            }
+        }
+
+        if finished && queued.is_none() {
+            info!("Trigger will not fire again, job finished");
+
+            return;
+        }
+    }
+}
+
+/// Handle a job falling due while its previous run is still in progress,
+/// returning whether the new run should go ahead once the previous one ends.
+/// At most one run is held back, so `queued` says whether one already is.
+async fn overlapped(job: &Job, due: DateTime<Utc>, queued: bool, ctx: &Context) -> bool {
+    match job.overlap {
+        Overlap::Skip => {
+            warn!(
+                job = job.name,
+                %due,
+                "Previous run is still in progress, skipping run"
+            );
+
+            false
+        }
+        Overlap::Queue | Overlap::Replace if queued => {
+            warn!(
+                job = job.name,
+                %due,
+                "Previous run is still in progress and another is queued, skipping run"
+            );
+
+            false
+        }
+        Overlap::Queue => {
+            info!(
+                job = job.name,
+                %due,
+                "Previous run is still in progress, queueing run"
+            );
+
+            true
+        }
+        // Stopping the container would stop far more than the last run, and
+        // a local command has no container to stop. Nor is there any point
+        // stopping a container that is only being stopped or started.
+        Overlap::Replace
+            if matches!(
+                job.action,
+                Action::Exec(_) | Action::Local(_) | Action::Control(_) | Action::Prune(_)
+            ) =>
+        {
+            warn!(
+                job = job.name,
+                %due,
+                "Previous run is still in progress and can't be stopped, queueing run"
+            );
+
+            true
+        }
+        Overlap::Replace => {
+            warn!(
+                job = job.name,
+                %due,
+                "Previous run is still in progress, stopping it"
+            );
+
+            let stopped: Result<(), RunError> = async {
+                let executor = ctx.executor(job)?;
+                let original = action_container(job, executor).await?;
+
+                executor
+                    .stop(&run_container(job, &original), STOP_GRACE)
+                    .await
+            }
+            .await;
+
+            if let Err(error) = stopped {
+                warn!(job = job.name, error = %error, "Failed to stop previous run");
+            }
+
+            true
+        }
+    }
+}
+
+/// A change to the cron jobs driven by the low-memory task.
+enum CompactUpdate {
+    /// Drive a job, catching up on a missed run straight away if there is
+    /// one.
+    Add(Box<Job>, Option<DateTime<Utc>>),
+    /// Stop driving the named job, abandoning any run in progress.
+    Remove(String),
+}
+
+/// The cron jobs driven by the low-memory task, and when each is next due.
+#[derive(Default)]
+struct CompactJobs {
+    /// Every job driven so far. Jobs that were since removed leave a gap, and
+    /// whatever was queued for them is dropped when it falls due.
+    jobs: Vec<Option<Arc<Job>>>,
+    by_name: HashMap<String, usize>,
+    queue: BinaryHeap<Reverse<(DateTime<Utc>, usize)>>,
+    /// Runs in progress, by job.
+    running: HashMap<usize, AbortHandle>,
+}
+
+impl CompactJobs {
+    fn add(&mut self, job: Job, missed: Option<DateTime<Utc>>, ctx: &Context) {
+        self.remove(&job.name);
+
+        let idx = self.jobs.len();
+
+        if !ctx.is_used_up(&job) {
+            if let Some(next) = missed.or_else(|| job.next_cron_fire(Utc::now())) {
+                self.queue.push(Reverse((next, idx)));
+            }
+        }
+
+        self.by_name.insert(job.name.clone(), idx);
+        self.jobs.push(Some(Arc::new(job)));
+    }
+
+    fn remove(&mut self, name: &str) {
+        let Some(idx) = self.by_name.remove(name) else {
+            return;
+        };
+
+        self.jobs[idx] = None;
+
+        if let Some(run) = self.running.remove(&idx) {
+            run.abort();
+        }
+    }
+}
+
+/// Drive many cron jobs from one task. Only the next fire time of each job is
+/// kept in a queue, and a job's following fire time is not computed until its
+/// current run has finished. Any fire times that passed during the run are
+/// then treated as overlaps, as in [`schedule_job`]. Jobs are added and
+/// removed through `updates`, leaving the others and their runs alone.
+async fn drive_compact(
+    mut updates: mpsc::UnboundedReceiver<CompactUpdate>,
+    ctx: Arc<Context>,
+    cancel: CancellationToken,
+) {
+    let mut compact = CompactJobs::default();
+    let mut runs: JoinSet<(usize, DateTime<Utc>)> = JoinSet::new();
+    let mut updates_open = true;
+    let mut clock = ClockWatch::default();
+
+    debug!("Scheduling cron jobs in low-memory mode");
+
+    loop {
+        // As in CronTrigger, runs that were pushed later by the clock jumping
+        // back are brought forward again.
+
+        if let Some(jump) = clock.jumped() {
+            warn!(
+                jump_secs = jump.num_seconds(),
+                "System clock jumped, rescheduling"
+            );
+
+            if jump < chrono::Duration::zero() {
+                let now = Utc::now();
+                let jobs = &compact.jobs;
+
+                compact.queue = compact
+                    .queue
+                    .drain()
+                    .map(|Reverse((due, idx))| {
+                        let next = jobs[idx]
+                            .as_ref()
+                            .and_then(|job| job.next_cron_fire(now))
+                            .map_or(due, |next| next.min(due));
+
+                        Reverse((next, idx))
+                    })
+                    .collect();
+            }
+        }
+
+        let next = compact.queue.peek().map(|Reverse(entry)| *entry);
+
+        if next.is_none() && runs.is_empty() && !updates_open {
+            break;
+        }
+
+        let wait = next.map_or(Duration::ZERO, |(due, _)| sleep_step(due));
+
+        select! {
+            _ = cancel.cancelled() => break,
+            update = updates.recv(), if updates_open => match update {
+                Some(CompactUpdate::Add(job, missed)) => compact.add(*job, missed, &ctx),
+                Some(CompactUpdate::Remove(name)) => compact.remove(&name),
+                None => updates_open = false,
+            },
+            Some(result) = runs.join_next(), if !runs.is_empty() => {
+                // Runs of jobs that were removed meanwhile were aborted, and
+                // the job is not rescheduled.
+                let Ok((idx, started)) = result else {
+                    continue;
+                };
+
+                compact.running.remove(&idx);
+
+                let Some(job) = compact.jobs[idx].clone() else {
+                    continue;
+                };
+                let now = Utc::now();
+
+                if ctx.is_used_up(&job) {
+                    continue;
+                }
+
+                let missed = job.next_cron_fire(started).filter(|missed| *missed <= now);
+
+                // Times that passed during the run count as overlaps,
+                // though only the first is reported so that a long run
+                // of a frequent job doesn't flood the log. Overlaps are
+                // only noticed once the run has ended, so a run is never
+                // replaced here, only followed by the missed one.
+
+                match (missed, job.overlap) {
+                    (Some(missed), Overlap::Skip) => {
+                        warn!(
+                            job = job.name,
+                            due = %missed,
+                            "Previous run was still in progress, skipped run"
+                        );
+                    }
+                    (Some(missed), Overlap::Queue | Overlap::Replace) => {
+                        info!(
+                            job = job.name,
+                            due = %missed,
+                            "Previous run was still in progress, running late"
+                        );
+                        compact.queue.push(Reverse((missed, idx)));
+                        continue;
+                    }
+                    (None, _) => {}
+                }
+
+                if let Some(next) = job.next_cron_fire(now) {
+                    compact.queue.push(Reverse((next, idx)));
+                }
+            }
+            _ = sleep(wait), if next.is_some() => {
+                if next.is_some_and(|(due, _)| due > Utc::now()) {
+                    continue;
+                }
+
+                let Some(Reverse((due, idx))) = compact.queue.pop() else {
+                    continue;
+                };
+                let Some(job) = compact.jobs[idx].clone() else {
+                    continue;
+                };
+                let ctx = ctx.clone();
+                // Late runs, such as catch-ups, overlap with what falls due
+                // from when they start rather than from when they were due.
+                let started = due.max(Utc::now());
+                let span = info_span!(
+                    "schedule_job",
+                    job = job.name,
+                    schedule = job.schedule.to_string(),
+                    container = job.action.container()
+                );
+
+                let run = runs.spawn(
+                    async move {
+                        // Jobs are rescheduled when their run finishes, so a
+                        // panic mustn't escape or the job would stop for good.
+                        if let Err(message) = CatchUnwind::new(fire(&job, due, &ctx)).await {
+                            ctx.task_panics.fetch_add(1, Ordering::Relaxed);
+                            error!(job = job.name, panic = message, "Job run panicked");
+                        }
+
+                        (idx, started)
+                    }
+                    .instrument(span),
+                );
+
+                compact.running.insert(idx, run);
+            }
+        }
+    }
+
+    // Let in-progress runs finish, the shutdown deadline permitting.
+
+    while runs.join_next().await.is_some() {}
+}
+
+/// Run a job that has fallen due, giving the run an id. The id is logged with
+/// everything the run does and labels any container created for it, so that
+/// the container's logs can be matched with the scheduler's.
+async fn fire(job: &Job, due: DateTime<Utc>, ctx: &Context) {
+    let run_id = uuid_v4();
+    let span = info_span!("run", run_id);
+
+    fire_run(job, due, &run_id, ctx).instrument(span).await
+}
+
+/// Run a job that has fallen due, unless it is paused or vetoed.
+async fn fire_run(job: &Job, due: DateTime<Utc>, run_id: &str, ctx: &Context) {
+    if ctx.is_paused(job) {
+        info!("Job is paused, skipping run");
+
+        return;
+    }
+
+    // Each run's delay depends only on the seed, the job and when the run
+    // was due, so that it's reproducible however tasks interleave.
+
+    if let Some(jitter) = job.jitter.or(ctx.splay).filter(|jitter| !jitter.is_zero()) {
+        let mut rng = Rng::for_job(ctx.seed ^ due.timestamp() as u64, &job.name);
+        let delay = rng.duration_below(jitter);
+
+        info!(
+            delay_ms = delay.as_millis() as u64,
+            "Delaying run by jitter"
+        );
+        sleep(delay).await;
+    }
+
+    if let Some(blackouts) = &ctx.blackouts {
+        if let Some(until) = blackouts.until(Utc::now()) {
+            match blackouts.action {
+                BlackoutAction::Skip => {
+                    info!(%until, "In a blackout window, skipping run");
+
+                    return;
+                }
+                BlackoutAction::Defer => {
+                    info!(%until, "In a blackout window, deferring run");
+
+                    // Another window may have opened by the time this one
+                    // closes, or the clock may have moved, so the run waits
+                    // until the wall clock is clear of every window.
+                    while let Some(until) = blackouts.until(Utc::now()) {
+                        sleep(sleep_step(until)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(reason) = ctx.vetoed(job, due).await {
+        info!(reason, "Run vetoed by policy");
+
+        return;
+    }
+
+    let _permit = match &ctx.run_slots {
+        Some(slots) => Some(match slots.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                info!("Too many runs in progress, delaying run");
+
+                let permit = slots.acquire().await.expect("semaphore is never closed");
+                let delay = (Utc::now() - due).to_std().unwrap_or_default();
+                info!(delay_ms = delay.as_millis() as u64, "Delayed run starting");
+
+                permit
+            }
+        }),
+        None => None,
+    };
+
+    let result = run_job(job, due, run_id, ctx).await;
+    ctx.record_run(job, result.is_ok());
+
+    // Only the output of the attempt that failed last is reported.
+    let output = ctx
+        .failure_logs
+        .lock()
+        .unwrap()
+        .remove(run_id)
+        .filter(|_| matches!(result, Err(RunError::Exit { .. })))
+        .unwrap_or_default();
+
+    match result {
+        Ok(()) => {
+            debug!("Successful exit");
+
+            for hook in &ctx.hooks.on_success {
+                hook(job);
+            }
+
+            ctx.emit(JobEvent::Succeeded {
+                job: job.name.clone(),
+                container: job.action.container().to_string(),
+                time: Utc::now(),
+            });
+        }
+        Err(error) => {
+            log_failure(&error, &output);
+
+            if let (Some(container), RunError::Exit { .. }, Ok(executor)) =
+                (&job.on_failure, &error, ctx.executor(job))
+            {
+                info!(hook = container, "Running on-failure container");
+
+                if let Err(error) = run_hook_container(executor, container).await {
+                    warn!(hook = container, error = %error, "On-failure container failed");
+                }
+            }
+
+            for hook in &ctx.hooks.on_failure {
+                hook(job, &error);
+            }
+
+            ctx.emit(JobEvent::Failed {
+                job: job.name.clone(),
+                container: job.action.container().to_string(),
+                time: Utc::now(),
+                error: error.to_string(),
+                output,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        job::{ComposeService, ControlContainer, JobBuildError, JobBuilder, Prune, PullPolicy},
+        state::{JobState, MemoryStore},
+        testing::{MockExecutor, Outcome},
+        trigger::{ManualTrigger, ManualTriggerHandle},
+    };
+
+    use super::*;
+
+    /// A job that starts the container of the same name every hour, for
+    /// tests to customise.
+    fn job(name: &str) -> JobBuilder {
+        Job::new(name)
+            .schedule("@every 1h")
+            .action(Action::Start(String::from(name)))
+    }
+
+    /// A started scheduler whose jobs run against a mock executor whenever
+    /// their manual triggers are fired.
+    struct Harness {
+        executor: Arc<MockExecutor>,
+        scheduler: SchedulerHandle,
+        events: Pin<Box<dyn Stream<Item = JobEvent>>>,
+        triggers: Vec<ManualTriggerHandle>,
+    }
+
+    impl Harness {
+        /// Start a scheduler with each of `jobs` on a manual trigger, after
+        /// `setup` has added containers and configured the scheduler.
+        fn start(
+            jobs: Vec<JobBuilder>,
+            setup: impl FnOnce(&Arc<MockExecutor>, &mut Scheduler),
+        ) -> Result<Self, JobBuildError> {
+            let executor = Arc::new(MockExecutor::new());
+            let mut scheduler = Scheduler::with_executor(Box::new(executor.clone()));
+            let events = Box::pin(scheduler.events());
+            let mut triggers = Vec::new();
+
+            setup(&executor, &mut scheduler);
+
+            for job in jobs {
+                let (trigger, handle) = ManualTrigger::new();
+
+                scheduler.add_job_with_trigger(job.build()?, Box::new(trigger));
+                triggers.push(handle);
+            }
+
+            Ok(Harness {
+                executor,
+                scheduler: scheduler.start(),
+                events,
+                triggers,
+            })
+        }
+
+        /// Fire the first job's trigger.
+        fn fire(&self) -> bool {
+            self.triggers[0].fire()
+        }
+
+        fn fire_all(&self) {
+            for trigger in &self.triggers {
+                trigger.fire();
+            }
+        }
+
+        /// The next event that ends a run, skipping those that start one.
+        async fn finished(&mut self) -> JobEvent {
+            loop {
+                match self.events.next().await {
+                    Some(JobEvent::Started { .. }) => {}
+                    Some(event) => return event,
+                    None => panic!("Event stream ended"),
+                }
+            }
+        }
+
+        /// How the next `runs` runs to finish ended, in order of job name.
+        async fn outcomes(&mut self, runs: usize) -> Vec<String> {
+            let mut outcomes = Vec::new();
+
+            while outcomes.len() < runs {
+                match self.finished().await {
+                    JobEvent::Succeeded { job, .. } => outcomes.push(format!("{job} succeeded")),
+                    JobEvent::Failed { job, error, .. } => {
+                        outcomes.push(format!("{job} failed: {error}"))
+                    }
+                    JobEvent::Started { .. } => {}
+                }
+            }
+
+            outcomes.sort();
+            outcomes
+        }
+
+        /// The next `count` events, as "started" or "succeeded", failing on
+        /// any failure.
+        async fn progress(&mut self, count: usize) -> Vec<&'static str> {
+            let mut progress = Vec::new();
+
+            while progress.len() < count {
+                match self.events.next().await {
+                    Some(JobEvent::Started { .. }) => progress.push("started"),
+                    Some(JobEvent::Succeeded { .. }) => progress.push("succeeded"),
+                    event => panic!("{event:?}"),
+                }
+            }
+
+            progress
+        }
+    }
+
+    #[test]
+    fn test_log_limiter() {
+        let start = Instant::now();
+        let mut limiter = LogLimiter::new(Some(2));
+
+        assert!(limiter.allow(start));
+        assert!(limiter.allow(start));
+        assert!(!limiter.allow(start + Duration::from_millis(500)));
+        assert_eq!(limiter.dropped, 1);
+
+        assert!(limiter.allow(start + Duration::from_secs(1)));
+        assert_eq!(limiter.dropped, 0);
+
+        let mut unlimited = LogLimiter::new(None);
+
+        assert!((0..100).all(|_| unlimited.allow(start)));
+    }
+
+    #[tokio::test]
+    async fn test_overlap_skip() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(vec![job("slow")], |executor, _| {
+            executor.add_container("slow", Outcome::exit(0).after(Duration::from_millis(200)));
+        })?;
+
+        harness.fire();
+        assert_eq!(harness.progress(1).await, ["started"]);
+
+        // Falls due while the first run is still going, so is skipped.
+        harness.fire();
+        assert_eq!(harness.progress(1).await, ["succeeded"]);
+        assert_eq!(harness.executor.starts("slow"), 1);
+
+        harness.fire();
+        assert_eq!(harness.progress(1).await, ["started"]);
+        assert_eq!(harness.executor.starts("slow"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overlap_queue() -> Result<(), anyhow::Error> {
+        let mut harness =
+            Harness::start(vec![job("slow").overlap(Overlap::Queue)], |executor, _| {
+                executor.add_container("slow", Outcome::exit(0).after(Duration::from_millis(200)));
+            })?;
+
+        harness.fire();
+        assert_eq!(harness.progress(1).await, ["started"]);
+
+        // Only one run is held back, so the third firing is skipped.
+        harness.fire();
+        harness.fire();
+
+        assert_eq!(
+            harness.progress(3).await,
+            ["succeeded", "started", "succeeded"]
+        );
+        assert_eq!(harness.executor.starts("slow"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overlap_replace() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(
+            vec![job("slow").overlap(Overlap::Replace)],
+            |executor, _| {
+                executor.add_container("slow", Outcome::exit(0).after(Duration::from_secs(3600)));
+            },
+        )?;
+
+        harness.fire();
+        assert_eq!(harness.progress(1).await, ["started"]);
+
+        // The first run is stopped, and so fails, and a new one started.
+        harness.fire();
+        let JobEvent::Failed { error, .. } = harness.finished().await else {
+            panic!()
+        };
+        assert!(error.contains("143"));
+
+        assert_eq!(harness.progress(1).await, ["started"]);
+        assert_eq!(harness.executor.starts("slow"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("flaky").retry(2, Duration::from_millis(10))];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container("flaky", Outcome::exit(1));
+        })?;
+
+        harness.fire();
+        let JobEvent::Failed { .. } = harness.finished().await else {
+            panic!()
+        };
+
+        assert_eq!(harness.executor.starts("flaky"), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_on_failure() -> Result<(), anyhow::Error> {
+        let mut harness =
+            Harness::start(vec![job("backup").on_failure("alert")], |executor, _| {
+                executor.add_container("backup", Outcome::exit(1));
+                executor.add_container("alert", Outcome::exit(0));
+            })?;
+
+        harness.fire();
+        let JobEvent::Failed { .. } = harness.finished().await else {
+            panic!()
+        };
+
+        assert_eq!(harness.executor.starts("alert"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_pre_hook() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("backup").before("quiesce").after("unquiesce")];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container("backup", Outcome::exit(0));
+            executor.add_container("quiesce", Outcome::exit(1));
+            executor.add_container("unquiesce", Outcome::exit(0));
+        })?;
+
+        harness.fire();
+        assert_eq!(
+            harness.outcomes(1).await,
+            ["backup failed: Pre-run hook container quiesce failed"]
+        );
+
+        // The job is skipped, but what the pre-run hook did is still undone.
+        assert_eq!(harness.executor.starts("backup"), 0);
+        assert_eq!(harness.executor.starts("unquiesce"), 1);
+
+        Ok(())
+    }
+
+    /// Keeps state in memory, but can never get it onto disk.
+    #[derive(Default)]
+    struct UnflushableStore(MemoryStore);
+
+    impl StateStore for UnflushableStore {
+        fn load(&self, job: &str) -> Result<JobState, StateError> {
+            self.0.load(job)
+        }
+
+        fn save(&self, job: &str, state: &JobState) -> Result<(), StateError> {
+            self.0.save(job, state)
+        }
+
+        fn flush(&self) -> Result<(), StateError> {
+            Err(StateError::Backend("disk full".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_journal_failure() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(vec![job("backup")], |executor, scheduler| {
+            executor.add_container("backup", Outcome::exit(0));
+            scheduler.set_state_store(Box::new(UnflushableStore::default()));
+        })?;
+
+        // Without the record on disk, a crash would lose track of the run.
+        harness.fire();
+        assert_eq!(
+            harness.outcomes(1).await,
+            ["backup failed: Failed to journal run"]
+        );
+        assert_eq!(harness.executor.starts("backup"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_image() -> Result<(), anyhow::Error> {
+        let jobs = [("kept", false), ("removed", true)].map(|(name, auto_remove)| {
+            let run = RunImage::new("alpine", name).auto_remove(auto_remove);
+
+            job(name).action(Action::Run(run)).env("MODE", "full")
+        });
+        let mut harness = Harness::start(jobs.to_vec(), |executor, _| {
+            executor.add_image("alpine", Outcome::exit(0));
+        })?;
+
+        harness.fire_all();
+        assert_eq!(
+            harness.outcomes(2).await,
+            ["kept succeeded", "removed succeeded"]
+        );
+
+        let executor = &harness.executor;
+        assert_eq!(executor.starts("docker-cron-kept"), 1);
+        assert_eq!(executor.starts("docker-cron-removed"), 1);
+        assert!(executor.has_container("docker-cron-kept"));
+        assert!(!executor.has_container("docker-cron-removed"));
+        assert_eq!(
+            executor.env("docker-cron-kept"),
+            Some(BTreeMap::from([(
+                String::from("MODE"),
+                String::from("full")
+            )]))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull() -> Result<(), anyhow::Error> {
+        let jobs = [("latest", "alpine"), ("missing", "ghost")].map(|(name, image)| {
+            job(name).action(Action::Run(
+                RunImage::new(image, name).pull(PullPolicy::Always),
+            ))
+        });
+        let mut harness = Harness::start(jobs.to_vec(), |executor, _| {
+            executor.add_image("alpine", Outcome::exit(0));
+        })?;
+
+        harness.fire_all();
+        assert_eq!(
+            harness.outcomes(2).await,
+            ["latest succeeded", "missing failed: Failed to pull image"]
+        );
+        assert_eq!(harness.executor.pulls("alpine"), 1);
+        assert!(!harness.executor.has_container("docker-cron-missing"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compose() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("backup").action(Action::Compose(ComposeService {
+            project: String::from("shop"),
+            service: String::from("backup"),
+        }))];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container("shop-backup-1", Outcome::exit(0));
+            executor.add_service("shop", "backup", "shop-backup-1");
+        })?;
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["backup succeeded"]);
+
+        // Compose recreates the container under a new name.
+        let executor = harness.executor.clone();
+        executor.remove_container("shop-backup-1");
+        executor.add_container("shop-backup-2", Outcome::exit(0));
+        executor.add_service("shop", "backup", "shop-backup-2");
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["backup succeeded"]);
+
+        assert_eq!(executor.starts("shop-backup-1"), 1);
+        assert_eq!(executor.starts("shop-backup-2"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hosts() -> Result<(), anyhow::Error> {
+        let remote = Arc::new(MockExecutor::new());
+        remote.add_container("backup", Outcome::exit(0));
+
+        let jobs = [("remote", "prod-2"), ("missing", "prod-3")].map(|(name, host)| {
+            job(name)
+                .action(Action::Start(String::from("backup")))
+                .host(host)
+        });
+        let mut harness = Harness::start(jobs.to_vec(), |executor, scheduler| {
+            executor.add_container("backup", Outcome::exit(0));
+            scheduler.add_host("prod-2", Box::new(remote.clone()));
+        })?;
+
+        harness.fire_all();
+        assert_eq!(
+            harness.outcomes(2).await,
+            [
+                "missing failed: No Docker host named prod-3",
+                "remote succeeded"
+            ]
+        );
+        assert_eq!(harness.executor.starts("backup"), 0);
+        assert_eq!(remote.starts("backup"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(vec![job("backup")], |executor, scheduler| {
+            executor.add_container("backup", Outcome::exit(0));
+            executor.disconnect(1);
+            scheduler.set_reconnect(Some(Duration::from_secs(1)));
+        })?;
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["backup succeeded"]);
+        assert_eq!(harness.executor.starts("backup"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failure_output() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(vec![job("backup")], |executor, scheduler| {
+            executor.add_container(
+                "backup",
+                Outcome::exit(1).output(&["Connecting", "Disk full", "Giving up"]),
+            );
+            scheduler.set_failure_log_lines(2);
+        })?;
+
+        harness.fire();
+        let JobEvent::Failed { output, .. } = harness.finished().await else {
+            panic!()
+        };
+
+        assert_eq!(output, ["Disk full", "Giving up"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_labels() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("backup").action(Action::Run(RunImage::new("alpine", "backup")))];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_image("alpine", Outcome::exit(0));
+        })?;
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["backup succeeded"]);
+
+        let labels = harness.executor.labels("docker-cron-backup").unwrap();
+        assert_eq!(labels["docker-cron.job"], "backup");
+        assert_eq!(labels["docker-cron.run-id"].len(), 36);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exec() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("dump")
+            .action(Action::Exec(ExecCommand {
+                container: String::from("postgres"),
+                command: vec![String::from("pg_dump")],
+            }))
+            .timeout(Duration::from_millis(50))];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container(
+                "postgres",
+                Outcome::exit(0).after(Duration::from_secs(3600)),
+            );
+        })?;
+
+        harness.fire();
+        let JobEvent::Failed { .. } = harness.finished().await else {
+            panic!()
+        };
+
+        // A command that times out is abandoned, without stopping the
+        // container that it was running in.
+        let executor = &harness.executor;
+        assert_eq!(executor.execs("postgres"), [vec![String::from("pg_dump")]]);
+        assert_eq!(executor.starts("postgres"), 0);
+        assert_eq!(executor.stops("postgres"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_healthy_within() -> Result<(), anyhow::Error> {
+        let jobs = ["web", "api"].map(|name| job(name).healthy_within(Duration::from_millis(50)));
+        let mut harness = Harness::start(jobs.to_vec(), |executor, _| {
+            executor.add_container("web", Outcome::exit(0));
+            executor.add_container("api", Outcome::exit(0).after(Duration::from_secs(3600)));
+        })?;
+
+        harness.fire_all();
+        assert_eq!(
+            harness.outcomes(2).await,
+            [
+                "api failed: Container did not become healthy: still starting after 50ms",
+                "web succeeded",
+            ]
+        );
+
+        // A healthy container is left running.
+        assert_eq!(harness.executor.stops("web"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_control() -> Result<(), anyhow::Error> {
+        let jobs =
+            [("night", ControlOp::Stop), ("morning", ControlOp::Restart)].map(|(name, op)| {
+                job(name).action(Action::Control(ControlContainer {
+                    op,
+                    container: String::from("web"),
+                }))
+            });
+        let mut harness = Harness::start(jobs.to_vec(), |executor, _| {
+            executor.add_container("web", Outcome::exit(0));
+        })?;
+
+        for (trigger, name) in harness.triggers.clone().iter().zip(["night", "morning"]) {
+            trigger.fire();
+            assert_eq!(harness.outcomes(1).await, [format!("{name} succeeded")]);
+        }
+
+        // The container is never started or waited on.
+        assert_eq!(
+            harness.executor.controls("web"),
+            [ControlOp::Stop, ControlOp::Restart]
+        );
+        assert_eq!(harness.executor.starts("web"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune() -> Result<(), anyhow::Error> {
+        let prune = Prune {
+            all: true,
+            ..Prune::new(PruneTarget::Images)
+        };
+        let jobs = vec![job("prune-images").action(Action::Prune(prune.clone()))];
+        let mut harness = Harness::start(jobs, |_, _| {})?;
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["prune-images succeeded"]);
+        assert_eq!(harness.executor.prunes(), [prune]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_env() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("upload").env("BUCKET", "s3://backups")];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container("upload", Outcome::exit(0));
+        })?;
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["upload succeeded"]);
+
+        let executor = &harness.executor;
+        assert_eq!(executor.starts("upload"), 0);
+        assert_eq!(executor.starts("upload-docker-cron"), 1);
+        assert_eq!(
+            executor.env("upload-docker-cron"),
+            Some(BTreeMap::from([(
+                String::from("BUCKET"),
+                String::from("s3://backups")
+            )]))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("backup").command(vec![String::from("--full")])];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container("backup", Outcome::exit(0));
+        })?;
+
+        harness.fire();
+        assert_eq!(harness.outcomes(1).await, ["backup succeeded"]);
+
+        let executor = &harness.executor;
+        assert_eq!(executor.starts("backup"), 0);
+        assert_eq!(executor.starts("backup-docker-cron"), 1);
+        assert_eq!(
+            executor.command("backup-docker-cron"),
+            Some(vec![String::from("--full")])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeout() -> Result<(), anyhow::Error> {
+        let jobs = vec![job("wedged").timeout(Duration::from_millis(50))];
+        let mut harness = Harness::start(jobs, |executor, _| {
+            executor.add_container("wedged", Outcome::exit(0).after(Duration::from_secs(3600)));
+        })?;
+
+        harness.fire();
+        let JobEvent::Failed { error, .. } = harness.finished().await else {
+            panic!()
+        };
+
+        assert!(error.contains("timed out"));
+        assert_eq!(harness.executor.stops("wedged"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jitter() -> Result<(), anyhow::Error> {
+        // Only the job without its own jitter is delayed by the splay.
+        let jobs = vec![job("splayed"), job("prompt").jitter(Duration::ZERO)];
+        let mut harness = Harness::start(jobs, |executor, scheduler| {
+            executor.add_container("splayed", Outcome::exit(0));
+            executor.add_container("prompt", Outcome::exit(0));
+            scheduler.set_splay(Some(Duration::from_secs(3600)));
+        })?;
+
+        harness.fire_all();
+        assert_eq!(harness.outcomes(1).await, ["prompt succeeded"]);
+        assert_eq!(harness.executor.starts("splayed"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_on_start() -> Result<(), anyhow::Error> {
+        let check = job("check")
+            .schedule("@yearly")
+            .run_on_start(true)
+            .build()?;
+        let off = job("off")
+            .schedule("@yearly")
+            .run_on_start(true)
+            .disabled(true)
+            .build()?;
+        let mut harness = Harness::start(Vec::new(), |executor, scheduler| {
+            executor.add_container("check", Outcome::exit(0));
+            scheduler.add_job(check).add_job(off);
+        })?;
+
+        assert_eq!(harness.outcomes(1).await, ["check succeeded"]);
+
+        // Disabled jobs never run, even on startup.
+        assert_eq!(harness.executor.starts("off"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_runs() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(vec![job("backfill").max_runs(2)], |executor, _| {
+            executor.add_container("backfill", Outcome::exit(0));
+        })?;
+
+        for _ in 0..2 {
+            harness.fire();
+            assert_eq!(harness.outcomes(1).await, ["backfill succeeded"]);
+        }
+
+        // The job's task finishes, dropping its trigger.
+        sleep(Duration::from_millis(50)).await;
+        assert!(!harness.fire());
+        assert_eq!(harness.executor.starts("backfill"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_catch_up() -> Result<(), anyhow::Error> {
+        let state = MemoryStore::default();
+
+        // Last succeeded two days ago, so yesterday's run was missed.
+        state.update("daily", &mut |state| {
+            state.last_success = Some(Utc::now() - chrono::Duration::days(2));
+        })?;
+
+        // The weekly job has never run, so there's nothing to catch up on.
+        let jobs = [
+            job("daily").schedule("@daily").build()?,
+            job("weekly").schedule("@weekly").build()?,
+        ];
+        let mut harness = Harness::start(Vec::new(), |executor, scheduler| {
+            for job in jobs {
+                executor.add_container(job.name.clone(), Outcome::exit(0));
+                scheduler.add_job(job);
+            }
+
+            scheduler.set_state_store(Box::new(state));
+            scheduler.set_catch_up(true);
+        })?;
+
+        assert_eq!(harness.outcomes(1).await, ["daily succeeded"]);
+        assert_eq!(harness.executor.starts("weekly"), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent() -> Result<(), anyhow::Error> {
+        let mut harness =
+            Harness::start(vec![job("first"), job("second")], |executor, scheduler| {
+                for name in ["first", "second"] {
+                    executor
+                        .add_container(name, Outcome::exit(0).after(Duration::from_millis(100)));
+                }
+
+                scheduler.set_max_concurrent(Some(1));
+            })?;
+
+        harness.fire_all();
+
+        // The second run waits for the first to finish.
+        assert_eq!(
+            harness.progress(4).await,
+            ["started", "succeeded", "started", "succeeded"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_after() -> Result<(), anyhow::Error> {
+        let upload = job("upload").schedule("@after backup").build()?;
+        let mut harness = Harness::start(vec![job("backup")], |executor, scheduler| {
+            executor.add_container("backup", Outcome::exit(0));
+            executor.add_container("upload", Outcome::exit(0));
+            scheduler.add_job(upload);
+        })?;
+
+        harness.fire();
+
+        let JobEvent::Succeeded { job: first, .. } = harness.finished().await else {
+            panic!()
+        };
+        let JobEvent::Succeeded { job: second, .. } = harness.finished().await else {
+            panic!()
+        };
+
+        assert_eq!([first, second], ["backup", "upload"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reload() -> Result<(), anyhow::Error> {
+        let jobs = ["kept", "edited", "dropped"]
+            .map(|name| job(name).build())
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut harness = Harness::start(Vec::new(), |executor, scheduler| {
+            for job in jobs {
+                executor.add_container(job.name.clone(), Outcome::exit(0));
+                scheduler.add_job(job);
+            }
+        })?;
+
+        harness.executor.add_container("added", Outcome::exit(0));
+
+        let jobs = vec![
+            job("kept").build()?,
+            job("edited").schedule("@every 2h").build()?,
+            job("added").schedule("@every 100ms").build()?,
+        ];
+        let reload = harness.scheduler.reload(jobs.clone());
+
+        assert_eq!(reload.added, ["added"]);
+        assert_eq!(reload.changed, ["edited"]);
+        assert_eq!(reload.removed, ["dropped"]);
+        assert!(harness.scheduler.cancel_token("dropped").is_none());
+        assert!(harness.scheduler.reload(jobs).is_empty());
+
+        assert_eq!(harness.outcomes(1).await, ["added succeeded"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reload_low_memory() -> Result<(), anyhow::Error> {
+        let steady = job("steady")
+            .schedule("* * * * * *")
+            .after("unquiesce")
+            .build()?;
+        let mut harness = Harness::start(Vec::new(), |executor, scheduler| {
+            executor.add_container("steady", Outcome::exit(0).after(Duration::from_millis(300)));
+            executor.add_container("unquiesce", Outcome::exit(0));
+            scheduler.set_low_memory(true).add_job(steady.clone());
+        })?;
+
+        assert_eq!(harness.progress(1).await, ["started"]);
+
+        let added = job("added").schedule("0 0 0 1 1 *").build()?;
+        let reload = harness.scheduler.reload(vec![steady, added]);
+        assert_eq!(reload.added, ["added"]);
+
+        // The run in progress of the unchanged job is left to finish, hooks
+        // and all, rather than being abandoned for the next one.
+        assert_eq!(harness.outcomes(1).await, ["steady succeeded"]);
+        assert_eq!(harness.executor.starts("steady"), 1);
+        assert_eq!(harness.executor.starts("unquiesce"), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_panic_isolation() -> Result<(), anyhow::Error> {
+        let mut harness = Harness::start(vec![job("bad"), job("good")], |executor, scheduler| {
+            executor.add_container("bad", Outcome::exit(0));
+            executor.add_container("good", Outcome::exit(0));
+            scheduler.on_start(|job| {
+                if job.name == "bad" {
+                    panic!("hook failed");
+                }
+            });
+        })?;
+
+        harness.triggers[0].fire();
+
+        while harness.scheduler.task_panics() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // The panicking job is gone, but the other one still runs.
+
+        assert!(!harness.triggers[0].fire());
+        assert!(harness.triggers[1].fire());
+        assert_eq!(harness.outcomes(1).await, ["good succeeded"]);
+        assert_eq!(harness.scheduler.task_panics(), 1);
+
+        Ok(())
+    }
+}
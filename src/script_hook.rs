@@ -0,0 +1,62 @@
+//! Run a host executable for every job event, as a generic escape hatch for
+//! integrations that docker-cron doesn't support natively.
+//!
+//! The event is written to the executable's stdin as a single line of JSON,
+//! and the most important fields are also passed as environment variables.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+};
+
+use thiserror::Error;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::event::JobEvent;
+
+#[derive(Debug, Error)]
+pub enum ScriptHookError {
+    #[error("Failed to run hook executable {path}")]
+    Spawn {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Hook executable {path} exited with {status}")]
+    Exit { path: PathBuf, status: ExitStatus },
+}
+
+pub async fn run_script_hook(path: &Path, event: &JobEvent) -> Result<(), ScriptHookError> {
+    let spawn_error = |source| ScriptHookError::Spawn {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut payload = serde_json::to_vec(event).expect("JobEvent is always serializable");
+    payload.push(b'\n');
+
+    let mut child = Command::new(path)
+        .env("DOCKER_CRON_EVENT", event.kind())
+        .env("DOCKER_CRON_JOB", event.job())
+        .env("DOCKER_CRON_CONTAINER", event.container())
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't care about the payload may exit without reading
+        // it, so a broken pipe here is not an error.
+
+        let _ = stdin.write_all(&payload).await;
+    }
+
+    let status = child.wait().await.map_err(spawn_error)?;
+
+    if !status.success() {
+        return Err(ScriptHookError::Exit {
+            path: path.to_path_buf(),
+            status,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Deterministic assignment of jobs to one of several docker-cron instances.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+/// One shard out of a fixed number, written as e.g. `2/5`. Shards are
+/// numbered from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    index: u32,
+    count: u32,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid shard {0:?}, expected <index>/<count> with 1 <= index <= count")]
+pub struct InvalidShardError(String);
+
+impl Shard {
+    /// Whether a job with this name belongs to this shard. Every job belongs
+    /// to exactly one of the shards `1/n` to `n/n`.
+    pub fn owns(&self, job_name: &str) -> bool {
+        fnv1a(job_name.as_bytes()) % u64::from(self.count) == u64::from(self.index - 1)
+    }
+}
+
+/// FNV-1a, chosen over the std hasher because its output must never change
+/// between releases or all the jobs would move between instances.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+impl FromStr for Shard {
+    type Err = InvalidShardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidShardError(s.to_string());
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: u32 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+
+        Ok(Shard { index, count })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Shard::from_str("2/5").unwrap(),
+            Shard { index: 2, count: 5 }
+        );
+        assert!(Shard::from_str("0/5").is_err());
+        assert!(Shard::from_str("6/5").is_err());
+        assert!(Shard::from_str("5").is_err());
+    }
+
+    #[test]
+    fn test_owns() {
+        let shards: Vec<Shard> = (1..=3).map(|index| Shard { index, count: 3 }).collect();
+
+        for name in ["backup", "cleanup", "report", "vacuum", "rotate-logs"] {
+            let owners = shards.iter().filter(|shard| shard.owns(name)).count();
+            assert_eq!(owners, 1);
+        }
+
+        // Hashes are part of the stable interface, see fnv1a.
+
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"backup"), 0x8f75c61dfd19093f);
+    }
+}
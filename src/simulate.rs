@@ -0,0 +1,235 @@
+//! Offline simulation of a set of jobs over a range of time, for checking
+//! what a crontab would do without waiting for it or touching Docker.
+
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use thiserror::Error;
+
+use crate::{job::Job, trigger::TriggerSpec};
+
+/// A half-open range of time written as `<start>..<end>`, where each end is
+/// either a date (meaning midnight UTC) or an RFC 3339 timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid time range {0:?}, expected <start>..<end> with dates or RFC 3339 timestamps")]
+pub struct InvalidTimeRangeError(String);
+
+fn parse_instant(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+impl FromStr for TimeRange {
+    type Err = InvalidTimeRangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidTimeRangeError(s.to_string());
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let start = parse_instant(start.trim()).ok_or_else(invalid)?;
+        let end = parse_instant(end.trim()).ok_or_else(invalid)?;
+
+        if end <= start {
+            return Err(invalid());
+        }
+
+        Ok(TimeRange { start, end })
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start.to_rfc3339(), self.end.to_rfc3339())
+    }
+}
+
+/// Whether a job's runs can be predicted at all. Jobs triggered by files
+/// depend on the outside world, `@reboot` jobs on when docker-cron starts and
+/// `@after` jobs on whether other runs succeed, so they are left out of
+/// simulations.
+pub fn is_simulable(job: &Job) -> bool {
+    !matches!(
+        job.schedule,
+        TriggerSpec::File(_) | TriggerSpec::Reboot | TriggerSpec::After(_)
+    )
+}
+
+/// The next time that `job` would fire strictly after `after`, if any.
+fn next_fire(job: &Job, start: DateTime<Utc>, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match &job.schedule {
+        TriggerSpec::Cron(_) => job.next_cron_fire(after),
+        TriggerSpec::Interval(period) => {
+            // Intervals count from the start of the simulation, as they count
+            // from startup when running for real.
+            // Runs before the job's start date are skipped.
+            let after = match job.not_before {
+                Some(not_before) => after.max(not_before - chrono::Duration::milliseconds(1)),
+                None => after,
+            };
+            let period_ms = i64::try_from(period.as_millis()).ok()?.max(1);
+            let periods = (after - start).num_milliseconds() / period_ms;
+            let next = start + chrono::Duration::milliseconds(period_ms * (periods + 1));
+
+            Some(next).filter(|next| job.is_active_at(*next))
+        }
+        TriggerSpec::At(at) => Some(*at).filter(|at| *at > after && job.is_active_at(*at)),
+        TriggerSpec::File(_) | TriggerSpec::Reboot | TriggerSpec::After(_) => None,
+    }
+}
+
+/// Every run of `jobs` that would happen within `range`, in time order and
+/// computed lazily, so that even very long or dense ranges are cheap to
+/// iterate over. Disabled jobs never run.
+pub fn simulate(jobs: &[Job], range: TimeRange) -> Simulation<'_> {
+    let mut queue = BinaryHeap::new();
+
+    for (idx, job) in jobs.iter().enumerate().filter(|(_, job)| !job.disabled) {
+        // Cron schedules and one-off times are inclusive of the start of the
+        // range, unlike the strictly-after semantics of `next_fire`.
+        let first = match &job.schedule {
+            TriggerSpec::Cron(_) | TriggerSpec::At(_) => {
+                next_fire(job, range.start, range.start - chrono::Duration::seconds(1))
+            }
+            _ => next_fire(job, range.start, range.start),
+        };
+
+        if let Some(due) = first.filter(|due| *due < range.end) {
+            queue.push(Reverse((due, idx)));
+        }
+    }
+
+    Simulation { jobs, range, queue }
+}
+
+/// Up to the first `limit` runs of each job within `range`, grouped by job
+/// and in the same order as `jobs`.
+pub fn upcoming(jobs: &[Job], range: TimeRange, limit: usize) -> Vec<(&Job, Vec<DateTime<Utc>>)> {
+    jobs.iter()
+        .map(|job| {
+            let runs = simulate(std::slice::from_ref(job), range)
+                .map(|(due, _)| due)
+                .take(limit)
+                .collect();
+
+            (job, runs)
+        })
+        .collect()
+}
+
+pub struct Simulation<'a> {
+    jobs: &'a [Job],
+    range: TimeRange,
+    queue: BinaryHeap<Reverse<(DateTime<Utc>, usize)>>,
+}
+
+impl<'a> Iterator for Simulation<'a> {
+    type Item = (DateTime<Utc>, &'a Job);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((due, idx)) = self.queue.pop()?;
+        let job = &self.jobs[idx];
+
+        if let Some(next) = next_fire(job, self.range.start, due).filter(|t| *t < self.range.end) {
+            self.queue.push(Reverse((next, idx)));
+        }
+
+        Some((due, job))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::job::Action;
+
+    use super::*;
+
+    #[test]
+    fn test_time_range() -> Result<(), anyhow::Error> {
+        let range = TimeRange::from_str("2024-01-01..2024-02-01T12:00:00Z")?;
+        assert_eq!(range.start.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(range.end.to_rfc3339(), "2024-02-01T12:00:00+00:00");
+
+        assert!(TimeRange::from_str("2024-01-01").is_err());
+        assert!(TimeRange::from_str("2024-02-01..2024-01-01").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate() -> Result<(), anyhow::Error> {
+        let jobs = vec![
+            Job::new("daily")
+                .schedule("0 0 3 * * *")
+                .action(Action::Start(String::from("daily")))
+                .build()?,
+            Job::new("often")
+                .schedule("@every 10h")
+                .action(Action::Start(String::from("often")))
+                .build()?,
+            Job::new("file")
+                .schedule("@file /tmp/go")
+                .action(Action::Start(String::from("file")))
+                .build()?,
+        ];
+        let range = TimeRange::from_str("2024-01-01..2024-01-02T07:00:00Z")?;
+
+        let runs: Vec<_> = simulate(&jobs, range)
+            .map(|(due, job)| format!("{} {}", due.format("%d %H:%M"), job.name))
+            .collect();
+
+        assert_eq!(
+            runs,
+            [
+                "01 03:00 daily",
+                "01 10:00 often",
+                "01 20:00 often",
+                "02 03:00 daily",
+                "02 06:00 often"
+            ]
+            .map(String::from)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upcoming() -> Result<(), anyhow::Error> {
+        let jobs = vec![
+            Job::new("hourly")
+                .schedule("@hourly")
+                .action(Action::Start(String::from("hourly")))
+                .build()?,
+            Job::new("daily")
+                .schedule("0 0 3 * * *")
+                .action(Action::Start(String::from("daily")))
+                .build()?,
+        ];
+        let range = TimeRange::from_str("2024-01-01..2024-01-02")?;
+
+        let runs: Vec<_> = upcoming(&jobs, range, 3)
+            .into_iter()
+            .map(|(job, runs)| {
+                let times: Vec<_> = runs
+                    .iter()
+                    .map(|due| due.format("%H:%M").to_string())
+                    .collect();
+
+                format!("{} {}", job.name, times.join(" "))
+            })
+            .collect();
+
+        assert_eq!(runs, ["hourly 00:00 01:00 02:00", "daily 03:00"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Reaching a remote Docker daemon over SSH, as the Docker CLI does: each
+//! connection runs `docker system dial-stdio` on the remote host through the
+//! system's `ssh` client, and is relayed to it from a local Unix socket that
+//! the Docker client can connect to as usual.
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, DirBuilder},
+    io,
+    os::unix::fs::DirBuilderExt,
+    path::PathBuf,
+    process::Stdio,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{UnixListener, UnixStream},
+    process::Command,
+    time::sleep,
+};
+use tracing::{debug, warn};
+
+use crate::random::uuid_v4;
+
+/// Tunnels already open, by URL, so that reconnecting reuses them. Entries
+/// are removed when their tunnel closes.
+static TUNNELS: Mutex<Option<HashMap<String, PathBuf>>> = Mutex::new(None);
+
+/// Name of the socket in a tunnel's directory.
+const SOCKET_NAME: &str = "docker.sock";
+
+/// Bounds on the delay before accepting connections again after failing to,
+/// such as when out of file descriptors.
+const ACCEPT_RETRY_INITIAL: Duration = Duration::from_millis(100);
+const ACCEPT_RETRY_MAX: Duration = Duration::from_secs(5);
+
+/// A tunnel's socket directory, which is removed along with the tunnel's
+/// entry in [`TUNNELS`] once the task serving it ends, as it does when its
+/// runtime shuts down.
+struct TunnelGuard {
+    url: String,
+    dir: PathBuf,
+}
+
+impl Drop for TunnelGuard {
+    fn drop(&mut self) {
+        let mut tunnels = TUNNELS.lock().unwrap_or_else(PoisonError::into_inner);
+        let tunnels = tunnels.get_or_insert_with(HashMap::new);
+
+        if tunnels.get(&self.url) == Some(&self.dir.join(SOCKET_NAME)) {
+            tunnels.remove(&self.url);
+        }
+
+        if let Err(error) = fs::remove_dir_all(&self.dir) {
+            warn!(error = %error, path = %self.dir.display(), "Failed to remove SSH tunnel socket");
+        }
+    }
+}
+
+/// The path of a local socket that relays to the Docker daemon at an
+/// `ssh://[user@]host[:port]` URL, opening the tunnel if it isn't already.
+/// Must be called within a Tokio runtime, which relays connections for as
+/// long as it runs. The socket is removed when the runtime shuts down.
+pub fn tunnel(url: &str) -> Result<PathBuf, io::Error> {
+    let mut tunnels = TUNNELS.lock().unwrap();
+    let tunnels = tunnels.get_or_insert_with(HashMap::new);
+
+    if let Some(path) = tunnels.get(url) {
+        return Ok(path.clone());
+    }
+
+    // Anyone who can connect to the socket can use the remote daemon, so it
+    // goes in a new directory that only we can enter. Creating the directory
+    // fails if something is already there, rather than trusting it.
+    let dir = env::temp_dir().join(format!("docker-cron-ssh-{}", uuid_v4()));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let guard = TunnelGuard {
+        url: url.to_string(),
+        dir: dir.clone(),
+    };
+    let path = dir.join(SOCKET_NAME);
+    let listener = UnixListener::bind(&path)?;
+    let target = url.to_string();
+
+    tokio::spawn(async move {
+        let _guard = guard;
+        let mut delay = ACCEPT_RETRY_INITIAL;
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(relay(stream, target.clone()));
+                    delay = ACCEPT_RETRY_INITIAL;
+                }
+                Err(error) => {
+                    warn!(
+                        error = %error,
+                        delay_ms = delay.as_millis() as u64,
+                        "Failed to accept SSH tunnel connection, retrying"
+                    );
+                    sleep(delay).await;
+                    delay = (delay * 2).min(ACCEPT_RETRY_MAX);
+                }
+            }
+        }
+    });
+
+    debug!(url, path = %path.display(), "Opened SSH tunnel to Docker");
+    tunnels.insert(url.to_string(), path.clone());
+
+    Ok(path)
+}
+
+/// Relay one connection to the remote daemon until either side closes it.
+async fn relay(mut stream: UnixStream, url: String) {
+    // BatchMode stops ssh from prompting for a password that nobody will
+    // type, so keys or an agent must be set up instead.
+    let child = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "--", &url])
+        .args(["docker", "system", "dial-stdio"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            warn!(url, error = %error, "Failed to run ssh");
+            return;
+        }
+    };
+    let (Some(mut stdin), Some(mut stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        return;
+    };
+    let (mut reader, mut writer) = stream.split();
+
+    let upload = async {
+        let result = tokio::io::copy(&mut reader, &mut stdin).await;
+
+        // Closing ssh's input tells the remote end that the client is done.
+        let _ = stdin.shutdown().await;
+        result
+    };
+    let download = async {
+        let result = tokio::io::copy(&mut stdout, &mut writer).await;
+
+        let _ = writer.shutdown().await;
+        result
+    };
+
+    if let (Err(error), _) | (_, Err(error)) = tokio::join!(upload, download) {
+        debug!(url, error = %error, "SSH tunnel connection ended with an error");
+    }
+
+    let _ = child.wait().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn test_tunnel_per_runtime() -> Result<(), anyhow::Error> {
+        let url = format!("ssh://docker-cron-test-{}", std::process::id());
+
+        // Nothing runs ssh until a connection is made to the socket.
+        let runtime = Runtime::new()?;
+        let first = runtime.block_on(async { tunnel(&url) })?;
+
+        assert!(first.exists());
+        assert_eq!(runtime.block_on(async { tunnel(&url) })?, first);
+
+        // The socket dies with the runtime serving it, so it is cleaned up
+        // and a later runtime gets a new one.
+        drop(runtime);
+        assert!(!first.parent().unwrap().exists());
+
+        let runtime = Runtime::new()?;
+        let second = runtime.block_on(async { tunnel(&url) })?;
+
+        assert_ne!(second, first);
+        assert!(second.exists());
+
+        drop(runtime);
+        assert!(!second.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,553 @@
+//! Persistent per-job state, such as when a job last ran and whether an
+//! operator has paused it.
+//!
+//! The scheduler only talks to the [`StateStore`] trait, so deployments can
+//! keep state wherever suits them. A JSON file store is always available and
+//! an SQLite store is available with the `sqlite` cargo feature.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::executor::ResourceUsage;
+
+/// Everything docker-cron remembers about a job between runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobState {
+    /// When the job was last started, successfully or not.
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+    /// When the job last completed successfully.
+    #[serde(default)]
+    pub last_success: Option<DateTime<Utc>>,
+    /// How many runs have completed successfully.
+    #[serde(default)]
+    pub successes: u64,
+    /// Paused jobs are not run until they are resumed.
+    #[serde(default)]
+    pub paused: bool,
+    /// Write-ahead record of the run in progress, if any.
+    #[serde(default)]
+    pub in_flight: Option<InFlight>,
+    /// Resources used by the last run, if they were recorded.
+    #[serde(default)]
+    pub last_usage: Option<ResourceUsage>,
+}
+
+/// A run that docker-cron was about to start, or had started, but hadn't yet
+/// seen finish. Finding one on startup means that docker-cron stopped
+/// uncleanly during that run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InFlight {
+    /// When the run fell due.
+    pub due: DateTime<Utc>,
+    /// When Docker confirmed that the container had started. Unset if the
+    /// start request was never issued or never answered.
+    #[serde(default)]
+    pub started: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("Error accessing state file {path}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("State file {path} is corrupt")]
+    Corrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Unsupported state store {0:?}, expected file:<path> or sqlite:<path>")]
+    UnknownStore(String),
+    #[error("State store backend error")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Storage backend for [`JobState`], keyed by job name.
+pub trait StateStore: Send + Sync {
+    /// Load a job's state, returning the default state for unknown jobs.
+    fn load(&self, job: &str) -> Result<JobState, StateError>;
+
+    fn save(&self, job: &str, state: &JobState) -> Result<(), StateError>;
+
+    /// Load, modify and save a job's state.
+    fn update(&self, job: &str, f: &mut dyn FnMut(&mut JobState)) -> Result<(), StateError> {
+        let mut state = self.load(job)?;
+        f(&mut state);
+        self.save(job, &state)
+    }
+
+    /// Wait until everything saved so far is on disk, for stores that save
+    /// in the background. This may block, so call it off the async runtime.
+    fn flush(&self) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+/// Keeps state in memory only, so it is lost on restart. This is the default.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    jobs: Mutex<HashMap<String, JobState>>,
+}
+
+impl StateStore for MemoryStore {
+    fn load(&self, job: &str) -> Result<JobState, StateError> {
+        let jobs = self.jobs.lock().unwrap();
+
+        Ok(jobs.get(job).cloned().unwrap_or_default())
+    }
+
+    fn save(&self, job: &str, state: &JobState) -> Result<(), StateError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(job.to_string(), state.clone());
+
+        Ok(())
+    }
+}
+
+/// Keeps state for all jobs in a single JSON file, which is rewritten
+/// atomically on every change and synced to disk, so that it survives a crash
+/// or power cut intact. Within a Tokio runtime the file is written on the
+/// blocking thread pool, so that saving never holds up the scheduler, and
+/// failures to write it are logged rather than returned unless they are
+/// waited for with [`StateStore::flush`].
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    jobs: Mutex<HashMap<String, JobState>>,
+    /// The version of the state most recently saved.
+    version: AtomicU64,
+    /// The version of the state most recently written, which is locked while
+    /// writing so that a slow write can't replace a newer one.
+    written: Arc<Mutex<u64>>,
+}
+
+/// A version of the state waiting to be written to its file.
+struct Flush {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    version: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl Flush {
+    fn write(self) -> Result<(), StateError> {
+        let mut written = self.written.lock().unwrap();
+
+        if *written >= self.version {
+            return Ok(());
+        }
+
+        write_synced(&self.path, &self.bytes).map_err(|source| StateError::Io {
+            path: self.path.clone(),
+            source,
+        })?;
+        *written = self.version;
+
+        Ok(())
+    }
+}
+
+/// Replace the file at `path` with `bytes`, such that a crash at any point
+/// leaves either the old or the new contents on disk.
+fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // The rename is only durable once the directory has been synced too.
+    sync_parent(path)
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    File::open(dir)?.sync_all()
+}
+
+/// Windows can't open a directory as a file, and makes renames durable by
+/// itself.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+impl FileStore {
+    pub fn open(path: &Path) -> Result<Self, StateError> {
+        let jobs = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|source| StateError::Corrupt {
+                path: path.to_path_buf(),
+                source,
+            })?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(source) => {
+                return Err(StateError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+
+        Ok(FileStore {
+            path: path.to_path_buf(),
+            jobs: Mutex::new(jobs),
+            version: AtomicU64::new(0),
+            written: Arc::new(Mutex::new(0)),
+        })
+    }
+
+    /// The state in `jobs` as the given version, ready to be written.
+    fn snapshot(&self, jobs: &HashMap<String, JobState>, version: u64) -> Flush {
+        Flush {
+            path: self.path.clone(),
+            bytes: serde_json::to_vec_pretty(jobs).expect("JobState is always serializable"),
+            version,
+            written: self.written.clone(),
+        }
+    }
+
+    /// Write a new version of the state, in the background if within a
+    /// runtime.
+    fn write_back(&self, jobs: &HashMap<String, JobState>) -> Result<(), StateError> {
+        let flush = self.snapshot(jobs, self.version.fetch_add(1, Ordering::Relaxed) + 1);
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return flush.write();
+        };
+
+        runtime.spawn_blocking(|| {
+            if let Err(error) = flush.write() {
+                warn!(error = %error, "Failed to save job state");
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl StateStore for FileStore {
+    fn load(&self, job: &str) -> Result<JobState, StateError> {
+        let jobs = self.jobs.lock().unwrap();
+
+        Ok(jobs.get(job).cloned().unwrap_or_default())
+    }
+
+    fn save(&self, job: &str, state: &JobState) -> Result<(), StateError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(job.to_string(), state.clone());
+
+        self.write_back(&jobs)
+    }
+
+    fn flush(&self) -> Result<(), StateError> {
+        // Versions are only taken with the jobs locked, so this one includes
+        // every change saved so far. Writing it makes any older write still
+        // in the background a no-op.
+        let flush = {
+            let jobs = self.jobs.lock().unwrap();
+            self.snapshot(&jobs, self.version.load(Ordering::Relaxed))
+        };
+
+        flush.write()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::{path::Path, sync::Mutex};
+
+    use chrono::{DateTime, Utc};
+    use rusqlite::{params, Connection, OptionalExtension};
+
+    use std::time::Duration;
+
+    use super::{InFlight, JobState, ResourceUsage, StateError, StateStore};
+
+    /// Keeps state in an SQLite database, one row per job.
+    #[derive(Debug)]
+    pub struct SqliteStore {
+        conn: Mutex<Connection>,
+    }
+
+    fn backend_error(error: rusqlite::Error) -> StateError {
+        StateError::Backend(Box::new(error))
+    }
+
+    impl SqliteStore {
+        pub fn open(path: &Path) -> Result<Self, StateError> {
+            let conn = Connection::open(path).map_err(backend_error)?;
+
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS job_state (
+                    job TEXT PRIMARY KEY,
+                    last_run TEXT,
+                    last_success TEXT,
+                    paused INTEGER NOT NULL DEFAULT 0
+                )",
+            )
+            .map_err(backend_error)?;
+
+            migrate(&conn).map_err(backend_error)?;
+
+            Ok(SqliteStore {
+                conn: Mutex::new(conn),
+            })
+        }
+    }
+
+    /// Columns added since the table was first created, which databases
+    /// written by older versions won't have.
+    const ADDED_COLUMNS: &[(&str, &str)] = &[
+        ("in_flight_due", "TEXT"),
+        ("in_flight_started", "TEXT"),
+        ("last_peak_memory", "INTEGER"),
+        ("last_cpu_millis", "INTEGER"),
+        ("successes", "INTEGER NOT NULL DEFAULT 0"),
+    ];
+
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        let mut stmt = conn.prepare("SELECT name FROM pragma_table_info('job_state')")?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for (name, ty) in ADDED_COLUMNS {
+            if !columns.iter().any(|column| column == name) {
+                conn.execute_batch(&format!("ALTER TABLE job_state ADD COLUMN {name} {ty}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    impl StateStore for SqliteStore {
+        fn load(&self, job: &str) -> Result<JobState, StateError> {
+            let conn = self.conn.lock().unwrap();
+
+            let state = conn
+                .query_row(
+                    "SELECT last_run, last_success, paused, in_flight_due, in_flight_started,
+                        last_peak_memory, last_cpu_millis, successes
+                        FROM job_state WHERE job = ?1",
+                    params![job],
+                    |row| {
+                        let in_flight_due: Option<DateTime<Utc>> = row.get(3)?;
+                        let in_flight_started: Option<DateTime<Utc>> = row.get(4)?;
+                        let last_peak_memory: Option<i64> = row.get(5)?;
+                        let last_cpu_millis: Option<i64> = row.get(6)?;
+                        let successes: i64 = row.get(7)?;
+
+                        Ok(JobState {
+                            last_run: row.get(0)?,
+                            last_success: row.get(1)?,
+                            successes: successes as u64,
+                            paused: row.get(2)?,
+                            in_flight: in_flight_due.map(|due| InFlight {
+                                due,
+                                started: in_flight_started,
+                            }),
+                            last_usage: last_peak_memory.zip(last_cpu_millis).map(
+                                |(memory, cpu_millis)| ResourceUsage {
+                                    peak_memory_bytes: memory as u64,
+                                    cpu_time: Duration::from_millis(cpu_millis as u64),
+                                },
+                            ),
+                        })
+                    },
+                )
+                .optional()
+                .map_err(backend_error)?;
+
+            Ok(state.unwrap_or_default())
+        }
+
+        fn save(&self, job: &str, state: &JobState) -> Result<(), StateError> {
+            let conn = self.conn.lock().unwrap();
+
+            conn.execute(
+                "INSERT OR REPLACE INTO job_state (
+                    job, last_run, last_success, paused, in_flight_due, in_flight_started,
+                    last_peak_memory, last_cpu_millis, successes
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    job,
+                    state.last_run,
+                    state.last_success,
+                    state.paused,
+                    state.in_flight.map(|in_flight| in_flight.due),
+                    state.in_flight.and_then(|in_flight| in_flight.started),
+                    state.last_usage.map(|usage| usage.peak_memory_bytes as i64),
+                    state
+                        .last_usage
+                        .map(|usage| usage.cpu_time.as_millis() as i64),
+                    state.successes as i64,
+                ],
+            )
+            .map_err(backend_error)?;
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// Open a state store from a `file:<path>` or `sqlite:<path>` specification.
+pub fn open_store(spec: &str) -> Result<Box<dyn StateStore>, StateError> {
+    let unknown = || StateError::UnknownStore(spec.to_string());
+    let (scheme, path) = spec.split_once(':').ok_or_else(unknown)?;
+
+    match scheme {
+        "file" => Ok(Box::new(FileStore::open(Path::new(path))?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Box::new(SqliteStore::open(Path::new(path))?)),
+        _ => Err(unknown()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_store() -> Result<(), anyhow::Error> {
+        let store = MemoryStore::default();
+
+        assert_eq!(store.load("a")?, JobState::default());
+
+        store.update("a", &mut |state| state.paused = true)?;
+
+        assert!(store.load("a")?.paused);
+        assert!(!store.load("b")?.paused);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_store() -> Result<(), anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("docker-cron-{}.json", std::process::id()));
+        let now = Utc::now();
+
+        let store = FileStore::open(&path)?;
+        store.update("a", &mut |state| state.last_run = Some(now))?;
+        drop(store);
+
+        let store = FileStore::open(&path)?;
+        let state = store.load("a")?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(state.last_run, Some(now));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_store_in_runtime() -> Result<(), anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("docker-cron-{}-rt.json", std::process::id()));
+        let store = FileStore::open(&path)?;
+
+        for _ in 0..10 {
+            store.update("a", &mut |state| state.successes += 1)?;
+        }
+
+        // Writes happen in the background, and the last one wins.
+
+        let successes = || FileStore::open(&path).and_then(|store| store.load("a"));
+
+        while successes()?.successes < 10 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(successes()?.successes, 10);
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_store_flush() -> Result<(), anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("docker-cron-{}-fl.json", std::process::id()));
+        let store = FileStore::open(&path)?;
+        let due = Utc::now();
+
+        store.update("a", &mut |state| {
+            state.in_flight = Some(InFlight { due, started: None })
+        })?;
+        store.flush()?;
+
+        // The record is on disk as soon as flush returns.
+        let state = FileStore::open(&path)?.load("a")?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(state.in_flight, Some(InFlight { due, started: None }));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store_migration() -> Result<(), anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("docker-cron-{}.db", std::process::id()));
+
+        // The first version of the table, with a since-dropped queued column.
+        rusqlite::Connection::open(&path)?.execute_batch(
+            "CREATE TABLE job_state (
+                job TEXT PRIMARY KEY,
+                last_run TEXT,
+                last_success TEXT,
+                paused INTEGER NOT NULL DEFAULT 0,
+                queued INTEGER NOT NULL DEFAULT 0
+            )",
+        )?;
+
+        let store = SqliteStore::open(&path)?;
+        store.update("a", &mut |state| state.successes = 3)?;
+        let state = store.load("a");
+        drop(store);
+        fs::remove_file(&path)?;
+
+        assert_eq!(state?.successes, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_flight() -> Result<(), anyhow::Error> {
+        // State files written before the journal existed must still load.
+        let state: JobState = serde_json::from_str(r#"{"paused":true}"#)?;
+        assert_eq!(state.in_flight, None);
+
+        let due = Utc::now();
+        let state = JobState {
+            in_flight: Some(InFlight { due, started: None }),
+            ..JobState::default()
+        };
+        let json = serde_json::to_string(&state)?;
+        assert_eq!(serde_json::from_str::<JobState>(&json)?, state);
+
+        Ok(())
+    }
+}
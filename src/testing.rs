@@ -0,0 +1,531 @@
+//! An in-memory stand-in for Docker, for testing crontabs and integrations
+//! end to end without a Docker daemon.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), anyhow::Error> {
+//! use std::sync::Arc;
+//!
+//! use docker_cron::{scheduler::Scheduler, testing::{MockExecutor, Outcome}};
+//!
+//! let executor = Arc::new(MockExecutor::new());
+//! executor.add_container("backup", Outcome::exit(0));
+//!
+//! let scheduler = Scheduler::with_executor(Box::new(executor.clone()));
+//! // ... add jobs, start the scheduler and let it run ...
+//!
+//! assert_eq!(executor.starts("backup"), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::{sync::Notify, time::sleep};
+
+use crate::{
+    executor::Executor,
+    job::{ComposeService, ControlOp, Prune, PullPolicy, RunImage},
+    scheduler::RunError,
+};
+
+/// What happens when a fake container runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub exit_code: i64,
+    /// How long the container appears to run for.
+    pub duration: Duration,
+    /// What the container appears to write.
+    pub output: Vec<String>,
+}
+
+impl Outcome {
+    /// Exit immediately with the given status.
+    pub fn exit(exit_code: i64) -> Self {
+        Outcome {
+            exit_code,
+            duration: Duration::ZERO,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn after(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn output(mut self, lines: &[&str]) -> Self {
+        self.output = lines.iter().map(|line| line.to_string()).collect();
+        self
+    }
+}
+
+/// A record of one container start.
+#[derive(Clone, Debug)]
+pub struct MockRun {
+    pub container: String,
+    pub started: DateTime<Utc>,
+}
+
+/// An [`Executor`] that only pretends to run containers. Starting a container
+/// that hasn't been added fails the same way Docker does.
+#[derive(Debug, Default)]
+pub struct MockExecutor {
+    outcomes: Mutex<HashMap<String, Outcome>>,
+    images: Mutex<HashMap<String, Outcome>>,
+    runs: Mutex<Vec<MockRun>>,
+    stopped: Mutex<HashMap<String, Arc<Notify>>>,
+    stops: Mutex<Vec<String>>,
+    controls: Mutex<Vec<(String, ControlOp)>>,
+    prunes: Mutex<Vec<Prune>>,
+    envs: Mutex<HashMap<String, BTreeMap<String, String>>>,
+    commands: Mutex<HashMap<String, Vec<String>>>,
+    labels: Mutex<HashMap<String, BTreeMap<String, String>>>,
+    /// How many starts are still to fail as if Docker had gone away.
+    disconnects: Mutex<usize>,
+    execs: Mutex<Vec<(String, Vec<String>)>>,
+    pulls: Mutex<Vec<String>>,
+    services: Mutex<HashMap<(String, String), String>>,
+}
+
+impl MockExecutor {
+    pub fn new() -> Self {
+        MockExecutor::default()
+    }
+
+    /// Create a fake container, or change the outcome of an existing one.
+    pub fn add_container(&self, container: impl Into<String>, outcome: Outcome) {
+        self.outcomes
+            .lock()
+            .unwrap()
+            .insert(container.into(), outcome);
+    }
+
+    /// Fail the next `starts` container starts as if the connection to
+    /// Docker was lost, as when the daemon restarts.
+    pub fn disconnect(&self, starts: usize) {
+        *self.disconnects.lock().unwrap() = starts;
+    }
+
+    pub fn remove_container(&self, container: &str) {
+        self.outcomes.lock().unwrap().remove(container);
+    }
+
+    /// Label an added container as that of a Compose service, replacing any
+    /// container the service had before.
+    pub fn add_service(&self, project: &str, service: &str, container: impl Into<String>) {
+        self.services
+            .lock()
+            .unwrap()
+            .insert((project.to_string(), service.to_string()), container.into());
+    }
+
+    /// Create a fake image, whose containers run with the given outcome.
+    pub fn add_image(&self, image: impl Into<String>, outcome: Outcome) {
+        self.images.lock().unwrap().insert(image.into(), outcome);
+    }
+
+    /// Whether the named container exists, whether added or created.
+    pub fn has_container(&self, container: &str) -> bool {
+        self.outcome(container).is_some()
+    }
+
+    /// Every container start so far, in order.
+    pub fn runs(&self) -> Vec<MockRun> {
+        self.runs.lock().unwrap().clone()
+    }
+
+    /// How many times the named container has been started.
+    pub fn starts(&self, container: &str) -> usize {
+        self.runs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|run| run.container == container)
+            .count()
+    }
+
+    /// The commands run in the named container so far, in order.
+    pub fn execs(&self, container: &str) -> Vec<Vec<String>> {
+        self.execs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(exec, _)| exec == container)
+            .map(|(_, command)| command.clone())
+            .collect()
+    }
+
+    /// How many times the named image has been pulled.
+    pub fn pulls(&self, image: &str) -> usize {
+        self.pulls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|pulled| *pulled == image)
+            .count()
+    }
+
+    /// How many times the named container has been stopped.
+    pub fn stops(&self, container: &str) -> usize {
+        self.stops
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|stopped| *stopped == container)
+            .count()
+    }
+
+    /// The changes made to the named container's state by control jobs, in
+    /// order.
+    pub fn controls(&self, container: &str) -> Vec<ControlOp> {
+        self.controls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(controlled, _)| controlled == container)
+            .map(|&(_, op)| op)
+            .collect()
+    }
+
+    /// Every prune so far, in order.
+    pub fn prunes(&self) -> Vec<Prune> {
+        self.prunes.lock().unwrap().clone()
+    }
+
+    /// The environment that the named container was last copied or created
+    /// with.
+    pub fn env(&self, container: &str) -> Option<BTreeMap<String, String>> {
+        self.envs.lock().unwrap().get(container).cloned()
+    }
+
+    /// The labels that the named container was last copied or created with,
+    /// on top of any it already had.
+    pub fn labels(&self, container: &str) -> Option<BTreeMap<String, String>> {
+        self.labels.lock().unwrap().get(container).cloned()
+    }
+
+    /// The command that the named container was last copied with, if it
+    /// replaced the original's.
+    pub fn command(&self, container: &str) -> Option<Vec<String>> {
+        self.commands.lock().unwrap().get(container).cloned()
+    }
+
+    fn outcome(&self, container: &str) -> Option<Outcome> {
+        self.outcomes.lock().unwrap().get(container).cloned()
+    }
+
+    fn stopped(&self, container: &str) -> Arc<Notify> {
+        self.stopped
+            .lock()
+            .unwrap()
+            .entry(container.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+fn no_such_container(container: &str) -> bollard::errors::Error {
+    bollard::errors::Error::DockerResponseServerError {
+        status_code: 404,
+        message: format!("No such container: {container}"),
+    }
+}
+
+#[async_trait]
+impl Executor for MockExecutor {
+    async fn start(&self, container: &str) -> Result<(), RunError> {
+        if self.outcome(container).is_none() {
+            return Err(RunError::Start(no_such_container(container)));
+        }
+
+        {
+            let mut disconnects = self.disconnects.lock().unwrap();
+
+            if *disconnects > 0 {
+                *disconnects -= 1;
+
+                let error = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+                return Err(RunError::Start(error.into()));
+            }
+        }
+
+        self.runs.lock().unwrap().push(MockRun {
+            container: container.to_string(),
+            started: Utc::now(),
+        });
+
+        Ok(())
+    }
+
+    async fn wait(&self, container: &str) -> Result<(), RunError> {
+        let outcome = self
+            .outcome(container)
+            .ok_or_else(|| RunError::Wait(no_such_container(container)))?;
+
+        let stopped = self.stopped(container);
+
+        tokio::select! {
+            _ = sleep(outcome.duration) => {}
+            _ = stopped.notified() => return Err(RunError::Exit { status_code: 143 }),
+        }
+
+        match outcome.exit_code {
+            0 => Ok(()),
+            status_code => Err(RunError::Exit { status_code }),
+        }
+    }
+
+    /// A container becomes healthy after its outcome's duration if it exits
+    /// with status zero, and unhealthy otherwise.
+    async fn wait_healthy(&self, container: &str) -> Result<(), RunError> {
+        let outcome = self
+            .outcome(container)
+            .ok_or_else(|| RunError::Wait(no_such_container(container)))?;
+
+        sleep(outcome.duration).await;
+
+        match outcome.exit_code {
+            0 => Ok(()),
+            _ => Err(RunError::Unhealthy {
+                reason: String::from("its healthcheck failed"),
+            }),
+        }
+    }
+
+    /// Stops a run straight away, which then fails as if terminated by
+    /// SIGTERM.
+    async fn stop(&self, container: &str, _grace: Duration) -> Result<(), RunError> {
+        if self.outcome(container).is_none() {
+            return Err(RunError::Stop(no_such_container(container)));
+        }
+
+        self.stops.lock().unwrap().push(container.to_string());
+        self.stopped(container).notify_waiters();
+
+        Ok(())
+    }
+
+    async fn control(&self, container: &str, op: ControlOp) -> Result<(), RunError> {
+        if self.outcome(container).is_none() {
+            return Err(RunError::Control {
+                op,
+                source: no_such_container(container),
+            });
+        }
+
+        self.controls
+            .lock()
+            .unwrap()
+            .push((container.to_string(), op));
+
+        Ok(())
+    }
+
+    async fn prune(&self, prune: &Prune) -> Result<(), RunError> {
+        self.prunes.lock().unwrap().push(prune.clone());
+
+        Ok(())
+    }
+
+    /// The copy runs with the same outcome as the original.
+    async fn copy(
+        &self,
+        container: &str,
+        copy: &str,
+        env: &BTreeMap<String, String>,
+        command: &[String],
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        let outcome = self
+            .outcome(container)
+            .ok_or_else(|| RunError::Copy(no_such_container(container)))?;
+
+        self.add_container(copy, outcome);
+        self.envs
+            .lock()
+            .unwrap()
+            .insert(copy.to_string(), env.clone());
+        self.labels
+            .lock()
+            .unwrap()
+            .insert(copy.to_string(), labels.clone());
+        let mut commands = self.commands.lock().unwrap();
+        match command {
+            [] => commands.remove(copy),
+            command => commands.insert(copy.to_string(), command.to_vec()),
+        };
+
+        Ok(())
+    }
+
+    async fn find_service(&self, service: &ComposeService) -> Result<String, RunError> {
+        let key = (service.project.clone(), service.service.clone());
+
+        self.services
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| RunError::NoService {
+                project: service.project.clone(),
+                service: service.service.clone(),
+            })
+    }
+
+    /// Added images are already present, and there's no registry to pull
+    /// any others from.
+    async fn pull(&self, image: &str, policy: PullPolicy) -> Result<(), RunError> {
+        let present = self.images.lock().unwrap().contains_key(image);
+
+        match policy {
+            PullPolicy::Never => return Ok(()),
+            PullPolicy::Missing if present => return Ok(()),
+            PullPolicy::Always | PullPolicy::Missing => {}
+        }
+
+        if !present {
+            return Err(RunError::Pull(
+                bollard::errors::Error::DockerResponseServerError {
+                    status_code: 404,
+                    message: format!("pull access denied for {image}"),
+                },
+            ));
+        }
+
+        self.pulls.lock().unwrap().push(image.to_string());
+
+        Ok(())
+    }
+
+    async fn create(
+        &self,
+        container: &str,
+        run: &RunImage,
+        env: &BTreeMap<String, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        let outcome = self.images.lock().unwrap().get(&run.image).cloned();
+        let outcome = outcome.ok_or_else(|| {
+            RunError::Create(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404,
+                message: format!("No such image: {}", run.image),
+            })
+        })?;
+
+        self.add_container(container, outcome);
+        self.envs
+            .lock()
+            .unwrap()
+            .insert(container.to_string(), env.clone());
+        self.labels
+            .lock()
+            .unwrap()
+            .insert(container.to_string(), labels.clone());
+
+        Ok(())
+    }
+
+    async fn remove(&self, container: &str) -> Result<(), RunError> {
+        self.outcomes
+            .lock()
+            .unwrap()
+            .remove(container)
+            .map(|_| ())
+            .ok_or_else(|| RunError::Remove(no_such_container(container)))
+    }
+
+    /// The command exits with the container's outcome.
+    async fn exec(
+        &self,
+        container: &str,
+        command: &[String],
+        _env: &BTreeMap<String, String>,
+    ) -> Result<(), RunError> {
+        let outcome = self
+            .outcome(container)
+            .ok_or_else(|| RunError::Exec(no_such_container(container)))?;
+
+        self.execs
+            .lock()
+            .unwrap()
+            .push((container.to_string(), command.to_vec()));
+        sleep(outcome.duration).await;
+
+        match outcome.exit_code {
+            0 => Ok(()),
+            status_code => Err(RunError::Exit { status_code }),
+        }
+    }
+
+    async fn log_tail(&self, container: &str, lines: usize) -> Vec<String> {
+        let output = self
+            .outcome(container)
+            .map(|outcome| outcome.output)
+            .unwrap_or_default();
+
+        output[output.len().saturating_sub(lines)..].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use crate::{
+        event::JobEvent,
+        job::{Action, Job},
+        scheduler::Scheduler,
+        trigger::ManualTrigger,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_executor() -> Result<(), anyhow::Error> {
+        let executor = Arc::new(MockExecutor::new());
+        executor.add_container("ok", Outcome::exit(0));
+        executor.add_container("broken", Outcome::exit(3));
+
+        let mut scheduler = Scheduler::with_executor(Box::new(executor.clone()));
+        let mut events = Box::pin(scheduler.events());
+        let mut triggers = Vec::new();
+
+        for name in ["ok", "broken", "missing"] {
+            let job = Job::new(name)
+                .schedule("@every 1h")
+                .action(Action::Start(String::from(name)))
+                .build()?;
+            let (trigger, handle) = ManualTrigger::new();
+
+            scheduler.add_job_with_trigger(job, Box::new(trigger));
+            handle.fire();
+            triggers.push(handle);
+        }
+
+        let _handle = scheduler.start();
+        let mut outcomes = Vec::new();
+
+        while outcomes.len() < 3 {
+            match events.next().await {
+                Some(JobEvent::Succeeded { job, .. }) => outcomes.push(format!("{job} ok")),
+                Some(JobEvent::Failed { job, .. }) => outcomes.push(format!("{job} failed")),
+                Some(JobEvent::Started { .. }) => {}
+                None => break,
+            }
+        }
+
+        outcomes.sort();
+        assert_eq!(outcomes, ["broken failed", "missing failed", "ok ok"]);
+        assert_eq!(executor.starts("ok"), 1);
+        assert_eq!(executor.starts("broken"), 1);
+        assert_eq!(executor.starts("missing"), 0);
+
+        Ok(())
+    }
+}
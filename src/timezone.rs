@@ -0,0 +1,352 @@
+//! Time zones that schedules can be evaluated in.
+//!
+//! Named zones such as `Europe/London` are looked up in the system's zoneinfo
+//! database. Minimal images (scratch, distroless) have no such database, so
+//! the `tzdata` cargo feature embeds one into the binary instead.
+//!
+//! Around daylight saving time transitions, schedules follow Vixie cron. A
+//! time that is skipped when the clocks go forward runs as soon as they have
+//! done so, and a time that is repeated when they go back runs only the first
+//! time round, unless the schedule runs every hour, in which case it runs
+//! both times.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use chrono::{DateTime, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::crontab::CronSchedule;
+
+#[derive(Clone)]
+pub enum Timezone {
+    Utc,
+    /// A fixed offset from UTC, without daylight saving time.
+    Fixed(FixedOffset),
+    /// A zone from the embedded database.
+    #[cfg(feature = "tzdata")]
+    Bundled(chrono_tz::Tz),
+    /// A zone from the system's zoneinfo database.
+    System {
+        name: String,
+        tz: tzfile::ArcTz,
+    },
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown time zone {0:?}")]
+pub struct UnknownTimezoneError(String);
+
+/// The furthest that any zone's clocks are known to go back or forward at
+/// once, at Antarctica's Troll station.
+const MAX_TRANSITION: chrono::Duration = chrono::Duration::hours(2);
+
+fn offset_at<Z: TimeZone>(tz: &Z, at: DateTime<Utc>) -> chrono::Duration {
+    let seconds = tz
+        .offset_from_utc_datetime(&at.naive_utc())
+        .fix()
+        .local_minus_utc();
+
+    chrono::Duration::seconds(seconds.into())
+}
+
+/// When the wall clock reaches `local`, a time that the clocks skip.
+fn gap_end<Z: TimeZone>(tz: &Z, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    // No zone changes its clocks twice in a day, so within a day either side
+    // the wall clock only ever moves forward.
+    let mut lo = local.and_utc().timestamp() - 86400;
+    let mut hi = local.and_utc().timestamp() + 86400;
+
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        let at = DateTime::from_timestamp(mid, 0)?;
+
+        if (at + offset_at(tz, at)).naive_utc() > local {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    DateTime::from_timestamp(hi, 0)
+}
+
+/// Whether a schedule runs every hour, and so should run in both passes
+/// through an hour that is repeated when the clocks go back.
+fn is_hourly(schedule: &CronSchedule) -> bool {
+    let source = schedule.to_string();
+
+    match source.strip_prefix('@') {
+        Some(alias) => alias == "hourly",
+        None => source
+            .split_whitespace()
+            .nth(2)
+            .is_some_and(|hours| hours.starts_with('*')),
+    }
+}
+
+fn next_in<Z: TimeZone>(
+    tz: &Z,
+    schedule: &CronSchedule,
+    after: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    // If the clocks are about to go back, times from before `after` on the
+    // wall clock may yet come round again.
+    let fold = (offset_at(tz, after) - offset_at(tz, after + MAX_TRANSITION))
+        .max(chrono::Duration::zero());
+    let start = after.with_timezone(tz).naive_local() - fold;
+    let hourly = is_hourly(schedule);
+    let mut best: Option<(DateTime<Utc>, NaiveDateTime)> = None;
+
+    // The schedule is evaluated on wall clock times, which are dressed up as
+    // UTC for the cron crate's benefit, and then each is converted to when
+    // it actually happens.
+
+    for local in schedule.after(&start.and_utc()).map(|t| t.naive_utc()) {
+        if best.is_some_and(|(_, best_local)| local > best_local + fold) {
+            break;
+        }
+
+        let next = match tz.from_local_datetime(&local) {
+            LocalResult::Single(t) => Some(t.with_timezone(&Utc)),
+            LocalResult::Ambiguous(first, second) => {
+                let (first, second) = (first.with_timezone(&Utc), second.with_timezone(&Utc));
+
+                if first > after || !hourly {
+                    Some(first)
+                } else {
+                    Some(second)
+                }
+            }
+            LocalResult::None => gap_end(tz, local),
+        };
+
+        if let Some(next) = next.filter(|next| *next > after) {
+            if best.is_none_or(|(best, _)| next < best) {
+                best = Some((next, local));
+            }
+        }
+    }
+
+    best.map(|(next, _)| next)
+}
+
+impl Timezone {
+    /// The zone configured for this machine through the TZ environment
+    /// variable or /etc/localtime, falling back to UTC.
+    pub fn local() -> Self {
+        if let Ok(name) = std::env::var("TZ") {
+            // A leading colon means "implementation-defined", which for us is
+            // a zone name like any other.
+            if let Ok(tz) = Timezone::from_str(name.trim_start_matches(':')) {
+                return tz;
+            }
+        }
+
+        std::fs::read("/etc/localtime")
+            .ok()
+            .and_then(|bytes| tzfile::Tz::parse("localtime", &bytes).ok())
+            .map_or(Timezone::Utc, |tz| Timezone::System {
+                name: String::from("localtime"),
+                tz: tzfile::ArcTz::new(tz),
+            })
+    }
+
+    /// The next time after `after` at which `schedule` fires, with the
+    /// schedule's fields interpreted as wall clock time in this zone.
+    pub fn next_after(
+        &self,
+        schedule: &CronSchedule,
+        after: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        match self {
+            Timezone::Utc => next_in(&Utc, schedule, after),
+            Timezone::Fixed(offset) => next_in(offset, schedule, after),
+            #[cfg(feature = "tzdata")]
+            Timezone::Bundled(tz) => next_in(tz, schedule, after),
+            Timezone::System { tz, .. } => next_in(tz, schedule, after),
+        }
+    }
+}
+
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+impl FromStr for Timezone {
+    type Err = UnknownTimezoneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if matches!(s, "UTC" | "Z" | "Etc/UTC") {
+            return Ok(Timezone::Utc);
+        }
+
+        if let Some(offset) = parse_offset(s) {
+            return Ok(Timezone::Fixed(offset));
+        }
+
+        #[cfg(feature = "tzdata")]
+        if let Ok(tz) = s.parse::<chrono_tz::Tz>() {
+            return Ok(Timezone::Bundled(tz));
+        }
+
+        match tzfile::Tz::named(s) {
+            Ok(tz) => Ok(Timezone::System {
+                name: s.to_string(),
+                tz: tzfile::ArcTz::new(tz),
+            }),
+            Err(_) => Err(UnknownTimezoneError(s.to_string())),
+        }
+    }
+}
+
+impl Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timezone::Utc => write!(f, "UTC"),
+            Timezone::Fixed(offset) => write!(f, "{offset}"),
+            #[cfg(feature = "tzdata")]
+            Timezone::Bundled(tz) => write!(f, "{}", tz.name()),
+            Timezone::System { name, .. } => write!(f, "{name}"),
+        }
+    }
+}
+
+impl fmt::Debug for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Timezone({self})")
+    }
+}
+
+impl Serialize for Timezone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        Timezone::from_str(&name).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_offset() -> Result<(), anyhow::Error> {
+        let schedule = CronSchedule::from_str("0 0 3 * * *")?;
+        let after = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")?.with_timezone(&Utc);
+
+        let tz = Timezone::from_str("+05:30")?;
+        assert_eq!(tz.to_string(), "+05:30");
+        assert_eq!(
+            tz.next_after(&schedule, after).map(|t| t.to_rfc3339()),
+            Some(String::from("2024-01-01T21:30:00+00:00"))
+        );
+
+        let tz = Timezone::from_str("UTC")?;
+        assert_eq!(
+            tz.next_after(&schedule, after).map(|t| t.to_rfc3339()),
+            Some(String::from("2024-01-01T03:00:00+00:00"))
+        );
+
+        assert_eq!(parse_offset("-0800"), FixedOffset::west_opt(8 * 3600));
+        assert_eq!(parse_offset("+25:00"), None);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tzdata")]
+    #[test]
+    fn test_dst() -> Result<(), anyhow::Error> {
+        let tz = Timezone::from_str("Europe/London")?;
+        let next = |schedule: &str, after: &str| -> Result<String, anyhow::Error> {
+            let schedule = CronSchedule::from_str(schedule)?;
+            let after = DateTime::parse_from_rfc3339(after)?.with_timezone(&Utc);
+
+            Ok(tz.next_after(&schedule, after).unwrap().to_rfc3339())
+        };
+
+        // 01:30 is skipped on 31 March, so runs when the clocks reach 02:00.
+        assert_eq!(
+            next("0 30 1 * * *", "2024-03-30T12:00:00Z")?,
+            "2024-03-31T01:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 30 1 * * *", "2024-03-31T01:00:00Z")?,
+            "2024-04-01T00:30:00+00:00"
+        );
+
+        // 01:30 happens twice on 27 October, but only runs the first time.
+        assert_eq!(
+            next("0 30 1 * * *", "2024-10-26T12:00:00Z")?,
+            "2024-10-27T00:30:00+00:00"
+        );
+        assert_eq!(
+            next("0 30 1 * * *", "2024-10-27T00:30:00Z")?,
+            "2024-10-28T01:30:00+00:00"
+        );
+
+        // Unless the schedule is hourly.
+        assert_eq!(
+            next("0 30 * * * *", "2024-10-27T00:30:00Z")?,
+            "2024-10-27T01:30:00+00:00"
+        );
+        assert_eq!(
+            next("0 30 * * * *", "2024-10-27T01:30:00Z")?,
+            "2024-10-27T02:30:00+00:00"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_hourly() -> Result<(), anyhow::Error> {
+        assert!(is_hourly(&CronSchedule::from_str("@hourly")?));
+        assert!(is_hourly(&CronSchedule::from_str("0 */15 * * * *")?));
+        assert!(!is_hourly(&CronSchedule::from_str("0 0 1-3 * * *")?));
+        assert!(!is_hourly(&CronSchedule::from_str("@daily")?));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tzdata")]
+    #[test]
+    fn test_bundled() -> Result<(), anyhow::Error> {
+        let schedule = CronSchedule::from_str("0 0 3 * * *")?;
+        let after = DateTime::parse_from_rfc3339("2024-07-01T00:00:00Z")?.with_timezone(&Utc);
+
+        let tz = Timezone::from_str("Europe/London")?;
+        assert!(matches!(tz, Timezone::Bundled(_)));
+        assert_eq!(
+            tz.next_after(&schedule, after).map(|t| t.to_rfc3339()),
+            Some(String::from("2024-07-01T02:00:00+00:00"))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,513 @@
+//! Sources of "when to run" for jobs.
+//!
+//! Every job task loops on a [`Trigger`], running the job each time it fires.
+//! Cron expressions are the usual trigger, but jobs can also run at a fixed
+//! interval, when a file appears, once at startup, after other jobs succeed,
+//! or whenever an embedder asks them to.
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::{sleep, sleep_until, Instant},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    clock::{sleep_step, ClockWatch},
+    crontab::CronSchedule,
+    event::JobEvent,
+    timezone::Timezone,
+};
+
+/// How often a [`FileTrigger`] checks for its file.
+const FILE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[async_trait]
+pub trait Trigger: Send {
+    /// Wait until the job should next run and return the time that it was
+    /// due. Returns `None` once the trigger will never fire again.
+    async fn next(&mut self) -> Option<DateTime<Utc>>;
+}
+
+/// Fires according to a cron schedule, evaluated in UTC unless given another
+/// time zone.
+pub struct CronTrigger {
+    schedule: CronSchedule,
+    timezone: Timezone,
+}
+
+impl CronTrigger {
+    pub fn new(schedule: CronSchedule) -> Self {
+        CronTrigger {
+            schedule,
+            timezone: Timezone::Utc,
+        }
+    }
+
+    pub fn in_timezone(mut self, timezone: Timezone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+}
+
+#[async_trait]
+impl Trigger for CronTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        let now = Utc::now();
+        let mut next = self.timezone.next_after(&self.schedule, now)?;
+        let mut clock = ClockWatch::default();
+
+        debug!(
+            dt_millis = (next - now).num_milliseconds(),
+            "Sleeping until next launch"
+        );
+
+        // Sleep in steps in case the clock changes meanwhile. If it jumps
+        // forward past the next run then that run is simply late, but if it
+        // jumps back then the next run may now be sooner.
+
+        while Utc::now() < next {
+            sleep(sleep_step(next)).await;
+
+            if let Some(jump) = clock.jumped() {
+                warn!(
+                    jump_secs = jump.num_seconds(),
+                    "System clock jumped, rescheduling"
+                );
+
+                if jump < chrono::Duration::zero() {
+                    next = self.timezone.next_after(&self.schedule, Utc::now())?;
+                }
+            }
+        }
+
+        Some(next)
+    }
+}
+
+/// Fires at a fixed interval, starting one interval from now.
+pub struct IntervalTrigger {
+    period: Duration,
+    next: Instant,
+}
+
+impl IntervalTrigger {
+    pub fn new(period: Duration) -> Self {
+        IntervalTrigger {
+            period,
+            next: Instant::now() + period,
+        }
+    }
+}
+
+#[async_trait]
+impl Trigger for IntervalTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        sleep_until(self.next).await;
+        self.next += self.period;
+
+        Some(Utc::now())
+    }
+}
+
+/// Fires once, straight away, for `@reboot` jobs that run when docker-cron
+/// starts.
+#[derive(Debug, Default)]
+pub struct RebootTrigger {
+    fired: bool,
+}
+
+#[async_trait]
+impl Trigger for RebootTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        if self.fired {
+            return None;
+        }
+
+        self.fired = true;
+
+        Some(Utc::now())
+    }
+}
+
+/// Fires once at a fixed time, or never if that time had already passed
+/// when the trigger was created.
+pub struct OneShotTrigger {
+    at: DateTime<Utc>,
+    fired: bool,
+}
+
+impl OneShotTrigger {
+    pub fn new(at: DateTime<Utc>) -> Self {
+        OneShotTrigger {
+            at,
+            fired: at <= Utc::now(),
+        }
+    }
+}
+
+#[async_trait]
+impl Trigger for OneShotTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        if self.fired {
+            return None;
+        }
+
+        self.fired = true;
+
+        while Utc::now() < self.at {
+            sleep(sleep_step(self.at)).await;
+        }
+
+        Some(self.at)
+    }
+}
+
+/// Fires once each of a set of other jobs has succeeded since it last fired,
+/// for jobs that carry on from where others leave off. A dependency that
+/// fails after succeeding has to succeed again.
+pub struct DependencyTrigger {
+    jobs: Vec<String>,
+    succeeded: HashSet<String>,
+    events: broadcast::Receiver<JobEvent>,
+}
+
+impl DependencyTrigger {
+    /// Watch for the named jobs to succeed among a scheduler's `events`.
+    pub fn new(jobs: Vec<String>, events: broadcast::Receiver<JobEvent>) -> Self {
+        DependencyTrigger {
+            jobs,
+            succeeded: HashSet::new(),
+            events,
+        }
+    }
+}
+
+#[async_trait]
+impl Trigger for DependencyTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        loop {
+            match self.events.recv().await {
+                Ok(JobEvent::Succeeded { job, .. }) if self.jobs.contains(&job) => {
+                    self.succeeded.insert(job);
+
+                    if self.succeeded.len() == self.jobs.len() {
+                        self.succeeded.clear();
+
+                        return Some(Utc::now());
+                    }
+                }
+                Ok(JobEvent::Failed { job, .. }) => {
+                    self.succeeded.remove(&job);
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        missed,
+                        "Fell behind on job events, dependencies may be missed"
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Fires whenever a file appears. The file has to disappear again before the
+/// trigger can fire a second time, so jobs should usually delete it.
+pub struct FileTrigger {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl FileTrigger {
+    pub fn new(path: PathBuf) -> Self {
+        FileTrigger { path, armed: true }
+    }
+}
+
+#[async_trait]
+impl Trigger for FileTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        loop {
+            let exists = self.path.exists();
+
+            if exists && self.armed {
+                self.armed = false;
+
+                return Some(Utc::now());
+            }
+
+            if !exists {
+                self.armed = true;
+            }
+
+            sleep(FILE_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Fires whenever its [`ManualTriggerHandle`] is poked, e.g. from a webhook
+/// handler in an embedding program.
+pub struct ManualTrigger {
+    rx: mpsc::UnboundedReceiver<()>,
+}
+
+#[derive(Clone)]
+pub struct ManualTriggerHandle {
+    tx: mpsc::UnboundedSender<()>,
+}
+
+impl ManualTrigger {
+    pub fn new() -> (Self, ManualTriggerHandle) {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        (ManualTrigger { rx }, ManualTriggerHandle { tx })
+    }
+}
+
+impl ManualTriggerHandle {
+    /// Ask for the job to run. Returns false if the job is no longer running.
+    pub fn fire(&self) -> bool {
+        self.tx.send(()).is_ok()
+    }
+}
+
+#[async_trait]
+impl Trigger for ManualTrigger {
+    async fn next(&mut self) -> Option<DateTime<Utc>> {
+        self.rx.recv().await?;
+
+        Some(Utc::now())
+    }
+}
+
+/// Serializable description of a built-in trigger. The textual form is a
+/// cron expression or @alias, `@every <duration>`, `@file <path>`,
+/// `@reboot`, `@after <job>[,<job>...]` or an RFC 3339 timestamp.
+#[derive(Clone, Debug)]
+pub enum TriggerSpec {
+    Cron(Box<CronSchedule>),
+    Interval(Duration),
+    File(PathBuf),
+    Reboot,
+    /// After the named jobs have all succeeded.
+    After(Vec<String>),
+    /// Once, at the given time.
+    At(DateTime<Utc>),
+}
+
+#[derive(Debug, Error)]
+pub enum TriggerSpecError {
+    #[error("Invalid cron expression")]
+    Cron(#[from] cron::error::Error),
+    #[error("Invalid interval")]
+    Interval(#[from] humantime::DurationError),
+    #[error("Interval must not be zero")]
+    ZeroInterval,
+    #[error("No jobs to run after")]
+    NoDependencies,
+}
+
+impl TriggerSpec {
+    /// Create a fresh trigger that fires according to this spec. `@after`
+    /// triggers need to see other jobs' events, so only fire when built by
+    /// the scheduler running those jobs, and never fire if built here.
+    pub fn build(&self) -> Box<dyn Trigger> {
+        match self {
+            TriggerSpec::Cron(schedule) => Box::new(CronTrigger::new((**schedule).clone())),
+            TriggerSpec::Interval(period) => Box::new(IntervalTrigger::new(*period)),
+            TriggerSpec::File(path) => Box::new(FileTrigger::new(path.clone())),
+            TriggerSpec::Reboot => Box::new(RebootTrigger::default()),
+            TriggerSpec::After(jobs) => {
+                let (_, events) = broadcast::channel(1);
+
+                Box::new(DependencyTrigger::new(jobs.clone(), events))
+            }
+            TriggerSpec::At(at) => Box::new(OneShotTrigger::new(*at)),
+        }
+    }
+}
+
+impl FromStr for TriggerSpec {
+    type Err = TriggerSpecError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if let Some(period) = spec.strip_prefix("@every ") {
+            let period = humantime::parse_duration(period.trim())?;
+
+            if period.is_zero() {
+                return Err(TriggerSpecError::ZeroInterval);
+            }
+
+            return Ok(TriggerSpec::Interval(period));
+        }
+
+        if let Some(path) = spec.strip_prefix("@file ") {
+            return Ok(TriggerSpec::File(PathBuf::from(path.trim())));
+        }
+
+        if spec == "@reboot" {
+            return Ok(TriggerSpec::Reboot);
+        }
+
+        if let Some(jobs) = spec.strip_prefix("@after ") {
+            let jobs: Vec<String> = jobs
+                .split(',')
+                .map(str::trim)
+                .filter(|job| !job.is_empty())
+                .map(String::from)
+                .collect();
+
+            if jobs.is_empty() {
+                return Err(TriggerSpecError::NoDependencies);
+            }
+
+            return Ok(TriggerSpec::After(jobs));
+        }
+
+        if let Ok(at) = DateTime::parse_from_rfc3339(spec) {
+            return Ok(TriggerSpec::At(at.with_timezone(&Utc)));
+        }
+
+        Ok(TriggerSpec::Cron(Box::new(CronSchedule::from_str(spec)?)))
+    }
+}
+
+impl Display for TriggerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerSpec::Cron(schedule) => write!(f, "{schedule}"),
+            TriggerSpec::Interval(period) => {
+                write!(f, "@every {}", humantime::format_duration(*period))
+            }
+            TriggerSpec::File(path) => write!(f, "@file {}", path.display()),
+            TriggerSpec::Reboot => write!(f, "@reboot"),
+            TriggerSpec::After(jobs) => write!(f, "@after {}", jobs.join(",")),
+            TriggerSpec::At(at) => write!(f, "{}", at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        }
+    }
+}
+
+impl From<Schedule> for TriggerSpec {
+    fn from(schedule: Schedule) -> Self {
+        TriggerSpec::Cron(Box::new(schedule.into()))
+    }
+}
+
+impl Serialize for TriggerSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TriggerSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let spec = String::deserialize(deserializer)?;
+
+        TriggerSpec::from_str(&spec).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dependency_trigger() {
+        let (tx, rx) = broadcast::channel(16);
+        let mut trigger = DependencyTrigger::new(vec![String::from("a"), String::from("b")], rx);
+        let event = |job: &str, succeeded| {
+            let (job, container, time) = (job.to_string(), job.to_string(), Utc::now());
+
+            if succeeded {
+                JobEvent::Succeeded {
+                    job,
+                    container,
+                    time,
+                }
+            } else {
+                JobEvent::Failed {
+                    job,
+                    container,
+                    time,
+                    error: String::new(),
+                    output: Vec::new(),
+                }
+            }
+        };
+
+        // b fails after succeeding, so only counts once it succeeds again.
+        for (job, succeeded) in [("a", true), ("b", true), ("b", false), ("c", true)] {
+            tx.send(event(job, succeeded)).unwrap();
+        }
+        tx.send(event("b", true)).unwrap();
+
+        assert!(trigger.next().await.is_some());
+
+        drop(tx);
+        assert!(trigger.next().await.is_none());
+    }
+
+    #[test]
+    fn test_trigger_spec() -> Result<(), anyhow::Error> {
+        let TriggerSpec::Cron(schedule) = TriggerSpec::from_str("0 0 3 * * *")? else {
+            panic!()
+        };
+        assert_eq!(schedule.to_string(), "0 0 3 * * *");
+
+        let TriggerSpec::Interval(period) = TriggerSpec::from_str("@every 5m")? else {
+            panic!()
+        };
+        assert_eq!(period, Duration::from_secs(300));
+
+        let TriggerSpec::File(path) = TriggerSpec::from_str("@file /tmp/go")? else {
+            panic!()
+        };
+        assert_eq!(path, PathBuf::from("/tmp/go"));
+
+        let TriggerSpec::Reboot = TriggerSpec::from_str("@reboot")? else {
+            panic!()
+        };
+
+        let spec = TriggerSpec::from_str("2024-06-01T03:00:00+02:00")?;
+        let TriggerSpec::At(at) = spec else { panic!() };
+        assert_eq!(at.to_rfc3339(), "2024-06-01T01:00:00+00:00");
+        assert_eq!(spec.to_string(), "2024-06-01T01:00:00Z");
+
+        let TriggerSpec::After(jobs) = TriggerSpec::from_str("@after backup, vacuum")? else {
+            panic!()
+        };
+        assert_eq!(jobs, ["backup", "vacuum"]);
+
+        assert_eq!(
+            TriggerSpec::from_str("@every 1h 30m")?.to_string(),
+            "@every 1h 30m"
+        );
+
+        let Err(TriggerSpecError::ZeroInterval) = TriggerSpec::from_str("@every 0s") else {
+            panic!()
+        };
+
+        let Err(TriggerSpecError::NoDependencies) = TriggerSpec::from_str("@after ,") else {
+            panic!()
+        };
+
+        Ok(())
+    }
+}
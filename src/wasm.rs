@@ -0,0 +1,136 @@
+//! Sandboxed WASM plugins that see every job event and may veto runs.
+//!
+//! A plugin module must export `memory`, `alloc(len: i32) -> i32` and
+//! `on_event(ptr: i32, len: i32) -> i64`. docker-cron allocates a buffer with
+//! `alloc`, writes an event into it as UTF-8 JSON and calls `on_event`.
+//!
+//! Before each run the event is `{"event":"due",...}`; the other events are
+//! the serialized [`JobEvent`]s. `on_event` returns zero to allow the run
+//! unconditionally, or `(ptr << 32) | len` locating a JSON [`Verdict`] in
+//! linear memory, e.g. `{"veto":"change freeze","annotations":{"ticket":"42"}}`.
+//! Verdicts returned for anything other than a `due` event are ignored.
+
+use std::{path::Path, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use thiserror::Error;
+use tracing::warn;
+use wasmtime::{Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::{
+    event::JobEvent,
+    job::Job,
+    notify::{Notifier, NotifyError},
+    policy::{RunPolicy, Verdict},
+};
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("WASM plugin error")]
+    Wasm(#[from] wasmtime::Error),
+    #[error("WASM plugin does not export {0}")]
+    MissingExport(&'static str),
+    #[error("WASM plugin returned an invalid verdict")]
+    InvalidVerdict(#[source] serde_json::Error),
+}
+
+struct Plugin {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_event: TypedFunc<(i32, i32), i64>,
+}
+
+pub struct WasmPlugin {
+    name: String,
+    inner: Mutex<Plugin>,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path) -> Result<Self, PluginError> {
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, path)?;
+        let mut store = Store::new(&engine, ());
+        let instance: Instance = Linker::new(&engine).instantiate(&mut store, &module)?;
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or(PluginError::MissingExport("memory"))?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let on_event = instance.get_typed_func(&mut store, "on_event")?;
+
+        Ok(WasmPlugin {
+            name: path.display().to_string(),
+            inner: Mutex::new(Plugin {
+                store,
+                memory,
+                alloc,
+                on_event,
+            }),
+        })
+    }
+
+    fn call(&self, payload: &[u8]) -> Result<Verdict, PluginError> {
+        let mut guard = self.inner.lock().unwrap();
+        let plugin = &mut *guard;
+
+        let len: i32 = payload.len().try_into().expect("Event payload fits in i32");
+        let ptr = plugin.alloc.call(&mut plugin.store, len)?;
+        plugin
+            .memory
+            .write(&mut plugin.store, ptr as usize, payload)
+            .map_err(wasmtime::Error::from)?;
+
+        let ret = plugin.on_event.call(&mut plugin.store, (ptr, len))?;
+
+        if ret == 0 {
+            return Ok(Verdict::allow());
+        }
+
+        let ret_ptr = (ret >> 32) as u32 as usize;
+        let ret_len = ret as u32 as usize;
+        let mut buf = vec![0; ret_len];
+        plugin
+            .memory
+            .read(&plugin.store, ret_ptr, &mut buf)
+            .map_err(wasmtime::Error::from)?;
+
+        serde_json::from_slice(&buf).map_err(PluginError::InvalidVerdict)
+    }
+}
+
+#[async_trait]
+impl RunPolicy for WasmPlugin {
+    async fn check(&self, job: &Job, due: DateTime<Utc>) -> Verdict {
+        let payload = json!({
+            "event": "due",
+            "job": job.name,
+            "container": job.action.container(),
+            "time": due,
+        });
+
+        match self.call(payload.to_string().as_bytes()) {
+            Ok(verdict) => verdict,
+            Err(error) => {
+                // A broken plugin shouldn't take every job down with it.
+                warn!(plugin = self.name, error = %error, "WASM plugin failed, allowing run");
+                Verdict::allow()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WasmPlugin {
+    fn wants(&self, _event: &JobEvent) -> bool {
+        true
+    }
+
+    async fn notify(&self, event: &JobEvent) -> Result<(), NotifyError> {
+        let payload = serde_json::to_vec(event).expect("JobEvent is always serializable");
+
+        self.call(&payload).map(|_| ()).map_err(NotifyError::new)
+    }
+}
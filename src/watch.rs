@@ -0,0 +1,135 @@
+//! Watch crontabs for changes, so that they can be reloaded as soon as they
+//! are edited.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use fs_notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use thiserror::Error;
+use tokio::{sync::mpsc, time::timeout};
+use tracing::{debug, warn};
+
+#[derive(Debug, Error)]
+pub enum WatchError {
+    #[error("Failed to start watching files")]
+    Start(#[source] fs_notify::Error),
+    #[error("Failed to watch {}", path.display())]
+    Watch {
+        path: PathBuf,
+        source: fs_notify::Error,
+    },
+}
+
+/// Watches the directories that some files are in, reporting a change once
+/// they have been quiet for a while.
+///
+/// Directories are watched rather than the files themselves because editors
+/// and Kubernetes ConfigMap volumes replace files by renaming new ones over
+/// them, which a watch on the old file would miss. Any change in a watched
+/// directory counts, so a change is sometimes reported when the files
+/// themselves are unchanged.
+pub struct FileWatcher {
+    // Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    rx: mpsc::UnboundedReceiver<()>,
+    debounce: Duration,
+}
+
+impl FileWatcher {
+    /// Watch `paths`, which may be files or directories. A change is only
+    /// reported once there have been no further changes for `debounce`, so
+    /// that a burst of writes from one edit causes a single reload.
+    pub fn new(paths: &[impl AsRef<Path>], debounce: Duration) -> Result<Self, WatchError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handler = move |result: fs_notify::Result<Event>| match result {
+            Ok(event) if event.kind.is_access() => {}
+            Ok(event) => {
+                debug!(paths = ?event.paths, "Watched file changed");
+                let _ = tx.send(());
+            }
+            Err(error) => warn!(error = %error, "Error watching files"),
+        };
+        let mut watcher = fs_notify::recommended_watcher(handler).map_err(WatchError::Start)?;
+
+        for dir in watched_dirs(paths) {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(|source| WatchError::Watch { path: dir, source })?;
+        }
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            rx,
+            debounce,
+        })
+    }
+
+    /// Wait for the next change. Returns `None` if watching has stopped.
+    pub async fn changed(&mut self) -> Option<()> {
+        self.rx.recv().await?;
+
+        loop {
+            match timeout(self.debounce, self.rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return None,
+                Err(_) => return Some(()),
+            }
+        }
+    }
+}
+
+/// The directories to watch for changes to `paths`: a directory itself, or
+/// the one that a file is in.
+fn watched_dirs(paths: &[impl AsRef<Path>]) -> BTreeSet<PathBuf> {
+    paths
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+
+            match path.parent() {
+                _ if path.is_dir() => path.to_path_buf(),
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn test_watched_dirs() {
+        let dirs = watched_dirs(&["crontab", "/etc/cron/jobs", "/etc/cron/more"]);
+
+        assert_eq!(
+            dirs.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("/etc/cron"), PathBuf::from(".")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_changed() -> Result<(), anyhow::Error> {
+        let dir = env::temp_dir().join(format!("docker-cron-watch-{}", std::process::id()));
+        let path = dir.join("crontab");
+
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, "")?;
+
+        let mut watcher = FileWatcher::new(&[&path], Duration::from_millis(50))?;
+
+        fs::write(&path, "@hourly backup\n")?;
+        let changed = timeout(Duration::from_secs(5), watcher.changed()).await;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(changed?, Some(()));
+
+        Ok(())
+    }
+}